@@ -1,14 +1,19 @@
-use std;
-
 use std::collections::HashMap;
 use std::fmt::Display;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
 use ::opcodes::{AddressingMode, OpCode};
+use assembler::expression::{EvaluationError, Expression};
+use assembler::fragment::Fragment;
 use assembler::lexer::{Lexer, LexerError};
 use assembler::parser::{Parser, ParserError};
+use assembler::symbols::{scoped_names, SymbolTable};
 use assembler::token::{LexerToken, ParserToken};
 
+/// How many passes the assembler takes over a program to settle where its
+/// labels are
+const MAX_PASSES: u32 = 64;
+
 #[derive(Debug, PartialEq)]
 pub struct Label(u16);
 
@@ -18,10 +23,28 @@ pub struct AssemblerError {
 }
 
 impl AssemblerError {
-    fn unknown_label<S>(label: S) -> AssemblerError
-        where S: Into<String> + std::fmt::Display
-    {
-        AssemblerError::from(format!("Unknown label: '{}'", label))
+    fn unknown_label(label: &str, scope: &str) -> AssemblerError {
+        if scope.is_empty() {
+            AssemblerError::from(format!("Unknown label: {}", written_label(label)))
+        } else {
+            AssemblerError::from(format!("Unknown label: {} in {}", written_label(label), scope))
+        }
+    }
+
+    fn value_out_of_range(expression: &Expression, bytes: u8) -> AssemblerError {
+        AssemblerError::from(format!("Value of {} does not fit in {} byte(s)", expression, bytes))
+    }
+
+    fn division_by_zero(expression: &Expression) -> AssemblerError {
+        AssemblerError::from(format!("Division by zero in {}", expression))
+    }
+
+    fn duplicate_label(label: &str) -> AssemblerError {
+        AssemblerError::from(format!("Label {} is defined more than once", written_label(label)))
+    }
+
+    fn labels_do_not_settle() -> AssemblerError {
+        AssemblerError::from(format!("Label addresses still changed after {} passes", MAX_PASSES))
     }
 
     fn relative_offset_too_large<S>(context: S) -> AssemblerError
@@ -29,6 +52,27 @@ impl AssemblerError {
     {
         AssemblerError::from(format!("Branch too far: {}", context))
     }
+
+    /// Adds where in the source the fragment the error came from is
+    fn at(self, location: &str) -> AssemblerError {
+        if location.is_empty() {
+            return self;
+        }
+
+        AssemblerError::from(format!("{}. {}", self.message, location))
+    }
+}
+
+/// A label's name the way it was written in the source, e.g. `'@loop'
+/// (local to CLEAR)` for `CLEAR@loop`. Labels renamed for each expansion of
+/// a macro lose the number they were given.
+fn written_label(name: &str) -> String {
+    let name = name.split('#').next().unwrap();
+
+    match name.find('@') {
+        Some(at) if at > 0 => format!("'{}' (local to {})", &name[at..], &name[..at]),
+        _ => format!("'{}'", name),
+    }
 }
 
 impl From<String> for AssemblerError {
@@ -56,12 +100,47 @@ pub struct CodeSegment {
 }
 
 pub struct Assembler {
+    /// The labels of the source being assembled
     symbol_table: HashMap<String, Label>,
+    /// The labels of every source assembled so far, for `symbols`
+    assembled: HashMap<String, Label>,
+    /// Constants defined through `define`, for every source assembled
+    constants: HashMap<String, i32>,
+    /// Where `.INCLUDE` and `.INCBIN` look for files
+    include_paths: Vec<PathBuf>,
+    /// The scope of the fragment being assembled, which symbols are looked
+    /// up from
+    scope: String,
+    /// Where the fragment being assembled came from, for errors
+    location: String,
 }
 
 impl Assembler {
     pub fn new() -> Assembler {
-        Assembler { symbol_table: HashMap::new() }
+        Assembler {
+            symbol_table: HashMap::new(),
+            assembled: HashMap::new(),
+            constants: HashMap::new(),
+            include_paths: Vec::new(),
+            scope: String::new(),
+            location: String::new(),
+        }
+    }
+
+    /// Adds a directory for `.INCLUDE` and `.INCBIN` to search, after
+    /// looking next to the file doing the including
+    pub fn add_include_path<P>(&mut self, path: P)
+        where P: Into<PathBuf>
+    {
+        self.include_paths.push(path.into());
+    }
+
+    /// Defines a constant for the source to use, e.g. to pick what
+    /// `.IF` blocks assemble for a particular build
+    pub fn define<S>(&mut self, name: S, value: i32)
+        where S: Into<String>
+    {
+        self.constants.insert(name.into(), value);
     }
 
     pub fn assemble_string<S, O>(&mut self,
@@ -72,10 +151,9 @@ impl Assembler {
               O: Into<Option<u16>>
     {
         let code = code.into();
-        let mut lexer = Lexer::new();
+        let mut lexer = Lexer::tolerant();
         let tokens = lexer.lex_string(code)?;
-        let mut parser = Parser::new();
-        let tokens = parser.parse(tokens)?;
+        let tokens = self.parser().parse(tokens)?;
 
         Ok(self.assemble(tokens, offset)?)
     }
@@ -87,87 +165,143 @@ impl Assembler {
         where P: AsRef<Path>,
               O: Into<Option<u16>>
     {
-        let mut lexer = Lexer::new();
-        let tokens = lexer.lex_file(path)?;
-        let mut parser = Parser::new();
-        let tokens = parser.parse(tokens)?;
+        let tokens = self.parser().parse_file(path)?;
 
         Ok(self.assemble(tokens, offset)?)
     }
 
+    fn parser(&self) -> Parser {
+        let mut parser = Parser::new();
+        parser.set_locations(true);
+        for (name, &value) in &self.constants {
+            parser.define(name.clone(), value);
+        }
+        for path in &self.include_paths {
+            parser.add_include_path(path.clone());
+        }
+
+        parser
+    }
+
+    /// The labels from everything assembled successfully so far. Cheap
+    /// local labels are named after their global label, e.g. `CLEAR@loop`,
+    /// and anonymous labels are left out.
+    pub fn symbols(&self) -> SymbolTable {
+        let mut symbols = SymbolTable::new();
+        for (name, &Label(addr)) in &self.assembled {
+            if !name.starts_with(':') {
+                symbols.insert(name.clone(), addr);
+            }
+        }
+
+        symbols
+    }
+
     fn assemble<O>(&mut self,
                    tokens: Vec<ParserToken>,
                    offset: O)
                    -> Result<Vec<CodeSegment>, AssemblerError>
         where O: Into<Option<u16>>
     {
-        let mut addr: u16 = offset.into().unwrap_or(0);
+        let offset = offset.into().unwrap_or(0);
+        let fragments = Fragment::from_tokens(tokens);
 
-        // First, index the labels so we have addresses for them
-        self.index_labels(&tokens, addr);
+        // First, index the labels so we have addresses for them. Each
+        // source only sees its own labels.
+        self.symbol_table.clear();
+        self.index_labels(&fragments, offset).map_err(|error| error.at(&self.location))?;
 
         // Now assemble the code
+        let result = self.emit(&fragments, offset).map_err(|error| error.at(&self.location))?;
+        self.assembled.extend(self.symbol_table.drain());
+
+        Ok(result)
+    }
+
+    /// Assembles the code of laid out fragments
+    fn emit(&mut self, fragments: &[Fragment], offset: u16) -> Result<Vec<CodeSegment>, AssemblerError> {
+        let mut addr = offset;
+        self.scope.clear();
+        self.location.clear();
         let mut result = Vec::new();
-        let mut last_addressing_mode = AddressingMode::Absolute;
         let mut current_segment = CodeSegment {
             address: addr,
             code: Vec::new(),
         };
 
-        for token in tokens {
-            // Push an opcode into the output and increment our address
-            // offset
-            if let ParserToken::OpCode(opcode) = token {
-                current_segment.code.push(opcode.code);
-                addr += opcode.length as u16;
-                last_addressing_mode = opcode.mode;
-            } else if let ParserToken::OrgDirective(org_addr) = token {
-                if current_segment.code.len() > 0 {
-                    result.push(current_segment);
+        for fragment in fragments {
+            let size = fragment.size(addr, &|name| self.label_value(name, addr));
+
+            match *fragment {
+                Fragment::Label(_) => (),
+                Fragment::Scope(ref scope) => self.scope = scope.clone(),
+                Fragment::Location(ref location) => self.location = location.clone(),
+                Fragment::Org(org_addr) => {
+                    if current_segment.code.len() > 0 {
+                        result.push(current_segment);
+                    }
+                    current_segment = CodeSegment {
+                        address: org_addr,
+                        code: Vec::new(),
+                    };
+                    addr = org_addr;
                 }
-                current_segment = CodeSegment {
-                    address: org_addr,
-                    code: Vec::new(),
-                };
-                addr = org_addr;
-            } else if let ParserToken::RawByte(byte) = token {
-                // Push raw bytes directly into the output
-                current_segment.code.push(byte);
-            } else if let ParserToken::RawBytes(bytes) = token {
-                // Push raw bytes directly into output
-                for b in &bytes {
-                    current_segment.code.push(*b);
+                Fragment::Instruction(opcode, ref operand) => {
+                    current_segment.code.push(opcode.code);
+                    if let Some(ref expression) = *operand {
+                        self.emit_operand(opcode.mode, expression, addr, size, &mut current_segment.code)?;
+                    }
                 }
-            } else if let ParserToken::LabelArg(ref label) = token {
-                // Labels as arguments should be in the symbol table, look
-                // it up and calculate the address direction/location
-                if let Some(&Label(label_addr)) = self.symbol_table.get(label) {
-                    if last_addressing_mode == AddressingMode::Absolute {
-                        let low_byte = (label_addr & 0xFF) as u8;
-                        let high_byte = ((label_addr >> 8) & 0xFF) as u8;
-
-                        current_segment.code.push(low_byte);
-                        current_segment.code.push(high_byte);
+                Fragment::ZeroPageOrAbsolute { zero_page, absolute, ref operand, .. } => {
+                    let opcode = if size == zero_page.length as u16 {
+                        zero_page
                     } else {
-                        // Its relative.. lets generate a relative branch
-                        if addr > label_addr {
-                            let distance = (label_addr as i16 - addr as i16) as i8;
-                            if distance < -128 || distance > 127 {
-                                return Err(AssemblerError::relative_offset_too_large(format!("Attempted jump to {} at {:04X}", label, addr)));
-                            }
-                            current_segment.code.push(distance as u8);
-                        } else {
-                            let distance = label_addr - addr;
-                            if distance > 127 {
-                                return Err(AssemblerError::relative_offset_too_large(format!("Attempted jump to {} at {:04X}", label, addr)));
-                            }
-                            current_segment.code.push(distance as u8);
-                        }
+                        absolute
+                    };
+                    current_segment.code.push(opcode.code);
+                    self.emit_operand(opcode.mode, operand, addr, size, &mut current_segment.code)?;
+                }
+                Fragment::Bytes(ref expressions) => {
+                    for expression in expressions {
+                        current_segment.code.push(self.evaluate_byte(expression, addr)?);
+                    }
+                }
+                Fragment::Words(ref expressions) => {
+                    for expression in expressions {
+                        let value = self.evaluate_word(expression, addr)?;
+                        current_segment.code.push(value as u8);
+                        current_segment.code.push((value >> 8) as u8);
+                    }
+                }
+                Fragment::DoubleBytes(ref expressions) => {
+                    for expression in expressions {
+                        let value = self.evaluate_word(expression, addr)?;
+                        current_segment.code.push((value >> 8) as u8);
+                        current_segment.code.push(value as u8);
                     }
-                } else {
-                    return Err(AssemblerError::unknown_label(label.clone()));
+                }
+                Fragment::Fill(ref count, ref value) => {
+                    let count_value = self.evaluate(count, addr)?;
+                    if !(0..=0xFFFF).contains(&count_value) {
+                        return Err(AssemblerError::value_out_of_range(count, 2));
+                    }
+                    let value = self.evaluate_byte(value, addr)?;
+                    for _ in 0..count_value {
+                        current_segment.code.push(value);
+                    }
+                }
+                Fragment::Align(_, fill) => {
+                    for _ in 0..size {
+                        current_segment.code.push(fill);
+                    }
+                }
+                Fragment::Raw(ref bytes) => {
+                    // Push raw bytes directly into output
+                    current_segment.code.extend_from_slice(bytes);
                 }
             }
+
+            addr = addr.wrapping_add(size);
         }
 
         result.push(current_segment);
@@ -175,32 +309,152 @@ impl Assembler {
         Ok(result)
     }
 
-    /// Stores all labels in the code in a Symbol table for lookup later
-    fn index_labels(&mut self, tokens: &[ParserToken], offset: u16) {
-        let mut addr: u16 = offset;
-        let mut last_addressing_mode = AddressingMode::Absolute;
-
-        for token in tokens {
-            if let &ParserToken::Label(ref label) = token {
-                // Insert a label with the specified memory address
-                // as its offset
-                self.symbol_table.insert(label.clone(), Label(addr));
-            } else if let &ParserToken::OpCode(opcode) = token {
-                // Add the length of this opcode to our
-                // address offset
-                addr += opcode.length as u16;
-                last_addressing_mode = opcode.mode;
-            } else if let &ParserToken::OrgDirective(new_addr) = token {
-                addr = new_addr
+    /// Pushes the bytes of the operand of the `size` byte instruction at
+    /// `addr`. Branches are relative to the instruction after it.
+    fn emit_operand(&self,
+                    mode: AddressingMode,
+                    expression: &Expression,
+                    addr: u16,
+                    size: u16,
+                    code: &mut Vec<u8>)
+                    -> Result<(), AssemblerError> {
+        // Operands can refer to labels, which are in the symbol table by now
+        let value = self.evaluate(expression, addr)?;
+        let next = addr.wrapping_add(size);
+
+        match mode {
+            AddressingMode::Relative => {
+                let distance = value - next as i32;
+                if !(-128..=127).contains(&distance) {
+                    return Err(AssemblerError::relative_offset_too_large(format!("Attempted jump to {} at {:04X}", expression, next)));
+                }
+                code.push(distance as u8);
+            }
+            AddressingMode::Absolute |
+            AddressingMode::AbsoluteX |
+            AddressingMode::AbsoluteY |
+            AddressingMode::Indirect => {
+                if !(0..=0xFFFF).contains(&value) {
+                    return Err(AssemblerError::value_out_of_range(expression, 2));
+                }
+                code.push(value as u8);
+                code.push((value >> 8) as u8);
+            }
+            _ => {
+                if !(-0x80..=0xFF).contains(&value) {
+                    return Err(AssemblerError::value_out_of_range(expression, 1));
+                }
+                code.push(value as u8);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The value of a label, or of `*` for something at `addr`. Labels are
+    /// looked for in the current scope, then in each scope around it.
+    fn label_value(&self, name: &str, addr: u16) -> Option<i32> {
+        if name == "*" {
+            return Some(addr as i32);
+        }
+        scoped_names(&self.scope, name)
+            .iter()
+            .filter_map(|name| self.symbol_table.get(name))
+            .map(|&Label(addr)| addr as i32)
+            .next()
+    }
+
+    /// Works out the value of an expression at `addr` from the labels in
+    /// the symbol table
+    fn evaluate(&self, expression: &Expression, addr: u16) -> Result<i32, AssemblerError> {
+        expression.evaluate(&|name| self.label_value(name, addr)).map_err(|error| match error {
+            EvaluationError::UnknownSymbol(name) => AssemblerError::unknown_label(&name, &self.scope),
+            EvaluationError::DivisionByZero => AssemblerError::division_by_zero(expression),
+        })
+    }
+
+    /// Works out the value of a data byte
+    fn evaluate_byte(&self, expression: &Expression, addr: u16) -> Result<u8, AssemblerError> {
+        let value = self.evaluate(expression, addr)?;
+        if !(-0x80..=0xFF).contains(&value) {
+            return Err(AssemblerError::value_out_of_range(expression, 1));
+        }
+
+        Ok(value as u8)
+    }
+
+    /// Works out the value of a `.WORD` or `.DBYTE` expression
+    fn evaluate_word(&self, expression: &Expression, addr: u16) -> Result<i32, AssemblerError> {
+        let value = self.evaluate(expression, addr)?;
+        if !(-0x8000..=0xFFFF).contains(&value) {
+            return Err(AssemblerError::value_out_of_range(expression, 2));
+        }
+
+        Ok(value)
+    }
+
+    /// Works out the address of every label. Fragments can change size as
+    /// the labels they depend on move, so this takes passes over the
+    /// program until the labels stop moving.
+    fn index_labels(&mut self, fragments: &[Fragment], offset: u16) -> Result<(), AssemblerError> {
+        for _ in 0..MAX_PASSES {
+            let mut addr = offset;
+            let mut labels: Vec<(&String, u16)> = Vec::new();
+            self.scope.clear();
+            self.location.clear();
+
+            for fragment in fragments {
+                match *fragment {
+                    Fragment::Label(ref label) => {
+                        if labels.iter().any(|&(name, _)| name == label) {
+                            return Err(AssemblerError::duplicate_label(label));
+                        }
+                        labels.push((label, addr));
+                    }
+                    Fragment::Org(org_addr) => addr = org_addr,
+                    Fragment::Scope(ref scope) => self.scope = scope.clone(),
+                    Fragment::Location(ref location) => self.location = location.clone(),
+                    _ => addr = addr.wrapping_add(fragment.size(addr, &|name| self.label_value(name, addr))),
+                }
+            }
+
+            let mut moved = false;
+            for (label, addr) in labels {
+                if self.symbol_table.insert(label.clone(), Label(addr)) != Some(Label(addr)) {
+                    moved = true;
+                }
+            }
+            if !moved {
+                return Ok(());
             }
         }
+
+        // This is down to the program as a whole, not any one line
+        self.location.clear();
+        Err(AssemblerError::labels_do_not_settle())
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use std::fs;
+
     use super::*;
 
+    /// Writes source files to a new directory for a test to assemble
+    fn write_files(test: &str, files: &[(&str, &[u8])]) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("rs6502_{}_{}", test, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+
+        for &(name, contents) in files {
+            let path = dir.join(name);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, contents).unwrap();
+        }
+
+        dir
+    }
+
     #[test]
     fn can_assemble_basic_code() {
         let mut assembler = Assembler::new();
@@ -377,7 +631,7 @@ mod tests {
                              None)
             .unwrap();
 
-        assert_eq!(&[0xA2, 0x0F, 0xB1, 0x00, 0x00], &segments[0].code[..]);
+        assert_eq!(&[0xA2, 0x0F, 0xB1, 0x00], &segments[0].code[..]);
     }
 
     #[test]
@@ -476,7 +730,638 @@ mod tests {
         assert_eq!(0xC000, segments[0].address);
         assert_eq!(0x2000, segments[1].address);
 
-        assert_eq!(0x05, segments[0].code[0x01]);
+        // CALLBACK comes after the two data bytes
+        assert_eq!(0x07, segments[0].code[0x01]);
         assert_eq!(0x20, segments[0].code[0x02]);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn can_use_expressions_as_operands_and_data() {
+        let mut assembler = Assembler::new();
+        let segments = assembler.assemble_string("
+            START:
+                LDA TABLE+2,X
+                LDA #<MESSAGE
+                LDY #>MESSAGE
+                LDX #(2+3)*4-1
+                JMP END
+            END:
+            TABLE:
+                .BYTE <(END-START), >(MESSAGE+$100), 1 << 4 | 2
+                .BYTE -1, 10 % 4 * 3, 2+3*4
+
+            .ORG $1234
+            MESSAGE:
+                .BYTE 1
+        ",
+                             0xC000)
+            .unwrap();
+
+        assert_eq!(&[0xBD, 0x0E, 0xC0, 0xA9, 0x34, 0xA0, 0x12, 0xA2, 0x13, 0x4C, 0x0C, 0xC0,
+                     0x0C, 0x13, 0x12, 0xFF, 0x06, 0x0E],
+                   &segments[0].code[..]);
+    }
+
+    #[test]
+    fn errors_on_unknown_labels_in_expressions() {
+        let mut assembler = Assembler::new();
+        let result = assembler.assemble_string("
+            LDA MISSING+1
+        ",
+                                               None);
+
+        assert_eq!("Unknown label: 'MISSING'. Line 2", result.unwrap_err().message);
+    }
+
+    #[test]
+    fn can_use_constants_as_immediates_addresses_and_data() {
+        let mut assembler = Assembler::new();
+        let segments = assembler.assemble_string("
+            POINTER = $10
+            SCREEN .EQU $0400
+            COLOUR = 7
+            LIMIT = COLOUR * 2
+
+                LDA #COLOUR
+                STA POINTER
+                LDX POINTER,Y
+                STA SCREEN+1
+                CMP #LIMIT+1
+                JMP SCREEN
+                .BYTE COLOUR, <SCREEN, >SCREEN
+        ",
+                             None)
+            .unwrap();
+
+        assert_eq!(&[0xA9, 0x07, 0x85, 0x10, 0xB6, 0x10, 0x8D, 0x01, 0x04, 0xC9, 0x0F, 0x4C,
+                     0x00, 0x04, 0x07, 0x00, 0x04],
+                   &segments[0].code[..]);
+    }
+
+    #[test]
+    fn can_define_constants_from_labels() {
+        let mut assembler = Assembler::new();
+        let segments = assembler.assemble_string("
+            NEXT = TABLE+1
+                LDA NEXT
+            TABLE:
+                RTS
+        ",
+                             0xC000)
+            .unwrap();
+
+        assert_eq!(&[0xAD, 0x04, 0xC0, 0x60], &segments[0].code[..]);
+    }
+
+    #[test]
+    fn can_expand_macros_with_arguments() {
+        let mut assembler = Assembler::new();
+        let segments = assembler.assemble_string("
+            .MACRO ADD16 DEST, VALUE
+                CLC
+                LDA DEST
+                ADC #<VALUE
+                STA DEST
+                LDA DEST+1
+                ADC #>VALUE
+                STA DEST+1
+            .ENDMACRO
+
+            .MACRO INC16 DEST
+                ADD16 DEST, 1
+            .ENDMACRO
+
+                ADD16 $10, $0102
+                INC16 $20
+        ",
+                             None)
+            .unwrap();
+
+        assert_eq!(&[0x18, 0xA5, 0x10, 0x69, 0x02, 0x85, 0x10, 0xA5, 0x11, 0x69, 0x01, 0x85, 0x11,
+                     0x18, 0xA5, 0x20, 0x69, 0x01, 0x85, 0x20, 0xA5, 0x21, 0x69, 0x00, 0x85, 0x21],
+                   &segments[0].code[..]);
+    }
+
+    #[test]
+    fn macro_labels_are_unique_to_each_expansion() {
+        let mut assembler = Assembler::new();
+        let segments = assembler.assemble_string("
+            .MACRO WAIT COUNT
+                LDX #COUNT
+            LOOP
+                DEX
+                BNE LOOP
+            .ENDMACRO
+
+                WAIT 5
+                WAIT 10
+        ",
+                             None)
+            .unwrap();
+
+        assert_eq!(&[0xA2, 0x05, 0xCA, 0xD0, 0xFD, 0xA2, 0x0A, 0xCA, 0xD0, 0xFD],
+                   &segments[0].code[..]);
+    }
+
+    #[test]
+    fn cheap_local_labels_in_macros_are_unique_to_each_expansion() {
+        let mut assembler = Assembler::new();
+        let segments = assembler.assemble_string("
+            .MACRO WAIT COUNT
+                LDX #COUNT
+            .loop DEX
+                BNE .loop
+            .ENDMACRO
+
+            MAIN:
+                WAIT 5
+                WAIT 10
+        ",
+                             None)
+            .unwrap();
+
+        assert_eq!(&[0xA2, 0x05, 0xCA, 0xD0, 0xFD, 0xA2, 0x0A, 0xCA, 0xD0, 0xFD],
+                   &segments[0].code[..]);
+    }
+
+    #[test]
+    fn errors_in_macros_point_at_the_call_site() {
+        let mut assembler = Assembler::new();
+        let result = assembler.assemble_string("
+            .MACRO BAD
+                LDA ($10),X
+            .ENDMACRO
+
+                BAD
+        ",
+                                               None);
+
+        assert_eq!("Unexpected token. Line 3, in macro 'BAD' called from line 6",
+                   result.unwrap_err().message);
+    }
+
+    #[test]
+    fn assembly_errors_in_macros_point_at_the_call_site() {
+        let mut assembler = Assembler::new();
+        let unknown = assembler.assemble_string("
+            .MACRO M
+                LDA NOPE
+            .ENDMACRO
+
+                M
+        ",
+                                                None);
+        let branch = assembler.assemble_string("
+            .MACRO WAIT_FOR TARGET
+                BNE TARGET
+            .ENDMACRO
+
+                WAIT_FOR FAR
+                .RES 200
+            FAR:
+        ",
+                                               0xC000);
+
+        assert_eq!("Unknown label: 'NOPE'. Line 3, in macro 'M' called from line 6",
+                   unknown.unwrap_err().message);
+        assert_eq!("Branch too far: Attempted jump to FAR at C002. Line 3, in macro 'WAIT_FOR' called from line 6",
+                   branch.unwrap_err().message);
+    }
+
+    #[test]
+    fn errors_on_macros_that_call_themselves() {
+        let mut assembler = Assembler::new();
+        let result = assembler.assemble_string("
+            .MACRO FOREVER
+                FOREVER
+            .ENDMACRO
+
+                FOREVER
+        ",
+                                               None);
+
+        assert_eq!("Macro 'FOREVER' is nested more than 32 deep. Line 3, in macro 'FOREVER' called from line 3, \
+                    in macro 'FOREVER' called from line 6",
+                   result.unwrap_err().message);
+    }
+
+    #[test]
+    fn conditionals_pick_code_for_the_defined_target() {
+        let source = "
+            C64 = 1
+            VIC20 = 2
+
+            .IF TARGET = C64
+                LDA #$01
+            .ELSEIF TARGET = VIC20
+                LDA #$02
+            .ELSE
+                LDA #$03
+            .ENDIF
+
+            .IFDEF DEBUG
+                BRK
+            .ENDIF
+        ";
+
+        let mut assembler = Assembler::new();
+        assembler.define("TARGET", 2);
+        let segments = assembler.assemble_string(source, None).unwrap();
+        assert_eq!(&[0xA9, 0x02], &segments[0].code[..]);
+
+        let mut assembler = Assembler::new();
+        assembler.define("TARGET", 3);
+        assembler.define("DEBUG", 1);
+        let segments = assembler.assemble_string(source, None).unwrap();
+        assert_eq!(&[0xA9, 0x03, 0x00], &segments[0].code[..]);
+    }
+
+    #[test]
+    fn skipped_conditionals_define_nothing_and_raise_no_errors() {
+        let mut assembler = Assembler::new();
+        let segments = assembler.assemble_string("
+            .IFNDEF DEBUG
+                LDA #$01
+            .ELSE
+            TRACE:
+                NOTANOPCODE $12
+                UNDEFINED_MACRO
+                .MACRO SKIPPED
+                .ENDMACRO
+                .IF UNKNOWN_CONSTANT
+                    LDA ($10),X
+                .ENDIF
+                LDA [
+            .ENDIF
+        ",
+                                                 None)
+            .unwrap();
+
+        assert_eq!(&[0xA9, 0x01], &segments[0].code[..]);
+        assert_eq!(None, assembler.symbols().address_of("TRACE"));
+    }
+
+    #[test]
+    fn labels_do_not_carry_over_between_sources() {
+        let mut assembler = Assembler::new();
+        assembler.assemble_string("FOO: NOP\nPTR: NOP", 0xC000).unwrap();
+        let unknown = assembler.assemble_string("JMP FOO", None);
+        let failed = assembler.assemble_string("BAR: NOP\nJMP MISSING", None);
+        let after_failure = assembler.assemble_string("JMP BAR", None);
+        let segments = assembler.assemble_string(".ORG $80\nPTR: NOP\n.ORG $C000\nLDA PTR", None)
+            .unwrap();
+
+        assert_eq!("Unknown label: 'FOO'. Line 1", unknown.unwrap_err().message);
+        assert!(failed.is_err());
+        assert_eq!("Unknown label: 'BAR'. Line 1", after_failure.unwrap_err().message);
+        assert_eq!(None, assembler.symbols().address_of("BAR"));
+        assert_eq!(&[0xA5, 0x80], &segments[1].code[..]);
+        assert_eq!(Some(0xC000), assembler.symbols().address_of("FOO"));
+        assert_eq!(Some(0x0080), assembler.symbols().address_of("PTR"));
+    }
+
+    #[test]
+    fn errors_on_lines_that_cant_be_lexed_when_they_are_assembled() {
+        let mut assembler = Assembler::new();
+        let skipped = assembler.assemble_string("T = 1\n.IF T = 2\nLDA [\n.ENDIF", None);
+        let assembled = assembler.assemble_string("T = 1\n.IF T = 1\nLDA [\n.ENDIF", None);
+
+        assert!(skipped.is_ok());
+        assert_eq!("Unexpected token. Line 3 col 5", assembled.unwrap_err().message);
+    }
+
+    #[test]
+    fn errors_on_unbalanced_conditionals() {
+        let mut assembler = Assembler::new();
+        let unterminated = assembler.assemble_string("\n.IF 1\nNOP", None);
+        let stray = assembler.assemble_string("NOP\n.ENDIF", None);
+        let unknown = assembler.assemble_string(".IF START\n.ENDIF", None);
+
+        assert_eq!(".IF has no .ENDIF. Line 2", unterminated.unwrap_err().message);
+        assert_eq!(".ENDIF without .IF. Line 2", stray.unwrap_err().message);
+        assert_eq!("Condition uses 'START', which is not a constant. Line 1",
+                   unknown.unwrap_err().message);
+    }
+
+    #[test]
+    fn includes_files_next_to_the_includer_then_from_include_paths() {
+        let dir = write_files("includes",
+                              &[("main.asm", b".INCLUDE \"lib/macros.asm\"\n    CLEAR SCREEN\n"),
+                                ("lib/macros.asm", b".INCLUDE \"screen.asm\"\n.INCLUDE \"shared.asm\"\n"),
+                                ("lib/screen.asm", b"SCREEN = $0400\n"),
+                                ("shared/shared.asm",
+                                 b".MACRO CLEAR ADDR\n    LDA #$20\n    STA ADDR\n.ENDMACRO\n")]);
+
+        let mut assembler = Assembler::new();
+        assembler.add_include_path(dir.join("shared"));
+        let segments = assembler.assemble_file(dir.join("main.asm"), None).unwrap();
+
+        assert_eq!(&[0xA9, 0x20, 0x8D, 0x00, 0x04], &segments[0].code[..]);
+    }
+
+    #[test]
+    fn errors_in_included_files_give_the_file_and_line() {
+        let dir = write_files("include_errors",
+                              &[("main.asm", b"    NOP\n.INCLUDE \"bad.asm\"\n"),
+                                ("bad.asm", b"    NOP\n    LDA ($10),X\n")]);
+
+        let mut assembler = Assembler::new();
+        let result = assembler.assemble_file(dir.join("main.asm"), None);
+
+        assert_eq!(format!("Unexpected token. Line 2, in {}, included from line 2, in {}",
+                           dir.join("bad.asm").display(),
+                           dir.join("main.asm").display()),
+                   result.unwrap_err().message);
+    }
+
+    #[test]
+    fn assembly_errors_in_included_files_give_the_file_and_line() {
+        let dir = write_files("include_assembly_errors",
+                              &[("main.asm", b"    NOP\n.INCLUDE \"bad.asm\"\n    LDA #FAR\nFAR:\n"),
+                                ("bad.asm", b"    NOP\n    JMP MISSING\n")]);
+
+        let mut assembler = Assembler::new();
+        let result = assembler.assemble_file(dir.join("main.asm"), None);
+        fs::write(dir.join("bad.asm"), b"    NOP\n").unwrap();
+        let range = assembler.assemble_file(dir.join("main.asm"), 0xC000);
+
+        assert_eq!(format!("Unknown label: 'MISSING'. Line 2, in {}, included from line 2, in {}",
+                           dir.join("bad.asm").display(),
+                           dir.join("main.asm").display()),
+                   result.unwrap_err().message);
+        assert_eq!(format!("Value of FAR does not fit in 1 byte(s). Line 3, in {}",
+                           dir.join("main.asm").display()),
+                   range.unwrap_err().message);
+    }
+
+    #[test]
+    fn errors_on_files_that_include_themselves() {
+        let dir = write_files("include_cycle",
+                              &[("a.asm", b".INCLUDE \"b.asm\"\n"), ("b.asm", b"NOP\n.INCLUDE \"a.asm\"\n")]);
+
+        let mut assembler = Assembler::new();
+        let result = assembler.assemble_file(dir.join("a.asm"), None);
+
+        assert!(result.unwrap_err().message.starts_with("'a.asm' is already being included. Line 2, in "));
+    }
+
+    #[test]
+    fn can_embed_binary_files() {
+        let dir = write_files("incbin",
+                              &[("main.asm", b".INCBIN \"font.bin\"\n.INCBIN \"font.bin\", 2, 1\n"),
+                                ("font.bin", &[0x00, 0x3C, 0x66, 0xFF])]);
+
+        let mut assembler = Assembler::new();
+        let segments = assembler.assemble_file(dir.join("main.asm"), None).unwrap();
+        let font = dir.join("font.bin").display().to_string();
+        let too_long = assembler.assemble_string(format!(".INCBIN \"{}\", 2, 3", font), None);
+
+        assert_eq!(&[0x00, 0x3C, 0x66, 0xFF, 0x66], &segments[0].code[..]);
+        assert_eq!(format!("Range is outside of '{}'. Line 1", font),
+                   too_long.unwrap_err().message);
+    }
+
+    #[test]
+    fn can_assemble_data_directives() {
+        let mut assembler = Assembler::new();
+        let segments = assembler.assemble_string("
+            .ORG $C000
+                JMP (TABLE)
+            .TEXT \"OK\", 13
+            .ASCIIZ \"A\\\"\"
+            .DBYTE $1234
+            .RES 2
+            .FILL 2, $EA
+            .ALIGN $10, $FF
+            TABLE:
+            .WORD START, TABLE + 2
+            START:
+                BNE START
+        ",
+                                                 None)
+            .unwrap();
+
+        assert_eq!(&[0x6C, 0x10, 0xC0, // JMP (TABLE)
+                     0x4F, 0x4B, 0x0D, // .TEXT
+                     0x41, 0x22, 0x00, // .ASCIIZ
+                     0x12, 0x34, // .DBYTE
+                     0x00, 0x00, // .RES
+                     0xEA, 0xEA, // .FILL
+                     0xFF, // .ALIGN
+                     0x14, 0xC0, 0x12, 0xC0, // .WORD
+                     0xD0, 0xFE],
+                   &segments[0].code[..]);
+    }
+
+    #[test]
+    fn labels_settle_when_data_sizes_depend_on_them() {
+        let mut assembler = Assembler::new();
+        let segments = assembler.assemble_string("
+            ; Room for the routine to be copied into
+            .RES COPY_END - COPY_START, $EA
+            COPY_START:
+                LDA #$01
+                RTS
+            COPY_END:
+                JMP COPY_END
+        ",
+                                                 None)
+            .unwrap();
+
+        assert_eq!(&[0xEA, 0xEA, 0xEA, 0xA9, 0x01, 0x60, 0x4C, 0x06, 0x00],
+                   &segments[0].code[..]);
+    }
+
+    #[test]
+    fn errors_on_labels_that_never_settle() {
+        let mut assembler = Assembler::new();
+        let unsettled = assembler.assemble_string("
+            START:
+            .RES 1 - (END - START)
+            END:
+        ",
+                                                  None);
+        let duplicated = assembler.assemble_string("LOOP NOP\nLOOP NOP", None);
+        let align = assembler.assemble_string("SIZE\n.ALIGN SIZE", None);
+
+        assert_eq!("Label addresses still changed after 64 passes", unsettled.unwrap_err().message);
+        assert_eq!("Label 'LOOP' is defined more than once. Line 2", duplicated.unwrap_err().message);
+        assert_eq!("Value must be known before assembly. Line 2", align.unwrap_err().message);
+    }
+
+    #[test]
+    fn labels_in_the_zero_page_use_zero_page_addressing() {
+        let mut assembler = Assembler::new();
+        let segments = assembler.assemble_string("
+            .ORG $0080
+            PTR:
+            .RES 2
+            COUNT:
+            .RES 1
+
+            .ORG $C000
+                LDA PTR,X
+                STX COUNT,Y
+                LDA (PTR),Y
+                INC BUFFER
+                DEC TEMP
+                LDA a:PTR
+                LDA z:TEMP
+                JMP MAIN
+
+            .ORG $0200
+            BUFFER:
+            .ORG $00F0
+            TEMP:
+            .ORG $D000
+            MAIN:
+        ",
+                                                 None)
+            .unwrap();
+
+        assert_eq!(&[0xB5, 0x80, // LDA PTR,X
+                     0x96, 0x82, // STX COUNT,Y
+                     0xB1, 0x80, // LDA (PTR),Y
+                     0xEE, 0x00, 0x02, // INC BUFFER
+                     0xC6, 0xF0, // DEC TEMP, a forward reference
+                     0xAD, 0x80, 0x00, // LDA a:PTR
+                     0xA5, 0xF0, // LDA z:TEMP
+                     0x4C, 0x00, 0xD0],
+                   &segments[1].code[..]);
+    }
+
+    #[test]
+    fn forward_references_settle_on_zero_page() {
+        let mut assembler = Assembler::new();
+        let segments = assembler.assemble_string("
+                LDA VALUE
+                BNE DONE
+                LDA LATER
+            DONE:
+                RTS
+            VALUE:
+            .BYTE 1
+            LATER:
+        ",
+                                                 None)
+            .unwrap();
+
+        // Both loads shrink to zero page, which moves DONE, VALUE and LATER
+        assert_eq!(&[0xA5, 0x07, 0xD0, 0x02, 0xA5, 0x08, 0x60, 0x01], &segments[0].code[..]);
+    }
+
+    #[test]
+    fn errors_on_zero_page_overrides_that_do_not_fit() {
+        let mut assembler = Assembler::new();
+        let label = assembler.assemble_string("LDA z:FAR\n.ORG $1234\nFAR:", None);
+        let constant = assembler.assemble_string("LDA z:$1234", None);
+
+        assert_eq!("Value of FAR does not fit in 1 byte(s). Line 1", label.unwrap_err().message);
+        assert_eq!("Address too large. Line 1", constant.unwrap_err().message);
+    }
+
+    #[test]
+    fn cheap_local_labels_belong_to_the_global_label_before_them() {
+        let mut assembler = Assembler::new();
+        let segments = assembler.assemble_string("
+            CLEAR: LDX #2
+            @loop: DEX
+                BNE @loop
+            FILL:
+                LDX #2
+            .loop DEX
+                BNE .loop
+                JMP CLEAR@loop
+        ",
+                                                 0xC000)
+            .unwrap();
+
+        assert_eq!(&[0xA2, 0x02, 0xCA, 0xD0, 0xFD, 0xA2, 0x02, 0xCA, 0xD0, 0xFD, 0x4C, 0x02, 0xC0],
+                   &segments[0].code[..]);
+        assert_eq!(Some(0xC002), assembler.symbols().address_of("CLEAR@loop"));
+        assert_eq!(Some(0xC007), assembler.symbols().address_of("FILL@loop"));
+    }
+
+    #[test]
+    fn can_use_anonymous_labels_and_the_current_address() {
+        let mut assembler = Assembler::new();
+        let segments = assembler.assemble_string("
+            :   DEX
+                BNE :-
+                BEQ :++
+                BCC +
+            :   NOP
+            :   JMP *
+                BNE *-3
+        ",
+                                                 0xC000)
+            .unwrap();
+
+        assert_eq!(&[0xCA, 0xD0, 0xFD, 0xF0, 0x03, 0x90, 0x00, 0xEA, 0x4C, 0x08, 0xC0, 0xD0, 0xFB],
+                   &segments[0].code[..]);
+        // Anonymous labels have no names to show
+        assert_eq!(None, assembler.symbols().describe(0xC008));
+    }
+
+    #[test]
+    fn errors_on_missing_local_and_anonymous_labels() {
+        let mut assembler = Assembler::new();
+        let local = assembler.assemble_string("MAIN:\nBNE @lop\n@loop: RTS", None);
+        let duplicate = assembler.assemble_string("MAIN:\n@loop:\n.loop:", None);
+        let in_macro = assembler.assemble_string(".MACRO TWICE\n.loop NOP\n.loop NOP\n.ENDMACRO\nMAIN:\nTWICE", None);
+        let before = assembler.assemble_string("BNE :-", None);
+        let after = assembler.assemble_string("BEQ :+\nRTS", None);
+
+        assert_eq!("Unknown label: '@lop' (local to MAIN). Line 2", local.unwrap_err().message);
+        assert_eq!("Label '@loop' (local to MAIN) is defined more than once. Line 3",
+                   duplicate.unwrap_err().message);
+        assert_eq!("Label '@loop' (local to MAIN) is defined more than once. Line 3, in macro 'TWICE' called from line 6",
+                   in_macro.unwrap_err().message);
+        assert_eq!("No anonymous label before this reference. Line 1", before.unwrap_err().message);
+        assert_eq!("No anonymous label after this reference. Line 1", after.unwrap_err().message);
+    }
+
+    #[test]
+    fn labels_and_constants_resolve_outward_from_their_scope() {
+        let mut assembler = Assembler::new();
+        let segments = assembler.assemble_string("
+                JSR player::update
+                JSR update
+            update:
+                RTS
+            .PROC player
+            SPEED = 2
+            update:
+                LDA #SPEED
+                JSR update
+                JSR ::update
+            .SCOPE sprite
+            update:
+                LDX #SPEED
+                JMP player::update
+            .ENDSCOPE
+            .ENDPROC
+                JMP player::sprite::update
+        ",
+                                                 0xC000)
+            .unwrap();
+
+        assert_eq!(&[0x20, 0x07, 0xC0, 0x20, 0x06, 0xC0, 0x60, 0xA9, 0x02, 0x20, 0x07, 0xC0, 0x20, 0x06,
+                     0xC0, 0xA2, 0x02, 0x4C, 0x07, 0xC0, 0x4C, 0x0F, 0xC0],
+                   &segments[0].code[..]);
+        assert_eq!(Some(0xC007), assembler.symbols().address_of("player"));
+        assert_eq!(Some(0xC00F), assembler.symbols().address_of("player::sprite::update"));
+    }
+
+    #[test]
+    fn errors_on_unbalanced_scopes_and_unknown_scoped_labels() {
+        let mut assembler = Assembler::new();
+        let unterminated = assembler.assemble_string(".PROC player\nRTS", None);
+        let mismatched = assembler.assemble_string(".SCOPE sprite\n.ENDPROC", None);
+        let unknown = assembler.assemble_string(".PROC player\nJMP missing\n.ENDPROC", None);
+
+        assert_eq!(".PROC player has no .ENDPROC. Line 1", unterminated.unwrap_err().message);
+        assert_eq!(".ENDPROC without .PROC. Line 2", mismatched.unwrap_err().message);
+        assert_eq!("Unknown label: 'missing' in player. Line 2", unknown.unwrap_err().message);
+    }
+}
@@ -0,0 +1,203 @@
+use std::fmt;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum UnaryOperator {
+    Negate,
+    /// `<`, the low byte of a word
+    LowByte,
+    /// `>`, the high byte of a word
+    HighByte,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum BinaryOperator {
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Modulo,
+    And,
+    Or,
+    Xor,
+    ShiftLeft,
+    ShiftRight,
+    /// Comparisons give 1 if they hold and 0 if they don't
+    Equal,
+    NotEqual,
+    Less,
+    Greater,
+    LessOrEqual,
+    GreaterOrEqual,
+}
+
+impl BinaryOperator {
+    /// How tightly the operator binds. Higher binds tighter.
+    pub fn precedence(&self) -> u8 {
+        match *self {
+            BinaryOperator::Equal |
+            BinaryOperator::NotEqual |
+            BinaryOperator::Less |
+            BinaryOperator::Greater |
+            BinaryOperator::LessOrEqual |
+            BinaryOperator::GreaterOrEqual => 1,
+            BinaryOperator::Or => 2,
+            BinaryOperator::Xor => 3,
+            BinaryOperator::And => 4,
+            BinaryOperator::ShiftLeft | BinaryOperator::ShiftRight => 5,
+            BinaryOperator::Add | BinaryOperator::Subtract => 6,
+            BinaryOperator::Multiply | BinaryOperator::Divide | BinaryOperator::Modulo => 7,
+        }
+    }
+
+    fn symbol(&self) -> &'static str {
+        match *self {
+            BinaryOperator::Add => "+",
+            BinaryOperator::Subtract => "-",
+            BinaryOperator::Multiply => "*",
+            BinaryOperator::Divide => "/",
+            BinaryOperator::Modulo => "%",
+            BinaryOperator::And => "&",
+            BinaryOperator::Or => "|",
+            BinaryOperator::Xor => "^",
+            BinaryOperator::ShiftLeft => "<<",
+            BinaryOperator::ShiftRight => ">>",
+            BinaryOperator::Equal => "=",
+            BinaryOperator::NotEqual => "<>",
+            BinaryOperator::Less => "<",
+            BinaryOperator::Greater => ">",
+            BinaryOperator::LessOrEqual => "<=",
+            BinaryOperator::GreaterOrEqual => ">=",
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum EvaluationError {
+    UnknownSymbol(String),
+    DivisionByZero,
+}
+
+/// An operand or data value. Symbols are looked up when the expression is
+/// evaluated, so an expression can refer to labels that haven't been
+/// defined yet.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Expression {
+    Number(i32),
+    Symbol(String),
+    Unary(UnaryOperator, Box<Expression>),
+    Binary(BinaryOperator, Box<Expression>, Box<Expression>),
+}
+
+impl Expression {
+    /// Works out the value of the expression, asking `lookup` for the value
+    /// of each symbol in it
+    pub fn evaluate<F>(&self, lookup: &F) -> Result<i32, EvaluationError>
+        where F: Fn(&str) -> Option<i32>
+    {
+        match *self {
+            Expression::Number(value) => Ok(value),
+            Expression::Symbol(ref name) => {
+                lookup(name).ok_or_else(|| EvaluationError::UnknownSymbol(name.clone()))
+            }
+            Expression::Unary(operator, ref operand) => {
+                let value = operand.evaluate(lookup)?;
+                Ok(match operator {
+                    UnaryOperator::Negate => value.wrapping_neg(),
+                    UnaryOperator::LowByte => value & 0xFF,
+                    UnaryOperator::HighByte => (value >> 8) & 0xFF,
+                })
+            }
+            Expression::Binary(operator, ref left, ref right) => {
+                let left = left.evaluate(lookup)?;
+                let right = right.evaluate(lookup)?;
+                Ok(match operator {
+                    BinaryOperator::Add => left.wrapping_add(right),
+                    BinaryOperator::Subtract => left.wrapping_sub(right),
+                    BinaryOperator::Multiply => left.wrapping_mul(right),
+                    BinaryOperator::Divide | BinaryOperator::Modulo if right == 0 => {
+                        return Err(EvaluationError::DivisionByZero);
+                    }
+                    BinaryOperator::Divide => left.wrapping_div(right),
+                    BinaryOperator::Modulo => left.wrapping_rem(right),
+                    BinaryOperator::And => left & right,
+                    BinaryOperator::Or => left | right,
+                    BinaryOperator::Xor => left ^ right,
+                    BinaryOperator::ShiftLeft => left.wrapping_shl(right as u32),
+                    BinaryOperator::ShiftRight => left.wrapping_shr(right as u32),
+                    BinaryOperator::Equal => (left == right) as i32,
+                    BinaryOperator::NotEqual => (left != right) as i32,
+                    BinaryOperator::Less => (left < right) as i32,
+                    BinaryOperator::Greater => (left > right) as i32,
+                    BinaryOperator::LessOrEqual => (left <= right) as i32,
+                    BinaryOperator::GreaterOrEqual => (left >= right) as i32,
+                })
+            }
+        }
+    }
+
+    /// The value of the expression, if it doesn't depend on any symbols
+    pub fn constant_value(&self) -> Option<i32> {
+        self.evaluate(&|_| None).ok()
+    }
+}
+
+impl fmt::Display for Expression {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Expression::Number(value) if value < 0 => write!(f, "-${:X}", -(value as i64)),
+            Expression::Number(value) => write!(f, "${:X}", value),
+            Expression::Symbol(ref name) => write!(f, "{}", name),
+            Expression::Unary(operator, ref operand) => {
+                let symbol = match operator {
+                    UnaryOperator::Negate => "-",
+                    UnaryOperator::LowByte => "<",
+                    UnaryOperator::HighByte => ">",
+                };
+                write!(f, "{}{}", symbol, operand)
+            }
+            Expression::Binary(operator, ref left, ref right) => {
+                write!(f, "({}{}{})", left, operator.symbol(), right)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn symbol(name: &str) -> Box<Expression> {
+        Box::new(Expression::Symbol(name.into()))
+    }
+
+    fn number(value: i32) -> Box<Expression> {
+        Box::new(Expression::Number(value))
+    }
+
+    #[test]
+    fn evaluates_symbols_through_the_lookup() {
+        // >(END-START)
+        let expression = Expression::Unary(UnaryOperator::HighByte,
+                                           Box::new(Expression::Binary(BinaryOperator::Subtract,
+                                                                       symbol("END"),
+                                                                       symbol("START"))));
+        let lookup = |name: &str| match name {
+            "START" => Some(0xC000),
+            "END" => Some(0xC345),
+            _ => None,
+        };
+
+        assert_eq!(Ok(0x03), expression.evaluate(&lookup));
+        assert_eq!(None, expression.constant_value());
+    }
+
+    #[test]
+    fn reports_unknown_symbols_and_division_by_zero() {
+        let unknown = Expression::Binary(BinaryOperator::Add, symbol("TABLE"), number(2));
+        let divide = Expression::Binary(BinaryOperator::Divide, number(2), number(0));
+
+        assert_eq!(Err(EvaluationError::UnknownSymbol("TABLE".into())),
+                   unknown.evaluate(&|_| None));
+        assert_eq!(Err(EvaluationError::DivisionByZero), divide.evaluate(&|_| None));
+    }
+}
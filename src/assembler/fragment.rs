@@ -0,0 +1,181 @@
+use std::cell::Cell;
+
+use ::opcodes::OpCode;
+use assembler::expression::Expression;
+use assembler::token::ParserToken;
+
+/// A piece of a program as the assembler lays it out. Each fragment knows
+/// how many bytes it takes up, which can depend on where it lands and on
+/// the addresses of labels.
+#[derive(Debug)]
+pub enum Fragment {
+    Label(String),
+    Org(u16),
+    /// Symbols after this are looked up from inside the named scope first
+    Scope(String),
+    /// Where the fragments after this came from in the source, for errors
+    Location(String),
+    /// An opcode and its operand, if it has one
+    Instruction(OpCode, Option<Expression>),
+    /// An instruction that is zero page if its operand fits in a byte,
+    /// and absolute if it doesn't or isn't known yet. Once it has been
+    /// seen to need absolute addressing it stays absolute, so that passes
+    /// can't flip it back and forth forever.
+    ZeroPageOrAbsolute {
+        zero_page: OpCode,
+        absolute: OpCode,
+        operand: Expression,
+        pinned: Cell<bool>,
+    },
+    Bytes(Vec<Expression>),
+    /// Little endian words
+    Words(Vec<Expression>),
+    /// Big endian words
+    DoubleBytes(Vec<Expression>),
+    /// A count, and the byte to repeat that many times
+    Fill(Expression, Expression),
+    /// Padding up to the next multiple of a size
+    Align(u32, u8),
+    Raw(Vec<u8>),
+}
+
+impl Fragment {
+    /// Groups the parser's tokens into fragments, joining each opcode up
+    /// with the operand bytes that follow it
+    pub fn from_tokens(tokens: Vec<ParserToken>) -> Vec<Fragment> {
+        let mut fragments = Vec::new();
+        let mut tokens = tokens.into_iter().peekable();
+
+        while let Some(token) = tokens.next() {
+            let fragment = match token {
+                ParserToken::Label(name) => Fragment::Label(name),
+                ParserToken::OrgDirective(addr) => Fragment::Org(addr),
+                ParserToken::Scope(scope) => Fragment::Scope(scope),
+                ParserToken::Location(location) => Fragment::Location(location),
+                ParserToken::OpCode(opcode) => {
+                    let operand = match tokens.peek() {
+                        Some(ParserToken::Operand(_)) => {
+                            match tokens.next() {
+                                Some(ParserToken::Operand(expression)) => Some(expression),
+                                _ => unreachable!(),
+                            }
+                        }
+                        Some(ParserToken::RawByte(_)) => {
+                            // Operands the parser could work out are little
+                            // endian bytes
+                            let mut value = 0;
+                            for shift in 0..opcode.length as i32 - 1 {
+                                if let Some(ParserToken::RawByte(byte)) = tokens.peek() {
+                                    value |= (*byte as i32) << (shift * 8);
+                                    tokens.next();
+                                }
+                            }
+                            Some(Expression::Number(value))
+                        }
+                        _ => None,
+                    };
+                    Fragment::Instruction(opcode, operand)
+                }
+                ParserToken::ZeroPageOrAbsolute(zero_page, absolute, operand) => {
+                    Fragment::ZeroPageOrAbsolute {
+                        zero_page,
+                        absolute,
+                        operand,
+                        pinned: Cell::new(false),
+                    }
+                }
+                ParserToken::RawByte(byte) => Fragment::Raw(vec![byte]),
+                ParserToken::RawBytes(bytes) => Fragment::Raw(bytes),
+                ParserToken::Bytes(expressions) => Fragment::Bytes(expressions),
+                ParserToken::Words(expressions) => Fragment::Words(expressions),
+                ParserToken::DoubleBytes(expressions) => Fragment::DoubleBytes(expressions),
+                ParserToken::Fill(count, value) => Fragment::Fill(count, value),
+                ParserToken::Align(size, fill) => Fragment::Align(size, fill),
+                ParserToken::Operand(_) | ParserToken::Absolute(_) => continue,
+            };
+            fragments.push(fragment);
+        }
+
+        fragments
+    }
+
+    /// How many bytes the fragment takes up at `addr`. `lookup` gives the
+    /// addresses labels had on the last pass, and a size that depends on a
+    /// label without an address yet counts as zero.
+    pub fn size<F>(&self, addr: u16, lookup: &F) -> u16
+        where F: Fn(&str) -> Option<i32>
+    {
+        match *self {
+            Fragment::Label(_) | Fragment::Org(_) | Fragment::Scope(_) | Fragment::Location(_) => 0,
+            Fragment::Instruction(opcode, _) => opcode.length as u16,
+            Fragment::ZeroPageOrAbsolute { zero_page, absolute, ref operand, ref pinned } => {
+                if !pinned.get() {
+                    match operand.evaluate(lookup) {
+                        Ok(value) if (0..=0xFF).contains(&value) => return zero_page.length as u16,
+                        Ok(_) => pinned.set(true),
+                        Err(_) => (),
+                    }
+                }
+                absolute.length as u16
+            }
+            Fragment::Bytes(ref expressions) => expressions.len() as u16,
+            Fragment::Words(ref expressions) |
+            Fragment::DoubleBytes(ref expressions) => expressions.len() as u16 * 2,
+            Fragment::Fill(ref count, _) => count.evaluate(lookup).map_or(0, |count| count.clamp(0, 0xFFFF) as u16),
+            Fragment::Align(size, _) => ((size - addr as u32 % size) % size) as u16,
+            Fragment::Raw(ref bytes) => bytes.len() as u16,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::opcodes::AddressingMode;
+
+    #[test]
+    fn joins_opcodes_to_their_operands() {
+        let lda = OpCode::from_mnemonic_and_addressing_mode("LDA", AddressingMode::Absolute).unwrap();
+        let nop = OpCode::from_mnemonic_and_addressing_mode("NOP", AddressingMode::Implied).unwrap();
+        let fragments = Fragment::from_tokens(vec![ParserToken::OpCode(lda),
+                                                   ParserToken::RawByte(0x00),
+                                                   ParserToken::RawByte(0x44),
+                                                   ParserToken::OpCode(nop),
+                                                   ParserToken::RawBytes(vec![1, 2])]);
+
+        assert_eq!(3, fragments.len());
+        match fragments[0] {
+            Fragment::Instruction(_, Some(Expression::Number(0x4400))) => (),
+            ref fragment => panic!("{:?}", fragment),
+        }
+        let sizes: Vec<u16> = fragments.iter().map(|fragment| fragment.size(0, &|_| None)).collect();
+        assert_eq!(vec![3, 1, 2], sizes);
+    }
+
+    #[test]
+    fn sizes_can_depend_on_labels_and_addresses() {
+        let count = Expression::Symbol("COUNT".into());
+        let fill = Fragment::Fill(count, Expression::Number(0));
+        let align = Fragment::Align(0x100, 0);
+
+        assert_eq!(0, fill.size(0, &|_| None));
+        assert_eq!(5, fill.size(0, &|_| Some(5)));
+        assert_eq!(0xFF, align.size(0xC001, &|_| None));
+        assert_eq!(0, align.size(0xC100, &|_| None));
+    }
+
+    #[test]
+    fn instructions_stay_absolute_once_they_need_it() {
+        let fragment = Fragment::ZeroPageOrAbsolute {
+            zero_page: OpCode::from_mnemonic_and_addressing_mode("LDA", AddressingMode::ZeroPage).unwrap(),
+            absolute: OpCode::from_mnemonic_and_addressing_mode("LDA", AddressingMode::Absolute).unwrap(),
+            operand: Expression::Symbol("PTR".into()),
+            pinned: Cell::new(false),
+        };
+
+        assert_eq!(3, fragment.size(0, &|_| None));
+        assert_eq!(2, fragment.size(0, &|_| Some(0x80)));
+        assert_eq!(3, fragment.size(0, &|_| Some(0x100)));
+        assert_eq!(3, fragment.size(0, &|_| Some(0x80)));
+    }
+}
@@ -1,6 +1,6 @@
 // This lexer is based on the grammar I found here: https://github.com/antlr/grammars-v4/blob/master/asm6502/asm6502.g4
-// It doesn't support the arithmetic operators, however. It looks like it matches the various 6502 assembly examples
-// I have seen online and so is good enough.
+// It looks like it matches the various 6502 assembly examples I have seen online and so is good enough. On top of
+// that grammar it lexes the operators used in expressions.
 
 use std;
 use std::error::Error;
@@ -53,6 +53,14 @@ impl LexerError {
     fn unexpected_token(line: u32, column: u32) -> LexerError {
         LexerError::from(format!("Unexpected token. Line {} col {}", line, column))
     }
+
+    fn unterminated_string(line: u32, column: u32) -> LexerError {
+        LexerError::from(format!("String has no closing quote. Line {} col {}", line, column))
+    }
+
+    fn unknown_escape(line: u32, column: u32) -> LexerError {
+        LexerError::from(format!("Unknown escape sequence. Line {} col {}", line, column))
+    }
 }
 
 impl From<std::io::Error> for LexerError {
@@ -78,11 +86,25 @@ impl<'a> From<&'a str> for LexerError {
 pub struct Lexer {
     line: u32,
     col: u32,
+    /// Whether a line that can't be lexed ends in an `Invalid` token,
+    /// rather than failing the whole source
+    tolerant: bool,
 }
 
 impl Lexer {
     pub fn new() -> Lexer {
-        Lexer { line: 0, col: 0 }
+        Lexer {
+            line: 0,
+            col: 0,
+            tolerant: false,
+        }
+    }
+
+    /// Returns a Lexer that keeps going past lines it can't lex. Each one
+    /// ends in a `LexerToken::Invalid` holding the error, which the parser
+    /// only reports if the line is assembled.
+    pub fn tolerant() -> Lexer {
+        Lexer { tolerant: true, ..Lexer::new() }
     }
 
     /// Returns a vector of Tokens given an input of
@@ -133,60 +155,211 @@ impl Lexer {
             }
 
             let mut tokens = Vec::new();
-            let mut iter = line.chars();
-            let mut peeker = iter.peekable();
-
-            loop {
-                // Break out if we've reached the end of the line
-                if let None = peeker.peek() {
-                    break;
+            if let Err(error) = self.lex_line(line, &mut tokens) {
+                if !self.tolerant {
+                    return Err(error);
                 }
+                tokens.push(LexerToken::Invalid(error.message));
+            }
 
-                // Consume any leading whitespace voids we're sitting in
-                if peeker.peek().unwrap().is_whitespace() {
-                    self.consume_whitespace(&mut peeker);
-                } else if peeker.peek().unwrap().is_alphanumeric() {
-                    let token = self.consume_alphanumeric(&mut peeker)?;
-                    tokens.push(token);
-                } else if *peeker.peek().unwrap() == ';' {
-                    // Skip the rest of this line
-                    break;
-                } else if *peeker.peek().unwrap() == '(' {
-                    // Indirect addressing
-                    self.advance(&mut peeker);
-                    tokens.push(LexerToken::OpenParenthesis);
-                } else if *peeker.peek().unwrap() == ')' {
-                    // Indirect addressing
-                    self.advance(&mut peeker);
-                    tokens.push(LexerToken::CloseParenthesis);
-                } else if *peeker.peek().unwrap() == '$' {
-                    let token = self.consume_address(&mut peeker)?;
-                    tokens.push(token);
-                } else if *peeker.peek().unwrap() == '#' {
-                    if let LexerToken::Immediate(number, base) = self.consume_number(&mut peeker)? {
-                        tokens.push(LexerToken::Immediate(number, base));
+            result.push(tokens);
+        }
+
+        Ok(result)
+    }
+
+    /// Lexes one line of source into `tokens`, stopping at the first error
+    fn lex_line(&mut self, line: &str, tokens: &mut Vec<LexerToken>) -> Result<(), LexerError> {
+        let mut iter = line.chars();
+        let mut peeker = iter.peekable();
+
+        loop {
+            // Break out if we've reached the end of the line
+            if let None = peeker.peek() {
+                break;
+            }
+
+            // Consume any leading whitespace voids we're sitting in
+            if peeker.peek().unwrap().is_whitespace() {
+                self.consume_whitespace(&mut peeker);
+            } else if peeker.peek().unwrap().is_ascii_digit() {
+                if let LexerToken::Immediate(number, _) =
+                       self.consume_digits(&mut peeker, &ImmediateBase::Base10)? {
+                    tokens.push(LexerToken::Number(number));
+                }
+            } else if peeker.peek().unwrap().is_alphanumeric() {
+                let token = self.consume_alphanumeric(&mut peeker)?;
+                tokens.push(token);
+            } else if *peeker.peek().unwrap() == '@' {
+                // Cheap local labels keep their @, so they can't clash
+                // with other names
+                let column = self.col + 1;
+                self.advance(&mut peeker);
+                match peeker.peek() {
+                    Some(&c) if c.is_alphanumeric() || c == '_' => {
+                        if let LexerToken::Ident(name) = self.consume_alphanumeric(&mut peeker)? {
+                            tokens.push(LexerToken::Ident(format!("@{}", name)));
+                        }
                     }
-                } else if *peeker.peek().unwrap() == '.' {
-                    self.advance(&mut peeker);
-                    tokens.push(LexerToken::Period);
-                } else if *peeker.peek().unwrap() == ':' {
+                    _ => return Err(LexerError::unexpected_token(self.line, column)),
+                }
+            } else if *peeker.peek().unwrap() == ';' {
+                // Skip the rest of this line
+                break;
+            } else if *peeker.peek().unwrap() == '(' {
+                // Indirect addressing
+                self.advance(&mut peeker);
+                tokens.push(LexerToken::OpenParenthesis);
+            } else if *peeker.peek().unwrap() == ')' {
+                // Indirect addressing
+                self.advance(&mut peeker);
+                tokens.push(LexerToken::CloseParenthesis);
+            } else if *peeker.peek().unwrap() == '$' {
+                let token = self.consume_address(&mut peeker)?;
+                tokens.push(token);
+            } else if *peeker.peek().unwrap() == '#' {
+                // A number straight after the hash is lexed with it, anything
+                // else is the start of an expression
+                let mut ahead = peeker.clone();
+                ahead.next();
+                match ahead.peek() {
+                    Some(&c) if c == '$' || c.is_ascii_digit() => {
+                        if let LexerToken::Immediate(number, base) =
+                               self.consume_number(&mut peeker)? {
+                            tokens.push(LexerToken::Immediate(number, base));
+                        }
+                    }
+                    _ => {
+                        self.advance(&mut peeker);
+                        tokens.push(LexerToken::Hash);
+                    }
+                }
+            } else if let Some(token) = self.consume_operator(&mut peeker) {
+                tokens.push(token);
+            } else if *peeker.peek().unwrap() == '.' {
+                self.advance(&mut peeker);
+                tokens.push(LexerToken::Period);
+            } else if *peeker.peek().unwrap() == ':' {
+                self.advance(&mut peeker);
+                // Two colons join the parts of a qualified name
+                if let Some(&':') = peeker.peek() {
                     self.advance(&mut peeker);
+                    tokens.push(LexerToken::DoubleColon);
+                } else {
                     tokens.push(LexerToken::Colon);
-                } else if *peeker.peek().unwrap() == '=' {
-                    self.advance(&mut peeker);
-                    tokens.push(LexerToken::Assignment);
-                } else if *peeker.peek().unwrap() == ',' {
-                    self.advance(&mut peeker);
-                    tokens.push(LexerToken::Comma);
+                }
+            } else if *peeker.peek().unwrap() == '=' {
+                self.advance(&mut peeker);
+                tokens.push(LexerToken::Assignment);
+            } else if *peeker.peek().unwrap() == ',' {
+                self.advance(&mut peeker);
+                tokens.push(LexerToken::Comma);
+            } else if *peeker.peek().unwrap() == '"' {
+                let token = self.consume_string(&mut peeker)?;
+                tokens.push(token);
+            } else {
+                return Err(LexerError::unexpected_token(self.line, self.col + 1));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Consumes an expression operator, if that is what comes next
+    fn consume_operator<I>(&mut self, peeker: &mut Peekable<I>) -> Option<LexerToken>
+        where I: Iterator<Item = char> + Clone
+    {
+        let token = match *peeker.peek().unwrap() {
+            '+' => LexerToken::Plus,
+            '-' => LexerToken::Minus,
+            '*' => LexerToken::Asterisk,
+            '/' => LexerToken::Slash,
+            '%' => LexerToken::Percent,
+            '&' => LexerToken::Ampersand,
+            '|' => LexerToken::Pipe,
+            '^' => LexerToken::Caret,
+            c @ '<' | c @ '>' => {
+                // Doubled up they're shifts, and followed by = or > they're
+                // comparisons
+                let mut ahead = peeker.clone();
+                ahead.next();
+                let token = match (c, ahead.peek()) {
+                    ('<', Some(&'<')) => Some(LexerToken::ShiftLeft),
+                    ('<', Some(&'=')) => Some(LexerToken::LessThanOrEqual),
+                    ('<', Some(&'>')) => Some(LexerToken::NotEqual),
+                    ('>', Some(&'>')) => Some(LexerToken::ShiftRight),
+                    ('>', Some(&'=')) => Some(LexerToken::GreaterThanOrEqual),
+                    _ => None,
+                };
+                if let Some(token) = token {
+                    self.advance(peeker);
+                    self.advance(peeker);
+                    return Some(token);
+                }
+
+                if c == '<' {
+                    LexerToken::LessThan
                 } else {
-                    return Err(LexerError::unexpected_token(self.line, self.col + 1));
+                    LexerToken::GreaterThan
                 }
             }
+            _ => return None,
+        };
 
-            result.push(tokens);
+        self.advance(peeker);
+        Some(token)
+    }
+
+    /// Consumes the text between a pair of double quotes. A backslash
+    /// starts an escape: `\n`, `\r`, `\t`, `\0`, `\\`, `\"` or `\x` and two hex
+    /// digits.
+    fn consume_string<I>(&mut self, peeker: &mut Peekable<I>) -> Result<LexerToken, LexerError>
+        where I: Iterator<Item = char>
+    {
+        let column = self.col + 1;
+        // Skip the opening quote
+        self.advance(peeker);
+
+        let mut text = String::new();
+        loop {
+            match peeker.peek() {
+                Some(&'"') => break,
+                Some(&'\\') => {
+                    let escape_column = self.col + 1;
+                    self.advance(peeker);
+                    let c = match peeker.peek() {
+                        Some(&'n') => '\n',
+                        Some(&'r') => '\r',
+                        Some(&'t') => '\t',
+                        Some(&'0') => '\0',
+                        Some(&'\\') => '\\',
+                        Some(&'"') => '"',
+                        Some(&'x') => {
+                            let mut value = 0;
+                            for _ in 0..2 {
+                                self.advance(peeker);
+                                match peeker.peek().and_then(|c| c.to_digit(16)) {
+                                    Some(digit) => value = value * 16 + digit,
+                                    None => return Err(LexerError::unknown_escape(self.line, escape_column)),
+                                }
+                            }
+                            std::char::from_u32(value).unwrap()
+                        }
+                        _ => return Err(LexerError::unknown_escape(self.line, escape_column)),
+                    };
+                    text.push(c);
+                    self.advance(peeker);
+                }
+                Some(&c) => {
+                    text.push(c);
+                    self.advance(peeker);
+                }
+                None => return Err(LexerError::unterminated_string(self.line, column)),
+            }
         }
+        self.advance(peeker);
 
-        Ok(result)
+        Ok(LexerToken::StringLiteral(text))
     }
 
     /// Consumes alphanumeric characters until it reachs something that terminates it
@@ -203,7 +376,9 @@ impl Lexer {
             }
             let c = *peeker.peek().unwrap();
 
-            if c.is_alphanumeric() || c == '_' {
+            // An @ inside a name is part of the full name of a cheap local
+            // label, like CLEAR@loop
+            if c.is_alphanumeric() || c == '_' || (c == '@' && !tok.is_empty()) {
                 tok.push(c);
                 self.advance(&mut peeker);
             } else {
@@ -413,7 +588,7 @@ mod tests {
     fn errors_on_unexpected_token() {
         let mut lexer = Lexer::new();
         let tokens = lexer.lex_string("
-            LDA ($F-----F,X)
+            LDA ($F!!!!!F,X)
         ");
 
         assert_eq!(Err(LexerError::unexpected_token(2, 20)), tokens);
@@ -464,4 +639,102 @@ mod tests {
                      LexerToken::Immediate("FF".into(), ImmediateBase::Base16)],
                    &tokens[2][..]);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn can_lex_expressions() {
+        let mut lexer = Lexer::new();
+        let tokens = lexer.lex_string("
+            LDA #<(TABLE+2*$10)>>1
+        ")
+            .unwrap();
+
+        assert_eq!(&[LexerToken::Ident("LDA".into()),
+                     LexerToken::Hash,
+                     LexerToken::LessThan,
+                     LexerToken::OpenParenthesis,
+                     LexerToken::Ident("TABLE".into()),
+                     LexerToken::Plus,
+                     LexerToken::Number("2".into()),
+                     LexerToken::Asterisk,
+                     LexerToken::Address("10".into()),
+                     LexerToken::CloseParenthesis,
+                     LexerToken::ShiftRight,
+                     LexerToken::Number("1".into())],
+                   &tokens[1][..]);
+    }
+
+    #[test]
+    fn can_lex_comparisons() {
+        let mut lexer = Lexer::new();
+        let tokens = lexer.lex_string(".IF A<=B <> C>=D").unwrap();
+
+        assert_eq!(&[LexerToken::Period,
+                     LexerToken::Ident("IF".into()),
+                     LexerToken::Ident("A".into()),
+                     LexerToken::LessThanOrEqual,
+                     LexerToken::Ident("B".into()),
+                     LexerToken::NotEqual,
+                     LexerToken::Ident("C".into()),
+                     LexerToken::GreaterThanOrEqual,
+                     LexerToken::Ident("D".into())],
+                   &tokens[0][..]);
+    }
+
+    #[test]
+    fn can_lex_strings() {
+        let mut lexer = Lexer::new();
+        let tokens = lexer.lex_string(".INCLUDE \"gfx/font; v2.asm\" ; comment").unwrap();
+
+        assert_eq!(&[LexerToken::Period,
+                     LexerToken::Ident("INCLUDE".into()),
+                     LexerToken::StringLiteral("gfx/font; v2.asm".into())],
+                   &tokens[0][..]);
+    }
+
+    #[test]
+    fn can_lex_escapes_in_strings() {
+        let mut lexer = Lexer::new();
+        let tokens = lexer.lex_string(r#".TEXT "say \"hi\"\r\n\x7F\\""#).unwrap();
+
+        assert_eq!(LexerToken::StringLiteral("say \"hi\"\r\n\x7F\\".into()), tokens[0][2]);
+        assert_eq!(LexerError { message: "Unknown escape sequence. Line 1 col 8".into() },
+                   Lexer::new().lex_string(r#".TEXT "\q""#).unwrap_err());
+    }
+
+    #[test]
+    fn errors_on_unterminated_strings() {
+        let mut lexer = Lexer::new();
+        let result = lexer.lex_string(".INCBIN \"font.bin");
+
+        assert_eq!(LexerError { message: "String has no closing quote. Line 1 col 9".into() },
+                   result.unwrap_err());
+    }
+
+    #[test]
+    fn can_lex_qualified_names() {
+        let mut lexer = Lexer::new();
+        let tokens = lexer.lex_string("JSR ::player::update").unwrap();
+
+        assert_eq!(&[LexerToken::Ident("JSR".into()),
+                     LexerToken::DoubleColon,
+                     LexerToken::Ident("player".into()),
+                     LexerToken::DoubleColon,
+                     LexerToken::Ident("update".into())],
+                   &tokens[0][..]);
+    }
+
+    #[test]
+    fn can_lex_local_labels() {
+        let mut lexer = Lexer::new();
+        let tokens = lexer.lex_string("@loop: BNE @loop\nJMP CLEAR@loop").unwrap();
+
+        assert_eq!(&[LexerToken::Ident("@loop".into()),
+                     LexerToken::Colon,
+                     LexerToken::Ident("BNE".into()),
+                     LexerToken::Ident("@loop".into())],
+                   &tokens[0][..]);
+        assert_eq!(LexerToken::Ident("CLEAR@loop".into()), tokens[1][1]);
+        assert_eq!(LexerError { message: "Unexpected token. Line 1 col 5".into() },
+                   Lexer::new().lex_string("JMP @").unwrap_err());
+    }
+}
@@ -1,9 +1,13 @@
 
 mod assembler;
+mod expression;
+mod fragment;
 mod token;
 mod lexer;
 mod parser;
+mod symbols;
 
 pub use self::assembler::{Assembler, CodeSegment};
+pub use self::symbols::SymbolTable;
 pub use self::token::LexerToken;
 pub use self::lexer::Lexer;
\ No newline at end of file
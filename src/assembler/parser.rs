@@ -1,10 +1,14 @@
 use std;
 use std::collections::HashMap;
-use std::iter::Peekable;
-
-use byteorder::{ByteOrder, LittleEndian};
+use std::fs::File;
+use std::io::Read;
+use std::iter::{self, Peekable};
+use std::path::{Path, PathBuf};
 
 use ::opcodes::{AddressingMode, OpCode};
+use assembler::expression::{BinaryOperator, EvaluationError, Expression, UnaryOperator};
+use assembler::lexer::{Lexer, LexerError};
+use assembler::symbols::scoped_names;
 use assembler::token::{ImmediateBase, LexerToken, ParserToken};
 
 #[derive(Debug, PartialEq)]
@@ -56,6 +60,116 @@ impl ParserError {
     fn unknown_identifier(line: u32) -> ParserError {
         ParserError::from(format!("Unknown identifier. Line {}", line))
     }
+
+    fn unterminated_macro(name: &str, line: u32) -> ParserError {
+        ParserError::from(format!("Macro '{}' has no .ENDMACRO. Line {}", name, line))
+    }
+
+    fn unexpected_endmacro(line: u32) -> ParserError {
+        ParserError::from(format!(".ENDMACRO without .MACRO. Line {}", line))
+    }
+
+    fn nested_macro_definition(line: u32) -> ParserError {
+        ParserError::from(format!("Macros can't be defined inside other macros. Line {}", line))
+    }
+
+    fn wrong_macro_arg_count(name: &str, expected: usize, found: usize, line: u32) -> ParserError {
+        ParserError::from(format!("Macro '{}' takes {} argument(s), found {}. Line {}",
+                                  name,
+                                  expected,
+                                  found,
+                                  line))
+    }
+
+    fn macro_too_deep(name: &str, line: u32) -> ParserError {
+        ParserError::from(format!("Macro '{}' is nested more than {} deep. Line {}",
+                                  name,
+                                  MAX_MACRO_DEPTH,
+                                  line))
+    }
+
+    fn unterminated_conditional(line: u32) -> ParserError {
+        ParserError::from(format!(".IF has no .ENDIF. Line {}", line))
+    }
+
+    fn unexpected_conditional(directive: &str, line: u32) -> ParserError {
+        ParserError::from(format!(".{} without .IF. Line {}", directive, line))
+    }
+
+    fn unknown_condition(name: &str, line: u32) -> ParserError {
+        ParserError::from(format!("Condition uses '{}', which is not a constant. Line {}", name, line))
+    }
+
+    fn division_by_zero(line: u32) -> ParserError {
+        ParserError::from(format!("Division by zero. Line {}", line))
+    }
+
+    fn value_too_large(bytes: u8, line: u32) -> ParserError {
+        ParserError::from(format!("Value does not fit in {} byte(s). Line {}", bytes, line))
+    }
+
+    fn expected_constant(line: u32) -> ParserError {
+        ParserError::from(format!("Value must be known before assembly. Line {}", line))
+    }
+
+    fn unencodable_character(c: char, line: u32) -> ParserError {
+        ParserError::from(format!("Character '{}' does not fit in a byte. Line {}", c, line))
+    }
+
+    fn expected_file_name(line: u32) -> ParserError {
+        ParserError::from(format!("File name expected. Line {}", line))
+    }
+
+    fn file_not_found(name: &str, line: u32) -> ParserError {
+        ParserError::from(format!("Can't find '{}'. Line {}", name, line))
+    }
+
+    fn recursive_include(name: &str, line: u32) -> ParserError {
+        ParserError::from(format!("'{}' is already being included. Line {}", name, line))
+    }
+
+    fn cannot_read_file(name: &str, error: std::io::Error, line: u32) -> ParserError {
+        ParserError::from(format!("Can't read '{}': {}. Line {}", name, error, line))
+    }
+
+    fn unterminated_scope(scope: &Scope) -> ParserError {
+        let (start, end) = scope.directives();
+        ParserError::from(format!(".{} {} has no .{}. Line {}", start, scope.name, end, scope.line))
+    }
+
+    fn unexpected_end_of_scope(directive: &str, line: u32) -> ParserError {
+        let start = if directive == "ENDPROC" { "PROC" } else { "SCOPE" };
+        ParserError::from(format!(".{} without .{}. Line {}", directive, start, line))
+    }
+
+    fn no_anonymous_label(direction: &str, line: u32) -> ParserError {
+        ParserError::from(format!("No anonymous label {} this reference. Line {}", direction, line))
+    }
+
+    fn outside_of_file(name: &str, line: u32) -> ParserError {
+        ParserError::from(format!("Range is outside of '{}'. Line {}", name, line))
+    }
+
+    /// Adds the file an error happened in
+    fn in_file(self, path: &Path) -> ParserError {
+        ParserError::from(format!("{}, in {}", self.message, path.display()))
+    }
+
+    /// Adds the line of the `.INCLUDE` an error came through
+    fn included_from(self, line: u32) -> ParserError {
+        ParserError::from(format!("{}, included from line {}", self.message, line))
+    }
+
+    /// Adds where a macro was called from to an error in one of its lines.
+    /// A macro calling itself from the same line is only mentioned once.
+    fn in_macro(self, name: &str, call_line: u32) -> ParserError {
+        let frame = format!(", in macro '{}' called from line {}", name, call_line);
+        if self.message.ends_with(&frame) {
+            return self;
+        }
+
+        ParserError::from(format!("{}{}", self.message, frame))
+    }
 }
 
 impl From<String> for ParserError {
@@ -64,17 +178,149 @@ impl From<String> for ParserError {
     }
 }
 
+impl From<LexerError> for ParserError {
+    fn from(error: LexerError) -> ParserError {
+        ParserError { message: error.message }
+    }
+}
+
 impl<'a> From<&'a str> for ParserError {
     fn from(error: &str) -> ParserError {
         ParserError { message: error.into() }
     }
 }
 
-#[derive(Clone, Debug, PartialEq)]
-pub struct Variable(LexerToken);
+/// The ways an operand can be written, before the size of its value picks
+/// between zero page and absolute addressing
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum OperandSyntax {
+    Immediate,
+    Direct,
+    DirectX,
+    DirectY,
+    Indirect,
+    IndirectX,
+    IndirectY,
+}
+
+struct Operand {
+    syntax: OperandSyntax,
+    expression: Expression,
+    /// How many digits the operand was written with, if it is a lone
+    /// literal address, or the digits an `a:` or `z:` prefix stands for
+    digits: Option<usize>,
+}
+
+impl Operand {
+    fn new(syntax: OperandSyntax, expression: Expression, digits: Option<usize>) -> Operand {
+        Operand {
+            syntax,
+            expression,
+            digits,
+        }
+    }
+}
+
+/// How deeply macros can call other macros, which stops a macro that
+/// calls itself from expanding forever
+const MAX_MACRO_DEPTH: u32 = 32;
+
+/// Names that start a directive after a period, rather than a cheap local
+/// label like `.loop`
+const DIRECTIVES: &[&str] = &["ORG", "BYTE", "TEXT", "ASCII", "ASCIIZ", "WORD", "DBYTE", "FILL", "RES",
+                              "ALIGN", "MACRO", "ENDMACRO", "INCLUDE", "INCBIN", "IF", "IFDEF", "IFNDEF",
+                              "ELSEIF", "ELSE", "ENDIF", "EQU", "PROC", "ENDPROC", "SCOPE", "ENDSCOPE"];
+
+/// A macro definition: the names of its parameters and the lines it
+/// expands to, along with the source line each of them came from
+#[derive(Clone, Debug)]
+struct Macro {
+    name: String,
+    params: Vec<String>,
+    lines: Vec<(u32, Vec<LexerToken>)>,
+    line: u32,
+    /// The file the macro was defined in, if it came from a file
+    file: Option<PathBuf>,
+}
+
+/// An `.IF` block being parsed
+#[derive(Clone, Copy, Debug)]
+struct Conditional {
+    /// Whether lines in the current branch are assembled
+    active: bool,
+    /// Whether one of the branches so far was chosen. Once one is, the rest
+    /// are skipped.
+    taken: bool,
+    /// Whether the `.ELSE` branch has been reached
+    in_else: bool,
+    line: u32,
+}
+
+/// Something the current line is inside of, for saying where it came from
+#[derive(Clone, Debug)]
+enum Context {
+    /// A file being parsed, as it was found
+    File(PathBuf),
+    /// The line of the `.INCLUDE` the file inside of this came through
+    IncludedFrom(u32),
+    /// A macro being expanded, and the line it was called from
+    Macro(String, u32),
+}
+
+/// A `.PROC` or `.SCOPE` block being parsed
+#[derive(Clone, Debug)]
+struct Scope {
+    name: String,
+    /// Whether `.PROC` opened it, rather than `.SCOPE`
+    procedure: bool,
+    line: u32,
+}
+
+impl Scope {
+    /// The directives that open and close the block
+    fn directives(&self) -> (&'static str, &'static str) {
+        if self.procedure {
+            ("PROC", "ENDPROC")
+        } else {
+            ("SCOPE", "ENDSCOPE")
+        }
+    }
+}
 
 pub struct Parser {
-    symbol_table: HashMap<String, Variable>,
+    macros: HashMap<String, Macro>,
+    /// The macro whose lines are being recorded
+    defining: Option<Macro>,
+    macro_depth: u32,
+    /// The number of macro expansions so far, used to make the labels of
+    /// each one unique
+    expansions: u32,
+    /// Constants defined with `=` or `.EQU`, by their full names. Their
+    /// values are expressions, so a constant can be worked out from labels.
+    constants: HashMap<String, Expression>,
+    /// The `.IF` blocks around the current line, innermost last
+    conditionals: Vec<Conditional>,
+    /// Where `.INCLUDE` and `.INCBIN` look for files that aren't next to
+    /// the file including them
+    include_paths: Vec<PathBuf>,
+    /// The files being parsed, the innermost last
+    files: Vec<PathBuf>,
+    /// The files, includes and macro expansions around the current line,
+    /// the innermost last
+    contexts: Vec<Context>,
+    /// Whether each line's tokens are preceded by its `Location`
+    locations: bool,
+    /// The `.PROC` and `.SCOPE` blocks around the current line, innermost
+    /// last. Labels and constants defined in them are named after them,
+    /// e.g. `player::update`.
+    scopes: Vec<Scope>,
+    /// The last global label, which cheap local labels belong to
+    global_label: String,
+    /// The number of anonymous labels so far, which also names them
+    anonymous: u32,
+    /// The anonymous labels `:+` references are waiting for, and the
+    /// lines they were made on
+    forward_references: Vec<(u32, u32)>,
     line: u32,
 }
 
@@ -82,416 +328,1203 @@ pub struct Parser {
 impl Parser {
     pub fn new() -> Parser {
         Parser {
-            symbol_table: HashMap::new(),
+            macros: HashMap::new(),
+            defining: None,
+            macro_depth: 0,
+            expansions: 0,
+            constants: HashMap::new(),
+            conditionals: Vec::new(),
+            include_paths: Vec::new(),
+            files: Vec::new(),
+            contexts: Vec::new(),
+            locations: false,
+            scopes: Vec::new(),
+            global_label: String::new(),
+            anonymous: 0,
+            forward_references: Vec::new(),
             line: 0,
         }
     }
 
+    /// Defines a constant before parsing, as if the source began with
+    /// `NAME = value`
+    pub fn define<S>(&mut self, name: S, value: i32)
+        where S: Into<String>
+    {
+        self.constants.insert(name.into(), Expression::Number(value));
+    }
+
+    /// Adds a directory to search for included files
+    pub fn add_include_path<P>(&mut self, path: P)
+        where P: Into<PathBuf>
+    {
+        self.include_paths.push(path.into());
+    }
+
     pub fn parse(&mut self, tokens: Vec<Vec<LexerToken>>) -> Result<Vec<ParserToken>, ParserError> {
         let mut result = Vec::new();
+        self.parse_lines(&tokens, &mut result)?;
+
+        Ok(result)
+    }
+
+    /// Lexes and parses a file. Files it includes are found relative to it.
+    pub fn parse_file<P>(&mut self, path: P) -> Result<Vec<ParserToken>, ParserError>
+        where P: AsRef<Path>
+    {
+        let mut result = Vec::new();
+        self.contexts.push(Context::File(path.as_ref().to_path_buf()));
+        let outcome = self.parse_file_lines(path.as_ref(), &mut result);
+        self.contexts.pop();
+        outcome.map_err(|error| error.in_file(path.as_ref()))?;
 
-        for line in &tokens {
-            let mut added_label = false;
+        Ok(result)
+    }
+
+    /// Turns on putting a `ParserToken::Location` before the tokens of each
+    /// line, so that errors found once the tokens are assembled can say
+    /// where they came from
+    pub fn set_locations(&mut self, enabled: bool) {
+        self.locations = enabled;
+    }
+
+    fn parse_file_lines(&mut self, path: &Path, result: &mut Vec<ParserToken>) -> Result<(), ParserError> {
+        let tokens = Lexer::tolerant().lex_file(path)?;
+
+        self.files.push(path.canonicalize().unwrap_or_else(|_| path.to_path_buf()));
+        let outcome = self.parse_lines(&tokens, result);
+        self.files.pop();
+
+        outcome
+    }
+
+    /// Parses the lines of one source. Macros and conditionals started in
+    /// it have to end in it.
+    fn parse_lines(&mut self, tokens: &[Vec<LexerToken>], result: &mut Vec<ParserToken>) -> Result<(), ParserError> {
+        let outer_line = std::mem::replace(&mut self.line, 0);
+        let outer_conditionals = std::mem::take(&mut self.conditionals);
+        let outer_references = std::mem::take(&mut self.forward_references);
+        let outer_scopes = self.scopes.len();
+
+        let mut outcome = Ok(());
+        for line in tokens {
             self.line += 1;
 
-            let mut peeker = line.iter().peekable();
+            // Lines inside a macro definition are kept for when it is called
+            outcome = if self.defining.is_some() {
+                self.record_macro_line(line)
+            } else {
+                self.parse_line(line, result)
+            };
+            if outcome.is_err() {
+                break;
+            }
+        }
 
-            // Skip blank lines
-            if let None = peeker.peek() {
-                continue;
+        if outcome.is_ok() {
+            if let Some(definition) = self.defining.take() {
+                outcome = Err(ParserError::unterminated_macro(&definition.name, definition.line));
+            } else if let Some(conditional) = self.conditionals.last() {
+                outcome = Err(ParserError::unterminated_conditional(conditional.line));
+            } else if let Some(&(_, line)) = self.forward_references.iter().find(|&&(target, _)| target > self.anonymous) {
+                outcome = Err(ParserError::no_anonymous_label("after", line));
+            } else if self.scopes.len() > outer_scopes {
+                outcome = Err(ParserError::unterminated_scope(self.scopes.last().unwrap()));
             }
+        }
 
-            let next = *peeker.peek().unwrap();
+        self.line = outer_line;
+        self.conditionals = outer_conditionals;
+        self.forward_references = outer_references;
 
-            if let &LexerToken::Ident(ref ident) = next {
-                // Check if this is an opcode
-                if Self::is_opcode(ident.clone()) {
-                    // Yep its an opcode, lets figure out its addressing mode
-                    let mut opcode = self.consume_opcode(&mut peeker, ident.clone())?;
-                    result.append(&mut opcode);
-                } else {
-                    // Skip the ident and we'll check what is next
-                    let original_ident = peeker.next().unwrap();
-                    // if there is nothing else - lets mark this as a Label and move on
-                    if let None = peeker.peek() {
-                        result.push(ParserToken::Label(ident.clone()));
-                        continue;
-                    }
+        outcome
+    }
+
+    fn parse_line(&mut self, line: &[LexerToken], result: &mut Vec<ParserToken>) -> Result<(), ParserError> {
+        let mut added_label = false;
+
+        // Conditional directives are followed even in skipped lines, to
+        // keep track of where the skipping ends
+        if let Some(directive) = Self::conditional_directive(line) {
+            return self.consume_conditional(&directive, &line[2..]);
+        }
+        if !self.assembling() {
+            return Ok(());
+        }
+        Self::check_lexed(line)?;
+        if self.locations && !line.is_empty() {
+            result.push(ParserToken::Location(self.location()));
+        }
+
+        // The rest of a line after a label is parsed like a line of its own
+        if let Some((label, length)) = self.line_label(line) {
+            result.push(label);
+            return self.parse_line(&line[length..], result);
+        }
+
+        let mut peeker = line.iter().peekable();
+
+        // Skip blank lines
+        if let None = peeker.peek() {
+            return Ok(());
+        }
+
+        let next = *peeker.peek().unwrap();
+
+        if let &LexerToken::Ident(ref ident) = next {
+            // Check if this is an opcode
+            if Self::is_opcode(ident.clone()) {
+                // Yep its an opcode, lets figure out its addressing mode
+                let mut opcode = self.consume_opcode(&mut peeker, ident.clone())?;
+                result.append(&mut opcode);
+            } else if self.macros.contains_key(ident) {
+                peeker.next();
+                self.expand_macro(ident, &mut peeker, result)?;
+            } else {
+                // Skip the ident and we'll check what is next
+                let original_ident = peeker.next().unwrap();
+                // if there is nothing else - lets mark this as a Label and move on
+                if let None = peeker.peek() {
+                    result.push(self.label(ident));
+                    return Ok(());
+                }
 
-                    // A colon after the ident also indicates a label
-                    let next = *peeker.peek().unwrap();
-                    if let &LexerToken::Colon = next {
-                        result.push(ParserToken::Label(ident.clone()));
-                        continue;
+                // Is the next one a label as well? Thats an error:
+                let next = *peeker.peek().unwrap();
+                if let &LexerToken::Ident(ref ident) = next {
+                    // Lets add the original as a label
+                    if let &LexerToken::Ident(ref original_ident) = original_ident {
+                        let label = self.label(original_ident);
+                        result.push(label);
                     }
 
-                    // Is the next one a label as well? Thats an error:
-                    if let &LexerToken::Ident(ref ident) = next {
-                        // Lets add the original as a label
-                        if let &LexerToken::Ident(ref original_ident) = original_ident {
-                            result.push(ParserToken::Label(original_ident.clone()));
+                    if self.macros.contains_key(ident) {
+                        peeker.next();
+                        self.expand_macro(ident, &mut peeker, result)?;
+                    } else if !Self::is_opcode(ident.clone()) {
+                        return Err(ParserError::expected_instruction(self.line));
+                    } else {
+                        // Oh it is an opcode after the label - consume it
+                        let mut opcode = self.consume_opcode(&mut peeker, ident.clone())?;
+                        result.append(&mut opcode);
+                    }
+                } else if let &LexerToken::Assignment = next {
+                    // Its a constant - lets store its value in the symbol table
+                    peeker.next(); // Jump the assignment operator
+                    self.consume_constant(&mut peeker, ident.clone())?;
+                } else if let &LexerToken::Period = next {
+                    // The other way to define a constant: NAME .EQU value
+                    peeker.next();
+                    match peeker.next() {
+                        Some(&LexerToken::Ident(ref directive)) if directive.to_uppercase() == "EQU" => {
+                            self.consume_constant(&mut peeker, ident.clone())?;
                         }
+                        _ => return Err(ParserError::unknown_identifier(self.line)),
+                    }
+                }
+            }
+        } else if let &LexerToken::Period = next {
+            // Its a directive? Lets make sure:
+            peeker.next();
+            if let None = peeker.peek() {
+                return Err(ParserError::unexpected_eol(self.line));
+            }
 
-                        if !Self::is_opcode(ident.clone()) {
-                            return Err(ParserError::expected_instruction(self.line));
-                        } else {
-                            // Oh it is an opcode after the label - consume it
-                            let mut opcode = self.consume_opcode(&mut peeker, ident.clone())?;
-                            result.append(&mut opcode);
+            let next = *peeker.peek().unwrap();
+            if let &LexerToken::Ident(ref directive) = next {
+                // Lets check if its a valid directive:
+                let directive = directive.to_uppercase();
+                match &directive[..] {
+                    "ORG" => {
+                        result.push(self.consume_org_directive(&mut peeker)?);
+                    }
+                    "BYTE" | "TEXT" | "ASCII" => {
+                        result.push(self.consume_byte_directive(&mut peeker, false)?);
+                    }
+                    "ASCIIZ" => {
+                        result.push(self.consume_byte_directive(&mut peeker, true)?);
+                    }
+                    "WORD" => {
+                        result.push(ParserToken::Words(self.consume_word_directive(&mut peeker)?));
+                    }
+                    "DBYTE" => {
+                        result.push(ParserToken::DoubleBytes(self.consume_word_directive(&mut peeker)?));
+                    }
+                    "FILL" | "RES" => {
+                        result.push(self.consume_fill_directive(&mut peeker)?);
+                    }
+                    "ALIGN" => {
+                        result.push(self.consume_align_directive(&mut peeker)?);
+                    }
+                    "MACRO" => {
+                        self.begin_macro(&mut peeker)?;
+                    }
+                    "INCLUDE" => {
+                        self.consume_include_directive(&mut peeker, result)?;
+                    }
+                    "INCBIN" => {
+                        result.push(self.consume_incbin_directive(&mut peeker)?);
+                    }
+                    "PROC" | "SCOPE" => {
+                        let mut tokens = self.consume_scope_directive(&mut peeker, directive == "PROC")?;
+                        result.append(&mut tokens);
+                    }
+                    "ENDPROC" | "ENDSCOPE" => {
+                        peeker.next();
+                        if peeker.peek().is_some() {
+                            return Err(ParserError::unexpected_token(self.line));
                         }
-                    } else if let &LexerToken::Assignment = next {
-                        // Its a variable assignment - lets store the variable in the symbol table
-                        peeker.next(); // Jump the assignment operator
-                        if let None = peeker.peek() {
-                            return Err(ParserError::unexpected_eol(self.line));
+                        match self.scopes.last() {
+                            Some(scope) if scope.procedure == (directive == "ENDPROC") => (),
+                            _ => return Err(ParserError::unexpected_end_of_scope(&directive, self.line)),
                         }
+                        self.scopes.pop();
+                        result.push(ParserToken::Scope(self.scope_name()));
+                    }
+                    "ENDMACRO" => return Err(ParserError::unexpected_endmacro(self.line)),
+                    _ => return Err(ParserError::unknown_identifier(self.line)),
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The token for a label defined in the source, named after the scope
+    /// it is in. Cheap local labels are also named after the global label
+    /// before them, and global labels outside of macros start a new scope
+    /// for them.
+    fn label(&mut self, name: &str) -> ParserToken {
+        if name.starts_with('@') {
+            return ParserToken::Label(self.qualify(&self.local_name(name)));
+        }
+        if self.macro_depth == 0 {
+            self.global_label = name.to_string();
+        }
+
+        ParserToken::Label(self.qualify(name))
+    }
+
+    /// The name of a cheap local label, e.g. `CLEAR@loop` for `@loop`
+    /// after `CLEAR`
+    fn local_name(&self, name: &str) -> String {
+        format!("{}{}", self.global_label, name)
+    }
+
+    /// The full name of the current scope, e.g. `game::player`
+    fn scope_name(&self) -> String {
+        self.scopes.iter().map(|scope| &scope.name[..]).collect::<Vec<_>>().join("::")
+    }
+
+    /// The full name of something defined in the current scope
+    fn qualify(&self, name: &str) -> String {
+        if self.scopes.is_empty() {
+            name.to_string()
+        } else {
+            format!("{}::{}", self.scope_name(), name)
+        }
+    }
+
+    /// The constant a name refers to from the current scope
+    fn constant(&self, name: &str) -> Option<&Expression> {
+        scoped_names(&self.scope_name(), name).iter().filter_map(|name| self.constants.get(name)).next()
+    }
+
+    /// A label at the start of a line that can have an instruction after
+    /// it: a name and a colon, a cheap local label written `.name`, or a
+    /// lone colon for an anonymous label. Gives the label and the number of
+    /// tokens it took up.
+    fn line_label(&mut self, line: &[LexerToken]) -> Option<(ParserToken, usize)> {
+        match (line.first(), line.get(1), line.get(2)) {
+            (Some(LexerToken::Colon), _, _) => {
+                self.anonymous += 1;
+                Some((ParserToken::Label(format!(":{}", self.anonymous)), 1))
+            }
+            (Some(LexerToken::Ident(name)), Some(LexerToken::Colon), _) => {
+                if Self::is_opcode(name.clone()) || self.macros.contains_key(name) {
+                    return None;
+                }
+                Some((self.label(name), 2))
+            }
+            (Some(LexerToken::Period), Some(LexerToken::Ident(name)), next) => {
+                if DIRECTIVES.contains(&&name.to_uppercase()[..]) {
+                    return None;
+                }
+                let length = match next {
+                    Some(LexerToken::Colon) => 3,
+                    None => 2,
+                    Some(LexerToken::Ident(next)) if Self::is_opcode(next.clone()) || self.macros.contains_key(next) => 2,
+                    Some(_) => return None,
+                };
+                Some((self.label(&format!("@{}", name)), length))
+            }
+            _ => None,
+        }
+    }
+
+    /// Refers to the `count`th anonymous label before (`-`) or after (`+`)
+    /// the current line
+    fn anonymous_reference(&mut self, direction: &LexerToken, count: u32) -> Result<Expression, ParserError> {
+        let target = if *direction == LexerToken::Minus {
+            if count > self.anonymous {
+                return Err(ParserError::no_anonymous_label("before", self.line));
+            }
+            self.anonymous + 1 - count
+        } else {
+            self.forward_references.push((self.anonymous + count, self.line));
+            self.anonymous + count
+        };
+
+        Ok(Expression::Symbol(format!(":{}", target)))
+    }
+
+    /// Whether lines are being assembled, rather than skipped by a
+    /// conditional
+    fn assembling(&self) -> bool {
+        self.conditionals.last().map_or(true, |conditional| conditional.active)
+    }
+
+    /// The directive a line starts with, if it is one of the conditionals
+    fn conditional_directive(line: &[LexerToken]) -> Option<String> {
+        let directive = match (line.first(), line.get(1)) {
+            (Some(LexerToken::Period), Some(LexerToken::Ident(directive))) => directive.to_uppercase(),
+            _ => return None,
+        };
+
+        match &directive[..] {
+            "IF" | "IFDEF" | "IFNDEF" | "ELSEIF" | "ELSE" | "ENDIF" => Some(directive),
+            _ => None,
+        }
+    }
+
+    /// Follows `.IF`, `.IFDEF`, `.IFNDEF`, `.ELSEIF`, `.ELSE` and `.ENDIF`.
+    /// Conditions are only worked out when the branch they start could be
+    /// chosen, so skipped lines can't cause errors.
+    fn consume_conditional(&mut self, directive: &str, args: &[LexerToken]) -> Result<(), ParserError> {
+        match directive {
+            "IF" | "IFDEF" | "IFNDEF" => {
+                let active = self.assembling() && self.condition(directive, args)?;
+                self.conditionals.push(Conditional {
+                    active,
+                    // Inside a skipped block, none of the branches are chosen
+                    taken: active || !self.assembling(),
+                    in_else: false,
+                    line: self.line,
+                });
+            }
+            "ELSEIF" | "ELSE" => {
+                let (taken, in_else) = match self.conditionals.last() {
+                    Some(conditional) => (conditional.taken, conditional.in_else),
+                    None => return Err(ParserError::unexpected_conditional(directive, self.line)),
+                };
+                if in_else {
+                    return Err(ParserError::unexpected_conditional(directive, self.line));
+                }
+
+                let active = !taken && (directive == "ELSE" || self.condition("IF", args)?);
+                let conditional = self.conditionals.last_mut().unwrap();
+                conditional.active = active;
+                conditional.taken = taken || active;
+                conditional.in_else = directive == "ELSE";
+            }
+            _ => {
+                if self.conditionals.pop().is_none() {
+                    return Err(ParserError::unexpected_conditional(directive, self.line));
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Whether the condition after `.IF`, `.IFDEF` or `.IFNDEF` holds
+    fn condition(&mut self, directive: &str, args: &[LexerToken]) -> Result<bool, ParserError> {
+        Self::check_lexed(args)?;
+        let mut peeker = args.iter().peekable();
+
+        let holds = if directive == "IF" {
+            let expression = self.consume_expression(&mut peeker)?;
+            match expression.evaluate(&|_| None) {
+                Ok(value) => value != 0,
+                Err(EvaluationError::UnknownSymbol(name)) => {
+                    return Err(ParserError::unknown_condition(&name, self.line))
+                }
+                Err(EvaluationError::DivisionByZero) => return Err(ParserError::division_by_zero(self.line)),
+            }
+        } else {
+            let defined = match peeker.next() {
+                Some(LexerToken::Ident(name)) => self.constant(name).is_some(),
+                Some(_) => return Err(ParserError::unexpected_token(self.line)),
+                None => return Err(ParserError::unexpected_eol(self.line)),
+            };
+            defined == (directive == "IFDEF")
+        };
+
+        if peeker.next().is_some() {
+            return Err(ParserError::expected_eol(self.line));
+        }
+
+        Ok(holds)
+    }
+
+    /// Starts recording a macro definition: `.MACRO NAME ARG1, ARG2`
+    fn begin_macro<'a, I>(&mut self, peeker: &mut Peekable<I>) -> Result<(), ParserError>
+        where I: Iterator<Item = &'a LexerToken>
+    {
+        // Jump over the directive
+        peeker.next();
+
+        let name = match peeker.next() {
+            Some(LexerToken::Ident(name)) if !Self::is_opcode(name.clone()) => name.clone(),
+            Some(_) => return Err(ParserError::unexpected_token(self.line)),
+            None => return Err(ParserError::unexpected_eol(self.line)),
+        };
+
+        let mut params = Vec::new();
+        while let Some(token) = peeker.next() {
+            match *token {
+                LexerToken::Ident(ref param) => params.push(param.clone()),
+                _ => return Err(ParserError::unexpected_token(self.line)),
+            }
+
+            match peeker.next() {
+                Some(LexerToken::Comma) | None => (),
+                Some(_) => return Err(ParserError::unexpected_token(self.line)),
+            }
+        }
+
+        self.defining = Some(Macro {
+            name,
+            params,
+            lines: Vec::new(),
+            line: self.line,
+            file: self.files.last().cloned(),
+        });
+
+        Ok(())
+    }
+
+    fn record_macro_line(&mut self, line: &[LexerToken]) -> Result<(), ParserError> {
+        let directive = match (line.first(), line.get(1)) {
+            (Some(LexerToken::Period), Some(LexerToken::Ident(directive))) => directive.to_uppercase(),
+            _ => String::new(),
+        };
 
-                        let next = *peeker.peek().unwrap();
-                        if let &LexerToken::Address(ref address) = next {
-                            self.symbol_table
-                                .insert(ident.clone(),
-                                        Variable(LexerToken::Address(address.clone())));
-                        } else if let &LexerToken::Ident(ref var_ident) = next {
-                            // Its another variable
-                            self.symbol_table
-                                .insert(ident.clone(),
-                                        Variable(LexerToken::Ident(var_ident.clone())));
+        match &directive[..] {
+            "ENDMACRO" => {
+                let definition = self.defining.take().unwrap();
+                self.macros.insert(definition.name.clone(), definition);
+            }
+            "MACRO" => return Err(ParserError::nested_macro_definition(self.line)),
+            _ => self.defining.as_mut().unwrap().lines.push((self.line, line.to_vec())),
+        }
+
+        Ok(())
+    }
+
+    /// Parses the lines of a macro in place of a call to it. The labels the
+    /// macro defines are renamed for each expansion, so a macro can be
+    /// called more than once.
+    fn expand_macro<'a, I>(&mut self,
+                           name: &str,
+                           peeker: &mut Peekable<I>,
+                           result: &mut Vec<ParserToken>)
+                           -> Result<(), ParserError>
+        where I: Iterator<Item = &'a LexerToken>
+    {
+        let definition = self.macros[name].clone();
+        let args = Self::split_macro_args(peeker);
+
+        if args.len() != definition.params.len() {
+            return Err(ParserError::wrong_macro_arg_count(name, definition.params.len(), args.len(), self.line));
+        }
+        if self.macro_depth >= MAX_MACRO_DEPTH {
+            return Err(ParserError::macro_too_deep(name, self.line));
+        }
+
+        self.expansions += 1;
+        let suffix = format!("#{}", self.expansions);
+        let locals: Vec<(bool, &String)> = definition.lines
+            .iter()
+            .filter_map(|(_, tokens)| self.defined_label(tokens, &definition.params))
+            .collect();
+
+        let call_line = self.line;
+        // Lines from a macro in another file are numbered in that file
+        let other_file = match definition.file {
+            Some(ref file) if Some(file) != self.files.last() => Some(file),
+            _ => None,
+        };
+        self.contexts.push(Context::Macro(name.to_string(), call_line));
+        if let Some(file) = other_file {
+            self.contexts.push(Context::File(file.clone()));
+        }
+        self.macro_depth += 1;
+        // A macro's conditionals have to be closed inside it
+        let outer_conditionals = std::mem::take(&mut self.conditionals);
+
+        let mut outcome = Ok(());
+        for &(line, ref tokens) in &definition.lines {
+            let mut expanded = Vec::new();
+            let mut after_period = false;
+            for token in tokens {
+                match *token {
+                    LexerToken::Ident(ref ident) => {
+                        if let Some(index) = definition.params.iter().position(|param| param == ident) {
+                            expanded.extend(args[index].iter().cloned());
+                        } else if locals.contains(&(after_period, ident)) {
+                            expanded.push(LexerToken::Ident(format!("{}{}", ident, suffix)));
+                        } else {
+                            expanded.push(token.clone());
                         }
                     }
+                    _ => expanded.push(token.clone()),
+                }
+                after_period = *token == LexerToken::Period;
+            }
+
+            self.line = line;
+            outcome = self.parse_line(&expanded, result);
+            if outcome.is_err() {
+                break;
+            }
+        }
+        if let (Ok(()), Some(conditional)) = (&outcome, self.conditionals.last()) {
+            outcome = Err(ParserError::unterminated_conditional(conditional.line));
+        }
+
+        self.macro_depth -= 1;
+        self.line = call_line;
+        self.conditionals = outer_conditionals;
+        self.contexts.truncate(self.contexts.len() - if other_file.is_some() { 2 } else { 1 });
+
+        outcome.map_err(|error| {
+            let error = match other_file {
+                Some(file) => error.in_file(file),
+                None => error,
+            };
+            error.in_macro(name, call_line)
+        })
+    }
+
+    /// The arguments to a macro call, split at the commas that aren't
+    /// inside parentheses
+    fn split_macro_args<'a, I>(peeker: &mut Peekable<I>) -> Vec<Vec<LexerToken>>
+        where I: Iterator<Item = &'a LexerToken>
+    {
+        let mut args = Vec::new();
+        let mut arg = Vec::new();
+        let mut depth = 0;
+
+        for token in peeker {
+            match *token {
+                LexerToken::Comma if depth == 0 => {
+                    args.push(arg);
+                    arg = Vec::new();
+                    continue;
+                }
+                LexerToken::OpenParenthesis => depth += 1,
+                LexerToken::CloseParenthesis => depth -= 1,
+                _ => (),
+            }
+            arg.push(token.clone());
+        }
+
+        if !arg.is_empty() || !args.is_empty() {
+            args.push(arg);
+        }
+
+        args
+    }
+
+    /// Where the current line came from, in the same words as parse errors,
+    /// e.g. `Line 3, in macro 'WAIT' called from line 9`
+    fn location(&self) -> String {
+        let mut location = ParserError::from(format!("Line {}", self.line));
+        for context in self.contexts.iter().rev() {
+            location = match *context {
+                Context::File(ref path) => location.in_file(path),
+                Context::IncludedFrom(line) => location.included_from(line),
+                Context::Macro(ref name, call_line) => location.in_macro(name, call_line),
+            };
+        }
+
+        location.message
+    }
+
+    /// Reports the error the lexer found in a line, if it found one. These
+    /// are left to the parser so that lines skipped by a conditional can
+    /// hold anything.
+    fn check_lexed(line: &[LexerToken]) -> Result<(), ParserError> {
+        match line.last() {
+            Some(LexerToken::Invalid(message)) => Err(ParserError::from(message.clone())),
+            _ => Ok(()),
+        }
+    }
+
+    /// The label a line defines, if it starts with one, and whether it is
+    /// a cheap local label written `.name`
+    fn defined_label<'b>(&self, tokens: &'b [LexerToken], params: &[String]) -> Option<(bool, &'b String)> {
+        let (cheap, label, next) = match (tokens.first(), tokens.get(1)) {
+            (Some(LexerToken::Period), Some(LexerToken::Ident(ident))) => {
+                if DIRECTIVES.contains(&&ident.to_uppercase()[..]) {
+                    return None;
+                }
+                (true, ident, tokens.get(2))
+            }
+            (Some(LexerToken::Ident(ident)), next) => (false, ident, next),
+            _ => return None,
+        };
+        if Self::is_opcode(label.clone()) || self.macros.contains_key(label) || params.contains(label) {
+            return None;
+        }
+
+        match next {
+            None | Some(LexerToken::Colon) | Some(LexerToken::Ident(_)) => Some((cheap, label)),
+            _ => None,
+        }
+    }
+
+    fn is_opcode<S>(mnemonic: S) -> bool
+        where S: Into<String>
+    {
+        if let Some(opcode) = OpCode::from_mnemonic(mnemonic) {
+            true
+        } else {
+            false
+        }
+    }
+
+    fn consume_opcode<'a, I, S>(&mut self,
+                                mut peeker: &mut Peekable<I>,
+                                ident: S)
+                                -> Result<Vec<ParserToken>, ParserError>
+        where I: Iterator<Item = &'a LexerToken> + Clone,
+              S: Into<String> + std::fmt::Display + Clone
+    {
+        // Jump over the opcode
+        peeker.next();
+
+        // If there is nothing else after this opcode.. lets check if there is
+        // a matching opcode with an implied addressing mode
+        if let None = peeker.peek() {
+            if let Some(opcode) =
+                   OpCode::from_mnemonic_and_addressing_mode(ident.clone(), AddressingMode::Implied) {
+                return Ok(vec![ParserToken::OpCode(opcode)]);
+            } else if let Some(opcode) =
+                          OpCode::from_mnemonic_and_addressing_mode(ident.clone(),
+                                                                    AddressingMode::Accumulator) {
+                return Ok(vec![ParserToken::OpCode(opcode)]);
+            } else {
+                return Err(ParserError::invalid_opcode_addressing_mode_combination(self.line));
+            }
+        }
+
+        let operand = self.consume_operand(peeker)?;
+        if peeker.peek().is_some() {
+            return Err(ParserError::unexpected_token(self.line));
+        }
+
+        let ident = ident.into();
+        let value = operand.expression.constant_value();
+
+        // Branches take their target as an operand and work out the
+        // offset once the address of the branch is known
+        if operand.syntax == OperandSyntax::Direct {
+            if let Some(opcode) =
+                   OpCode::from_mnemonic_and_addressing_mode(&ident[..], AddressingMode::Relative) {
+                return Ok(vec![ParserToken::OpCode(opcode),
+                               ParserToken::Operand(operand.expression)]);
+            }
+        }
+
+        // A lone literal address, or one with an a: or z: prefix, keeps the
+        // width it was written with. Anything else is zero page if its value
+        // fits and the opcode has a zero page form, and absolute otherwise.
+        // Values that refer to labels try zero page first, and are sized
+        // once the labels are known.
+        let zero_page = match operand.digits {
+            Some(digits) => digits <= 2,
+            None => value.map_or(true, |value| (0..=0xFF).contains(&value)),
+        };
+
+        let addressing_mode = match operand.syntax {
+            OperandSyntax::Immediate => AddressingMode::Immediate,
+            OperandSyntax::Direct if zero_page => AddressingMode::ZeroPage,
+            OperandSyntax::Direct => AddressingMode::Absolute,
+            OperandSyntax::DirectX if zero_page => AddressingMode::ZeroPageX,
+            OperandSyntax::DirectX => AddressingMode::AbsoluteX,
+            OperandSyntax::DirectY if zero_page => AddressingMode::ZeroPageY,
+            OperandSyntax::DirectY => AddressingMode::AbsoluteY,
+            OperandSyntax::Indirect => AddressingMode::Indirect,
+            OperandSyntax::IndirectX => AddressingMode::IndirectX,
+            OperandSyntax::IndirectY => AddressingMode::IndirectY,
+        };
+
+        let opcode = OpCode::from_mnemonic_and_addressing_mode(&ident[..], addressing_mode)
+            .or_else(|| match (operand.digits, Self::absolute_mode(addressing_mode)) {
+                (None, Some(absolute)) => OpCode::from_mnemonic_and_addressing_mode(&ident[..], absolute),
+                _ => None,
+            });
+        let opcode = match opcode {
+            Some(opcode) => opcode,
+            None => return Err(ParserError::invalid_opcode_addressing_mode_combination(self.line)),
+        };
+        let addressing_mode = opcode.mode;
+
+        if value.is_none() && operand.digits.is_none() {
+            let absolute = Self::absolute_mode(addressing_mode)
+                .and_then(|absolute| OpCode::from_mnemonic_and_addressing_mode(&ident[..], absolute));
+            if let Some(absolute) = absolute {
+                return Ok(vec![ParserToken::ZeroPageOrAbsolute(opcode, absolute, operand.expression)]);
+            }
+        }
+
+        // The indirect jump needs a full 16-bit address
+        if addressing_mode == AddressingMode::Indirect && operand.digits.is_some_and(|digits| digits != 4) {
+            return Err(ParserError::address_out_of_bounds(self.line));
+        }
+
+        let mut final_vec = vec![ParserToken::OpCode(opcode)];
+        match value {
+            Some(value) if opcode.length == 3 => {
+                if !(0..=0xFFFF).contains(&value) {
+                    return Err(ParserError::address_out_of_bounds(self.line));
                 }
-            } else if let &LexerToken::Period = next {
-                // Its a directive? Lets make sure:
+                final_vec.push(ParserToken::RawByte(value as u8));
+                final_vec.push(ParserToken::RawByte((value >> 8) as u8));
+            }
+            Some(value) if addressing_mode == AddressingMode::Immediate => {
+                if !(-0x80..=0xFF).contains(&value) {
+                    return Err(ParserError::cannot_parse_immediate(self.line));
+                }
+                final_vec.push(ParserToken::RawByte(value as u8));
+            }
+            Some(value) => {
+                if !(0..=0xFF).contains(&value) {
+                    return Err(ParserError::address_out_of_bounds(self.line));
+                }
+                final_vec.push(ParserToken::RawByte(value as u8));
+            }
+            // Refers to labels, so it is worked out once they are known
+            None => final_vec.push(ParserToken::Operand(operand.expression)),
+        }
+
+        Ok(final_vec)
+    }
+
+    /// The absolute addressing mode a zero page mode falls back to
+    fn absolute_mode(mode: AddressingMode) -> Option<AddressingMode> {
+        match mode {
+            AddressingMode::ZeroPage => Some(AddressingMode::Absolute),
+            AddressingMode::ZeroPageX => Some(AddressingMode::AbsoluteX),
+            AddressingMode::ZeroPageY => Some(AddressingMode::AbsoluteY),
+            _ => None,
+        }
+    }
+
+    /// Consumes everything after a mnemonic, working out which addressing
+    /// mode syntax it is written in
+    fn consume_operand<'a, I>(&mut self, peeker: &mut Peekable<I>) -> Result<Operand, ParserError>
+        where I: Iterator<Item = &'a LexerToken> + Clone
+    {
+        match **peeker.peek().unwrap() {
+            LexerToken::Immediate(..) => {
+                let expression = self.consume_expression(peeker)?;
+                Ok(Operand::new(OperandSyntax::Immediate, expression, None))
+            }
+            LexerToken::Hash => {
                 peeker.next();
-                if let None = peeker.peek() {
-                    return Err(ParserError::unexpected_eol(self.line));
+                let expression = self.consume_expression(peeker)?;
+                Ok(Operand::new(OperandSyntax::Immediate, expression, None))
+            }
+            LexerToken::OpenParenthesis => {
+                peeker.next(); // skip the opening paren
+                let digits = self.literal_digits(peeker);
+                let inner = self.consume_expression(peeker)?;
+
+                match peeker.next() {
+                    Some(&LexerToken::Comma) => {
+                        self.consume_register(peeker, "X")?;
+                        return match peeker.next() {
+                            Some(&LexerToken::CloseParenthesis) => {
+                                Ok(Operand::new(OperandSyntax::IndirectX, inner, digits))
+                            }
+                            Some(_) => Err(ParserError::unexpected_token(self.line)),
+                            None => Err(ParserError::unexpected_eol(self.line)),
+                        };
+                    }
+                    Some(&LexerToken::CloseParenthesis) => (),
+                    Some(_) => return Err(ParserError::unexpected_token(self.line)),
+                    None => return Err(ParserError::unexpected_eol(self.line)),
                 }
 
-                let next = *peeker.peek().unwrap();
-                if let &LexerToken::Ident(ref directive) = next {
-                    // Lets check if its a valid directive:
-                    let directive = directive.to_uppercase();
-                    match &directive[..] {
-                        "ORG" => {
-                            result.push(self.consume_org_directive(&mut peeker)?);
-                        }
-                        "BYTE" => {
-                            result.push(self.consume_byte_directive(&mut peeker)?);
-                        }
-                        _ => return Err(ParserError::unknown_identifier(self.line)),
+                match peeker.peek() {
+                    None => Ok(Operand::new(OperandSyntax::Indirect, inner, digits)),
+                    Some(LexerToken::Comma) => {
+                        peeker.next();
+                        self.consume_register(peeker, "Y")?;
+                        Ok(Operand::new(OperandSyntax::IndirectY, inner, digits))
+                    }
+                    Some(_) => {
+                        // The parentheses were only grouping the start of an
+                        // expression, as in LDA (BASE+1)*2,X
+                        let expression = self.consume_binary_expression(peeker, inner, 0)?;
+                        self.consume_index(peeker, expression, None)
                     }
                 }
             }
+            _ => {
+                let digits = self.width_prefix(peeker).or_else(|| self.literal_digits(peeker));
+                let expression = self.consume_expression(peeker)?;
+                self.consume_index(peeker, expression, digits)
+            }
+        }
+    }
+
+    /// Consumes an `a:` or `z:` prefix, which asks for absolute or zero
+    /// page addressing whatever the operand's value. Gives the digits of
+    /// an address that wide.
+    fn width_prefix<'a, I>(&self, peeker: &mut Peekable<I>) -> Option<usize>
+        where I: Iterator<Item = &'a LexerToken> + Clone
+    {
+        let mut ahead = peeker.clone();
+        let digits = match (ahead.next(), ahead.next()) {
+            (Some(LexerToken::Ident(prefix)), Some(LexerToken::Colon)) => {
+                match &prefix.to_uppercase()[..] {
+                    "A" => 4,
+                    "Z" => 2,
+                    _ => return None,
+                }
+            }
+            _ => return None,
+        };
+
+        peeker.next();
+        peeker.next();
+        Some(digits)
+    }
+
+    /// Consumes the optional `,X` or `,Y` after a direct operand
+    fn consume_index<'a, I>(&mut self,
+                            peeker: &mut Peekable<I>,
+                            expression: Expression,
+                            digits: Option<usize>)
+                            -> Result<Operand, ParserError>
+        where I: Iterator<Item = &'a LexerToken>
+    {
+        match peeker.peek() {
+            None => Ok(Operand::new(OperandSyntax::Direct, expression, digits)),
+            Some(LexerToken::Comma) => {
+                peeker.next();
+                let syntax = match peeker.next() {
+                    Some(LexerToken::Ident(register)) if register.to_uppercase() == "X" => {
+                        OperandSyntax::DirectX
+                    }
+                    Some(LexerToken::Ident(register)) if register.to_uppercase() == "Y" => {
+                        OperandSyntax::DirectY
+                    }
+                    Some(_) => return Err(ParserError::unexpected_token(self.line)),
+                    None => return Err(ParserError::unexpected_eol(self.line)),
+                };
+                Ok(Operand::new(syntax, expression, digits))
+            }
+            Some(_) => Err(ParserError::unexpected_token(self.line)),
+        }
+    }
+
+    fn consume_register<'a, I>(&mut self,
+                               peeker: &mut Peekable<I>,
+                               register: &str)
+                               -> Result<(), ParserError>
+        where I: Iterator<Item = &'a LexerToken>
+    {
+        match peeker.next() {
+            Some(LexerToken::Ident(ident)) if ident.to_uppercase() == register => Ok(()),
+            Some(_) => Err(ParserError::unexpected_token(self.line)),
+            None => Err(ParserError::unexpected_eol(self.line)),
+        }
+    }
+
+    /// The number of digits a lone literal address was written with, if the
+    /// expression about to be consumed is only that
+    fn literal_digits<'a, I>(&self, peeker: &mut Peekable<I>) -> Option<usize>
+        where I: Iterator<Item = &'a LexerToken> + Clone
+    {
+        let mut ahead = peeker.clone();
+        let digits = match ahead.next() {
+            Some(LexerToken::Address(address)) => address.len(),
+            _ => return None,
+        };
+
+        match ahead.peek() {
+            None | Some(LexerToken::Comma) | Some(LexerToken::CloseParenthesis) => Some(digits),
+            Some(_) => None,
+        }
+    }
+
+    /// Consumes an expression, stopping at the first token that can't
+    /// continue it
+    fn consume_expression<'a, I>(&mut self, peeker: &mut Peekable<I>) -> Result<Expression, ParserError>
+        where I: Iterator<Item = &'a LexerToken>
+    {
+        // Low and high byte apply to everything after them
+        let operator = match peeker.peek() {
+            Some(LexerToken::LessThan) => Some(UnaryOperator::LowByte),
+            Some(LexerToken::GreaterThan) => Some(UnaryOperator::HighByte),
+            _ => None,
+        };
+        if let Some(operator) = operator {
+            peeker.next();
+            let operand = self.consume_expression(peeker)?;
+            return Ok(Expression::Unary(operator, Box::new(operand)));
         }
 
-        Ok(result)
+        let left = self.consume_unary_expression(peeker)?;
+        self.consume_binary_expression(peeker, left, 0)
     }
 
-    fn is_opcode<S>(mnemonic: S) -> bool
-        where S: Into<String>
+    /// Consumes binary operators binding tighter than `min_precedence`,
+    /// with `left` as the left hand side of the first
+    fn consume_binary_expression<'a, I>(&mut self,
+                                        peeker: &mut Peekable<I>,
+                                        left: Expression,
+                                        min_precedence: u8)
+                                        -> Result<Expression, ParserError>
+        where I: Iterator<Item = &'a LexerToken>
     {
-        if let Some(opcode) = OpCode::from_mnemonic(mnemonic) {
-            true
-        } else {
-            false
+        let mut left = left;
+        loop {
+            let operator = match peeker.peek().and_then(|token| Self::binary_operator(token)) {
+                Some(operator) if operator.precedence() > min_precedence => operator,
+                _ => return Ok(left),
+            };
+            peeker.next();
+
+            // Anything binding tighter belongs to the right hand side
+            let right = self.consume_unary_expression(peeker)?;
+            let right = self.consume_binary_expression(peeker, right, operator.precedence())?;
+
+            left = Expression::Binary(operator, Box::new(left), Box::new(right));
         }
     }
 
-    fn consume_opcode<'a, I, S>(&mut self,
-                                mut peeker: &mut Peekable<I>,
-                                ident: S)
-                                -> Result<Vec<ParserToken>, ParserError>
-        where I: Iterator<Item = &'a LexerToken>,
-              S: Into<String> + std::fmt::Display + Clone
+    fn consume_unary_expression<'a, I>(&mut self, peeker: &mut Peekable<I>) -> Result<Expression, ParserError>
+        where I: Iterator<Item = &'a LexerToken>
     {
-        // Jump over the opcode
-        peeker.next();
+        let next = match peeker.next() {
+            Some(next) => next,
+            None => return Err(ParserError::unexpected_eol(self.line)),
+        };
 
-        // If there is nothing else after this opcode.. lets check if there is
-        // a matching opcode with an implied addressing mode
-        if let None = peeker.peek() {
-            if let Some(opcode) =
-                   OpCode::from_mnemonic_and_addressing_mode(ident.clone(), AddressingMode::Implied) {
-                return Ok(vec![ParserToken::OpCode(opcode)]);
-            } else if let Some(opcode) =
-                          OpCode::from_mnemonic_and_addressing_mode(ident.clone(),
-                                                                    AddressingMode::Accumulator) {
-                return Ok(vec![ParserToken::OpCode(opcode)]);
-            } else {
-                return Err(ParserError::invalid_opcode_addressing_mode_combination(self.line));
-            }
-        } else {
-            // Check the next token, is it an address or identifier?
-            let mut next = (*peeker.peek().unwrap()).clone();
-            next = if let LexerToken::Ident(ref label) = next {
-                // Lets see if its a variable?
-                if let Ok(variable) = self.get_variable_value(label.clone()) {
-                    variable.clone().0
-                } else {
-                    // takes care of this later
-                    let ident = ident.clone().into().to_uppercase();
-                    let addressing_mode = if ident == "JMP" || ident == "JSR" {
-                        AddressingMode::Absolute
-                    } else {
-                        AddressingMode::Relative
-                    };
+        match *next {
+            LexerToken::Minus | LexerToken::Plus => {
+                let mut count = 1;
+                while peeker.peek() == Some(&next) {
+                    peeker.next();
+                    count += 1;
+                }
 
-                    if let Some(opcode) =
-                           OpCode::from_mnemonic_and_addressing_mode(ident.clone(), addressing_mode) {
-                        return Ok(vec![ParserToken::OpCode(opcode),
-                                       ParserToken::LabelArg(label.clone())]);
-                    } else {
-                        return Err(ParserError::invalid_opcode_addressing_mode_combination(self.line));
+                match peeker.peek() {
+                    // On their own, -'s and +'s refer to anonymous labels
+                    None | Some(LexerToken::Comma) | Some(LexerToken::CloseParenthesis) => {
+                        self.anonymous_reference(next, count)
                     }
-                }
-            } else {
-                next.clone()
-            };
-            if let LexerToken::Address(ref address) = next {
-                // Its an address. What sort of address?
-                if address.len() <= 4 {
-                    // Its zero-page or absolute.. lets try and convert it to a raw byte
-                    let addressing_mode = if address.len() <= 2 {
-                        // Its a 1 byte address
-                        AddressingMode::ZeroPage
-                    } else {
-                        AddressingMode::Absolute
-                    };
-                    let bytes = self.parse_address_bytes(address)?;
-                    // consume the address and peek what is next:
-                    peeker.next();
-                    if let None = peeker.peek() {
-                        // Nothing else.. find an opcode with this ident and addressing mode
-                        if let Some(opcode) =
-                               OpCode::from_mnemonic_and_addressing_mode(ident, addressing_mode) {
-                            // We found one..
-                            let mut final_vec = vec![ParserToken::OpCode(opcode)];
-                            // Push the address bytes into the result
-                            for b in bytes {
-                                final_vec.push(ParserToken::RawByte(b));
-                            }
-                            return Ok(final_vec);
-                        } else {
-                            return Err(ParserError::invalid_opcode_addressing_mode_combination(self.line));
+                    Some(_) if *next == LexerToken::Plus => Err(ParserError::unexpected_token(self.line)),
+                    Some(_) => {
+                        let mut operand = self.consume_unary_expression(peeker)?;
+                        for _ in 0..count {
+                            operand = Expression::Unary(UnaryOperator::Negate, Box::new(operand));
                         }
+                        Ok(operand)
                     }
+                }
+            }
+            LexerToken::Colon => {
+                let direction = match peeker.next() {
+                    Some(direction @ &LexerToken::Minus) | Some(direction @ &LexerToken::Plus) => direction,
+                    Some(_) => return Err(ParserError::unexpected_token(self.line)),
+                    None => return Err(ParserError::unexpected_eol(self.line)),
+                };
+                let mut count = 1;
+                while peeker.peek() == Some(&direction) {
+                    peeker.next();
+                    count += 1;
+                }
 
-                    // There is something after this address - if its
-                    // a comma, then we're peachy. If its something else.. Thats
-                    // an error.
-                    let next = *peeker.peek().unwrap();
-                    if let &LexerToken::Comma = next {
-                        // Yes, its a comma. Consume it and check what is next
-                        peeker.next();
-                        // If theres nothing after the comma thats an error
-                        if let None = peeker.peek() {
-                            return Err(ParserError::unexpected_eol(self.line));
-                        }
-
-                        let next = *peeker.peek().unwrap();
-                        if let &LexerToken::Ident(ref register) = next {
-                            let register = register.to_uppercase();
-                            if register != "X" && register != "Y" {
-                                return Err(ParserError::unexpected_token(self.line));
-                            }
-                            let addressing_mode = if register == "X" {
-                                if addressing_mode == AddressingMode::ZeroPage {
-                                    AddressingMode::ZeroPageX
-                                } else {
-                                    AddressingMode::AbsoluteX
-                                }
-                            } else {
-                                if addressing_mode == AddressingMode::ZeroPage {
-                                    AddressingMode::ZeroPageY
-                                } else {
-                                    AddressingMode::AbsoluteY
-                                }
-                            };
-                            if let Some(opcode) =
-                                   OpCode::from_mnemonic_and_addressing_mode(ident, addressing_mode) {
-                                // We found one..
-                                let mut final_vec = vec![ParserToken::OpCode(opcode)];
-                                // Push the address bytes into the result
-                                for b in bytes {
-                                    final_vec.push(ParserToken::RawByte(b));
-                                }
-                                return Ok(final_vec);
-                            } else {
-                                return Err(ParserError::invalid_opcode_addressing_mode_combination(self.line));
-                            }
-                        } else {
-                            return Err(ParserError::unexpected_token(self.line));
+                self.anonymous_reference(direction, count)
+            }
+            // The address of the current instruction
+            LexerToken::Asterisk => Ok(Expression::Symbol("*".into())),
+            LexerToken::Period => {
+                match peeker.next() {
+                    Some(LexerToken::Ident(name)) => Ok(Expression::Symbol(self.local_name(&format!("@{}", name)))),
+                    Some(_) => Err(ParserError::unexpected_token(self.line)),
+                    None => Err(ParserError::unexpected_eol(self.line)),
+                }
+            }
+            LexerToken::LessThan => {
+                let operand = self.consume_unary_expression(peeker)?;
+                Ok(Expression::Unary(UnaryOperator::LowByte, Box::new(operand)))
+            }
+            LexerToken::GreaterThan => {
+                let operand = self.consume_unary_expression(peeker)?;
+                Ok(Expression::Unary(UnaryOperator::HighByte, Box::new(operand)))
+            }
+            LexerToken::OpenParenthesis => {
+                let inner = self.consume_expression(peeker)?;
+                match peeker.next() {
+                    Some(&LexerToken::CloseParenthesis) => Ok(inner),
+                    Some(_) => Err(ParserError::unexpected_token(self.line)),
+                    None => Err(ParserError::unexpected_eol(self.line)),
+                }
+            }
+            LexerToken::Address(ref address) => {
+                let value = u16::from_str_radix(address, 16)
+                    .map_err(|_| ParserError::cannot_parse_address(self.line))?;
+                Ok(Expression::Number(value as i32))
+            }
+            LexerToken::Immediate(ref value, base) => {
+                Ok(Expression::Number(self.parse_number(value, base)?))
+            }
+            LexerToken::Number(ref value) => {
+                Ok(Expression::Number(self.parse_number(value, ImmediateBase::Base10)?))
+            }
+            LexerToken::Ident(ref ident) if ident.starts_with('@') => {
+                Ok(Expression::Symbol(self.local_name(ident)))
+            }
+            LexerToken::Ident(ref ident) => {
+                let name = self.consume_qualified_name(peeker, ident.clone())?;
+                // Constants are known by now, anything else is a label
+                match self.constant(&name) {
+                    Some(expression) => Ok(expression.clone()),
+                    None => Ok(Expression::Symbol(name)),
+                }
+            }
+            LexerToken::DoubleColon => {
+                // A name in the outermost scope, whatever scope this is in
+                match peeker.next() {
+                    Some(LexerToken::Ident(ident)) => {
+                        let name = self.consume_qualified_name(peeker, format!("::{}", ident))?;
+                        match self.constant(&name) {
+                            Some(expression) => Ok(expression.clone()),
+                            None => Ok(Expression::Symbol(name)),
                         }
-                    } else {
-                        return Err(ParserError::unexpected_token(self.line));
                     }
-                    let next = *peeker.peek().unwrap();
-
-                } else {
-                    return Err(ParserError::cannot_parse_address(self.line));
+                    Some(_) => Err(ParserError::unexpected_token(self.line)),
+                    None => Err(ParserError::unexpected_eol(self.line)),
                 }
-            } else if let LexerToken::OpenParenthesis = next {
-                // We're moving into Indirect memory addressing
-                let addressing_mode = AddressingMode::Indirect;
-                peeker.next(); // skip the opening paren
+            }
+            _ => Err(ParserError::unexpected_token(self.line)),
+        }
+    }
 
-                // If we have nothing else, thats an error
-                if let None = peeker.peek() {
-                    return Err(ParserError::unexpected_eol(self.line));
+    /// Consumes the rest of a qualified name like `player::update`, after
+    /// its first part
+    fn consume_qualified_name<'a, I>(&mut self, peeker: &mut Peekable<I>, first: String) -> Result<String, ParserError>
+        where I: Iterator<Item = &'a LexerToken>
+    {
+        let mut name = first;
+        while let Some(&&LexerToken::DoubleColon) = peeker.peek() {
+            peeker.next();
+            match peeker.next() {
+                Some(LexerToken::Ident(part)) => {
+                    name.push_str("::");
+                    name.push_str(part);
                 }
+                Some(_) => return Err(ParserError::unexpected_token(self.line)),
+                None => return Err(ParserError::unexpected_eol(self.line)),
+            }
+        }
 
-                // Is the next thing an address?
-                let mut next = (*peeker.peek().unwrap()).clone();
-                next = if let LexerToken::Ident(ref label) = next {
-                    // Lets see if its a variable?
-                    if let Ok(variable) = self.get_variable_value(label.clone()) {
-                        variable.clone().0
-                    } else {
-                        next.clone()
-                    }
-                } else {
-                    next.clone()
-                };
-                if let LexerToken::Address(ref address) = next {
-                    if address.len() > 4 {
-                        return Err(ParserError::address_out_of_bounds(self.line));
-                    }
+        Ok(name)
+    }
 
-                    let bytes = self.parse_address_bytes(address)?;
+    fn binary_operator(token: &LexerToken) -> Option<BinaryOperator> {
+        match *token {
+            LexerToken::Plus => Some(BinaryOperator::Add),
+            LexerToken::Minus => Some(BinaryOperator::Subtract),
+            LexerToken::Asterisk => Some(BinaryOperator::Multiply),
+            LexerToken::Slash => Some(BinaryOperator::Divide),
+            LexerToken::Percent => Some(BinaryOperator::Modulo),
+            LexerToken::Ampersand => Some(BinaryOperator::And),
+            LexerToken::Pipe => Some(BinaryOperator::Or),
+            LexerToken::Caret => Some(BinaryOperator::Xor),
+            LexerToken::ShiftLeft => Some(BinaryOperator::ShiftLeft),
+            LexerToken::ShiftRight => Some(BinaryOperator::ShiftRight),
+            LexerToken::Assignment => Some(BinaryOperator::Equal),
+            LexerToken::NotEqual => Some(BinaryOperator::NotEqual),
+            LexerToken::LessThan => Some(BinaryOperator::Less),
+            LexerToken::GreaterThan => Some(BinaryOperator::Greater),
+            LexerToken::LessThanOrEqual => Some(BinaryOperator::LessOrEqual),
+            LexerToken::GreaterThanOrEqual => Some(BinaryOperator::GreaterOrEqual),
+            _ => None,
+        }
+    }
 
-                    // The address is the right length - lets jump over that and peek next
-                    peeker.next();
-                    if let None = peeker.peek() {
-                        return Err(ParserError::unexpected_eol(self.line));
-                    }
-                    let next = *peeker.peek().unwrap();
-                    if let &LexerToken::Comma = next {
-                        // If its a comma - lets target IndirectX
-                        peeker.next(); // skip the comma
-                        if let None = peeker.peek() {
-                            return Err(ParserError::unexpected_eol(self.line));
-                        }
+    fn parse_number(&self, value: &str, base: ImmediateBase) -> Result<i32, ParserError> {
+        let radix = match base {
+            ImmediateBase::Base10 => 10,
+            ImmediateBase::Base16 => 16,
+        };
 
-                        let next = *peeker.peek().unwrap();
-                        if let &LexerToken::Ident(ref register) = next {
-                            let register = register.to_uppercase();
-                            if register != "X" {
-                                return Err(ParserError::unexpected_token(self.line));
-                            }
+        i32::from_str_radix(value, radix).map_err(|_| ParserError::cannot_parse_immediate(self.line))
+    }
 
-                            peeker.next(); // Jump over the X
+    fn consume_constant<'a, I>(&mut self,
+                               peeker: &mut Peekable<I>,
+                               name: String)
+                               -> Result<(), ParserError>
+        where I: Iterator<Item = &'a LexerToken>
+    {
+        if peeker.peek().is_none() {
+            return Err(ParserError::unexpected_eol(self.line));
+        }
 
-                            if let None = peeker.peek() {
-                                return Err(ParserError::unexpected_eol(self.line));
-                            }
+        let expression = self.consume_expression(peeker)?;
+        if peeker.peek().is_some() {
+            return Err(ParserError::unexpected_token(self.line));
+        }
 
-                            let next = *peeker.peek().unwrap();
-                            if let &LexerToken::CloseParenthesis = next {
-                                peeker.next();
-                                // Lets make sure we can find an appropriate opcode
-                                if let Some(opcode) = OpCode::from_mnemonic_and_addressing_mode(ident, AddressingMode::IndirectX) {
-                                    // We have everything we need now.. lets return an IndirectX opcode
-                                    // accompanied by the address
-                                    return Ok(vec![ParserToken::OpCode(opcode), ParserToken::RawByte(bytes[0])]);
-                                } else {
-                                    return Err(ParserError::invalid_opcode_addressing_mode_combination(self.line));
-                                }
-                            } else {
-                                return Err(ParserError::unexpected_token(self.line));
-                            }
-                        } else {
-                            return Err(ParserError::unexpected_token(self.line));
-                        }
-                    } else if let &LexerToken::CloseParenthesis = next {
-                        // We're headed for Indirect or IndirectY ..
-                        let addressing_mode = AddressingMode::IndirectY;
-                        peeker.next(); // Skip the closing paren
-
-                        if let None = peeker.peek() {
-                            // If this is the end.. then lets check if this
-                            // is the indirect jump: JMP ($0000)
-                            if let Some(opcode) = OpCode::from_mnemonic_and_addressing_mode(ident, AddressingMode::Indirect) {
-                                // Yep, we've found the only Indirect opcode
-                                // Lets make sure the address is 16-bit
-                                if address.len() != 4 {
-                                    return Err(ParserError::address_out_of_bounds(self.line));
-                                }
-                                let mut final_vec = vec![ParserToken::OpCode(opcode)];
-                                for b in bytes {
-                                    final_vec.push(ParserToken::RawByte(b));
-                                }
-                                return Ok(final_vec);
-                            } else {
-                                return Err(ParserError::invalid_opcode_addressing_mode_combination(self.line));
-                            }
-                        }
+        let name = self.qualify(&name);
+        self.constants.insert(name, expression);
+        Ok(())
+    }
 
-                        // Lets check for a comma
-                        let next = *peeker.peek().unwrap();
-                        if let &LexerToken::Comma = next {
-                            // Great, lets continue
-                            peeker.next();  // Skip the comma
-                            if let None = peeker.peek() {
-                                return Err(ParserError::unexpected_eol(self.line));
-                            }
+    /// Consumes `.PROC name`, which defines a label and opens a scope of
+    /// the same name, or `.SCOPE name`, which only opens the scope
+    fn consume_scope_directive<'a, I>(&mut self,
+                                      peeker: &mut Peekable<I>,
+                                      procedure: bool)
+                                      -> Result<Vec<ParserToken>, ParserError>
+        where I: Iterator<Item = &'a LexerToken>
+    {
+        peeker.next();
+        let name = match peeker.next() {
+            Some(LexerToken::Ident(name)) if !name.starts_with('@') => name.clone(),
+            Some(_) => return Err(ParserError::unexpected_token(self.line)),
+            None => return Err(ParserError::unexpected_eol(self.line)),
+        };
+        if peeker.peek().is_some() {
+            return Err(ParserError::unexpected_token(self.line));
+        }
 
-                            let next = *peeker.peek().unwrap();
-                            if let &LexerToken::Ident(ref register) = next {
-                                let register = register.to_uppercase();
-                                // If its not IndirectY .. thats a problem
-                                if register != "Y" {
-                                    return Err(ParserError::unexpected_token(self.line));
-                                }
-                                if let Some(opcode) = OpCode::from_mnemonic_and_addressing_mode(ident, AddressingMode::IndirectY) {
-                                    // Yep, we've found the only Indirect opcode
-                                    let mut final_vec = vec![ParserToken::OpCode(opcode)];
-                                    for b in bytes {
-                                        final_vec.push(ParserToken::RawByte(b));
-                                    }
-
-                                    return Ok(final_vec);
-                                } else {
-                                    return Err(ParserError::invalid_opcode_addressing_mode_combination(self.line));
-                                }
-                            } else {
-                                return Err(ParserError::unexpected_token(self.line));
-                            }
-                        } else {
-                            return Err(ParserError::unexpected_token(self.line));
-                        }
-                    } else {
-                        return Err(ParserError::unexpected_token(self.line));
-                    }
-                } else {
-                    return Err(ParserError::cannot_parse_address(self.line));
-                }
-            } else if let LexerToken::Immediate(ref immediate, base) = next {
-                peeker.next(); // Jump over the immediate
-                if let Ok(val) = u8::from_str_radix(&immediate[..],
-                                                    if base == ImmediateBase::Base10 {
-                                                        10
-                                                    } else {
-                                                        16
-                                                    }) {
-                    if let Some(opcode) =
-                           OpCode::from_mnemonic_and_addressing_mode(ident,
-                                                                     AddressingMode::Immediate) {
-                        return Ok(vec![ParserToken::OpCode(opcode), ParserToken::RawByte(val)]);
-                    } else {
-                        return Err(ParserError::invalid_opcode_addressing_mode_combination(self.line));
-                    }
-                } else {
-                    return Err(ParserError::cannot_parse_immediate(self.line));
-                }
-            } else {
-                return Err(ParserError::expected_address(self.line));
-            }
+        let mut result = Vec::new();
+        if procedure {
+            result.push(self.label(&name));
         }
+        self.scopes.push(Scope {
+            name,
+            procedure,
+            line: self.line,
+        });
+        result.push(ParserToken::Scope(self.scope_name()));
 
-        unreachable!();
+        Ok(result)
     }
 
     fn consume_org_directive<'a, I>(&mut self,
-                                    mut peeker: &mut Peekable<I>)
+                                    peeker: &mut Peekable<I>)
                                     -> Result<ParserToken, ParserError>
         where I: Iterator<Item = &'a LexerToken>
     {
@@ -501,18 +1534,20 @@ impl Parser {
             return Err(ParserError::expected_address(self.line));
         }
 
-        let next = peeker.next().unwrap();
-
-        if let &LexerToken::Address(ref address) = next {
-            let bytes = self.parse_address_bytes(address)?;
-            return Ok(ParserToken::OrgDirective(LittleEndian::read_u16(&bytes)));
-        } else {
-            return Err(ParserError::expected_address(self.line));
+        let expression = self.consume_expression(peeker)?;
+        match expression.constant_value() {
+            Some(addr) if (0..=0xFFFF).contains(&addr) => Ok(ParserToken::OrgDirective(addr as u16)),
+            Some(_) => Err(ParserError::address_out_of_bounds(self.line)),
+            None => Err(ParserError::expected_address(self.line)),
         }
     }
 
+    /// Data bytes from `.BYTE`, `.TEXT` or `.ASCII`, or `.ASCIIZ` when
+    /// `terminate` is set. Strings and values can be mixed, as in
+    /// `.TEXT "READY.", 13`.
     fn consume_byte_directive<'a, I>(&mut self,
-                                     mut peeker: &mut Peekable<I>)
+                                     peeker: &mut Peekable<I>,
+                                     terminate: bool)
                                      -> Result<ParserToken, ParserError>
         where I: Iterator<Item = &'a LexerToken>
     {
@@ -525,82 +1560,229 @@ impl Parser {
         }
 
         loop {
-            let mut next = peeker.next().unwrap();
-            if let &LexerToken::Ident(ref ident) = next {
-                let variable = self.get_variable_value(ident.clone())?;
-                if let LexerToken::Immediate(ref value, base) = variable.0 {
-                    let immediate = self.unwrap_immediate(&value[..], base);
-                    result.push(immediate);
-                } else {
-                    return Err(ParserError::expected_immediate(self.line));
+            if let Some(LexerToken::StringLiteral(text)) = peeker.peek() {
+                peeker.next();
+                for c in text.chars() {
+                    if c as u32 > 0xFF {
+                        return Err(ParserError::unencodable_character(c, self.line));
+                    }
+                    result.push(Expression::Number(c as i32));
                 }
-            } else if let &LexerToken::Immediate(ref value, base) = next {
-                let immediate = self.unwrap_immediate(&value[..], base);
-                result.push(immediate);
             } else {
-                return Err(ParserError::expected_immediate(self.line));
+                let expression = self.consume_expression(peeker)?;
+                if let Some(value) = expression.constant_value() {
+                    if !(-0x80..=0xFF).contains(&value) {
+                        return Err(ParserError::cannot_parse_immediate(self.line));
+                    }
+                }
+                result.push(expression);
             }
 
             // Check if the next thing is a comma. If it is, consume it and go again
-            if let None = peeker.peek() {
-                break;
+            match peeker.next() {
+                Some(&LexerToken::Comma) => (),
+                Some(_) => return Err(ParserError::unexpected_token(self.line)),
+                None => break,
             }
+        }
 
-            let next = peeker.next().unwrap();
-            if let &LexerToken::Comma = next {
-                // Awesome, go again
-            } else {
-                break;
+        if terminate {
+            result.push(Expression::Number(0));
+        }
+
+        Ok(ParserToken::Bytes(result))
+    }
+
+    /// The values of `.WORD` or `.DBYTE`
+    fn consume_word_directive<'a, I>(&mut self, peeker: &mut Peekable<I>) -> Result<Vec<Expression>, ParserError>
+        where I: Iterator<Item = &'a LexerToken>
+    {
+        let mut result = Vec::new();
+
+        // Jump over the directive
+        peeker.next();
+        if peeker.peek().is_none() {
+            return Err(ParserError::expected_address(self.line));
+        }
+
+        loop {
+            let expression = self.consume_expression(peeker)?;
+            if let Some(value) = expression.constant_value() {
+                if !(-0x8000..=0xFFFF).contains(&value) {
+                    return Err(ParserError::value_too_large(2, self.line));
+                }
+            }
+            result.push(expression);
+
+            match peeker.next() {
+                Some(&LexerToken::Comma) => (),
+                Some(_) => return Err(ParserError::unexpected_token(self.line)),
+                None => break,
             }
         }
 
-        Ok(ParserToken::RawBytes(result))
+        Ok(result)
     }
 
-    fn unwrap_immediate<S>(&self, value: S, base: ImmediateBase) -> u8
-        where S: Into<String>
+    /// `.FILL count [, value]` or `.RES count [, value]`: `count` bytes of
+    /// `value`, which is zero if it isn't given
+    fn consume_fill_directive<'a, I>(&mut self, peeker: &mut Peekable<I>) -> Result<ParserToken, ParserError>
+        where I: Iterator<Item = &'a LexerToken>
     {
-        let base = match base {
-            ImmediateBase::Base10 => 10,
-            ImmediateBase::Base16 => 16,
+        // Jump over the directive
+        peeker.next();
+        if peeker.peek().is_none() {
+            return Err(ParserError::unexpected_eol(self.line));
+        }
+        let count = self.consume_expression(peeker)?;
+        if let Some(count) = count.constant_value() {
+            if !(0..=0xFFFF).contains(&count) {
+                return Err(ParserError::address_out_of_bounds(self.line));
+            }
+        }
+
+        let value = match peeker.next() {
+            Some(&LexerToken::Comma) => self.consume_expression(peeker)?,
+            Some(_) => return Err(ParserError::unexpected_token(self.line)),
+            None => Expression::Number(0),
         };
+        if let Some(value) = value.constant_value() {
+            if !(-0x80..=0xFF).contains(&value) {
+                return Err(ParserError::cannot_parse_immediate(self.line));
+            }
+        }
+        if peeker.next().is_some() {
+            return Err(ParserError::expected_eol(self.line));
+        }
+
+        Ok(ParserToken::Fill(count, value))
+    }
+
+    /// `.ALIGN size [, value]`: pads with `value`, or zero, up to the next
+    /// address that is a multiple of `size`
+    fn consume_align_directive<'a, I>(&mut self, peeker: &mut Peekable<I>) -> Result<ParserToken, ParserError>
+        where I: Iterator<Item = &'a LexerToken>
+    {
+        // Jump over the directive
+        peeker.next();
+        let size = self.consume_constant_argument(peeker)?;
+        if !(1..=0x10000).contains(&size) {
+            return Err(ParserError::address_out_of_bounds(self.line));
+        }
 
-        let value = value.into();
-        let immediate = u8::from_str_radix(&value[..], base).unwrap();
+        let value = match peeker.next() {
+            Some(&LexerToken::Comma) => self.consume_constant_argument(peeker)?,
+            Some(_) => return Err(ParserError::unexpected_token(self.line)),
+            None => 0,
+        };
+        if !(-0x80..=0xFF).contains(&value) {
+            return Err(ParserError::cannot_parse_immediate(self.line));
+        }
+        if peeker.next().is_some() {
+            return Err(ParserError::expected_eol(self.line));
+        }
 
-        immediate
+        Ok(ParserToken::Align(size as u32, value as u8))
     }
 
-    fn parse_address_bytes(&self, address: &str) -> Result<Vec<u8>, ParserError> {
-        if let Ok(addr) = u16::from_str_radix(address, 16) {
-            if address.len() <= 2 {
-                return Ok(vec![addr as u8]);
-            } else {
-                let low_byte = addr as u8;
-                let high_byte = (addr >> 0x08) as u8;
-                return Ok(vec![low_byte, high_byte]);
-            }
-        } else {
-            Err(ParserError::cannot_parse_address(self.line))
+    /// A directive argument that has to be known before assembly
+    fn consume_constant_argument<'a, I>(&mut self, peeker: &mut Peekable<I>) -> Result<i32, ParserError>
+        where I: Iterator<Item = &'a LexerToken>
+    {
+        if peeker.peek().is_none() {
+            return Err(ParserError::unexpected_eol(self.line));
         }
+
+        self.consume_expression(peeker)?
+            .constant_value()
+            .ok_or_else(|| ParserError::expected_constant(self.line))
     }
 
-    fn get_variable_value<S>(&self, ident: S) -> Result<Variable, ParserError>
-        where S: Into<String>
+    /// Parses another file in place of `.INCLUDE "file"`
+    fn consume_include_directive<'a, I>(&mut self,
+                                        peeker: &mut Peekable<I>,
+                                        result: &mut Vec<ParserToken>)
+                                        -> Result<(), ParserError>
+        where I: Iterator<Item = &'a LexerToken>
     {
-        let ident = ident.into();
+        // Jump over the directive
+        peeker.next();
+        let (name, path) = self.consume_file_name(peeker)?;
+        if peeker.next().is_some() {
+            return Err(ParserError::expected_eol(self.line));
+        }
 
-        if let Some(ref var) = self.symbol_table.get(&ident) {
-            let var = var.clone();
-            if let LexerToken::Ident(ref ident) = var.0 {
-                // If this is _yet another_ variable .. recursively find its value:
-                return self.get_variable_value(ident.clone());
-            } else {
-                return Ok(Variable(var.clone().0));
+        let canonical = path.canonicalize().unwrap_or_else(|_| path.clone());
+        if self.files.contains(&canonical) {
+            return Err(ParserError::recursive_include(&name, self.line));
+        }
+
+        let line = self.line;
+        self.contexts.push(Context::IncludedFrom(line));
+        self.contexts.push(Context::File(path.clone()));
+        let outcome = self.parse_file_lines(&path, result);
+        self.contexts.truncate(self.contexts.len() - 2);
+
+        outcome.map_err(|error| error.in_file(&path).included_from(line))
+    }
+
+    /// Embeds the bytes of a file: `.INCBIN "file"`, optionally followed
+    /// by the offset to start at and how many bytes to take
+    fn consume_incbin_directive<'a, I>(&mut self, peeker: &mut Peekable<I>) -> Result<ParserToken, ParserError>
+        where I: Iterator<Item = &'a LexerToken>
+    {
+        // Jump over the directive
+        peeker.next();
+        let (name, path) = self.consume_file_name(peeker)?;
+
+        let mut bytes = Vec::new();
+        File::open(&path)
+            .and_then(|mut file| file.read_to_end(&mut bytes))
+            .map_err(|error| ParserError::cannot_read_file(&name, error, self.line))?;
+
+        let mut range = Vec::new();
+        while let Some(token) = peeker.next() {
+            if *token != LexerToken::Comma || range.len() == 2 {
+                return Err(ParserError::unexpected_token(self.line));
             }
-        } else {
-            return Err(ParserError::unknown_identifier(self.line));
+            match self.consume_expression(peeker)?.constant_value() {
+                Some(value) if value >= 0 => range.push(value as usize),
+                _ => return Err(ParserError::outside_of_file(&name, self.line)),
+            }
+        }
+
+        let start = range.first().cloned().unwrap_or(0);
+        let end = match range.get(1) {
+            Some(&length) => start + length,
+            None => bytes.len(),
+        };
+        if start > end || end > bytes.len() {
+            return Err(ParserError::outside_of_file(&name, self.line));
         }
+
+        Ok(ParserToken::RawBytes(bytes[start..end].to_vec()))
+    }
+
+    /// The name of a file to include, and where it was found. Files are
+    /// looked for next to the file being parsed, then in the include paths.
+    fn consume_file_name<'a, I>(&self, peeker: &mut Peekable<I>) -> Result<(String, PathBuf), ParserError>
+        where I: Iterator<Item = &'a LexerToken>
+    {
+        let name = match peeker.next() {
+            Some(LexerToken::StringLiteral(name)) => name.clone(),
+            _ => return Err(ParserError::expected_file_name(self.line)),
+        };
+
+        let next_to_source = match self.files.last().and_then(|file| file.parent()) {
+            Some(dir) => dir.join(&name),
+            None => PathBuf::from(&name),
+        };
+        let path = iter::once(next_to_source)
+            .chain(self.include_paths.iter().map(|dir| dir.join(&name)))
+            .find(|path| path.is_file())
+            .ok_or_else(|| ParserError::file_not_found(&name, self.line))?;
+
+        Ok((name, path))
     }
 }
 
@@ -624,7 +1806,9 @@ mod tests {
 
     #[test]
     fn can_parse_labels_via_colon_terminator() {
-        let tokens = vec![vec![LexerToken::Ident("MAIN".into())], vec![LexerToken::Colon]];
+        // The colon is on the same line as the label, since a colon on its
+        // own line is an anonymous label
+        let tokens = vec![vec![LexerToken::Ident("MAIN".into()), LexerToken::Colon]];
 
         let mut parser = Parser::new();
         let result = parser.parse(tokens).unwrap();
@@ -814,7 +1998,9 @@ mod tests {
                                LexerToken::Assignment,
                                LexerToken::Address("00".into())],
                           vec![LexerToken::Ident("JMP".into()),
-                               LexerToken::Ident("MAIN_ADDRESS".into())]];
+                               LexerToken::Ident("MAIN_ADDRESS".into()),
+                               LexerToken::Comma,
+                               LexerToken::Ident("X".into())]];
 
         let mut parser = Parser::new();
         let result = parser.parse(tokens);
@@ -834,4 +2020,68 @@ mod tests {
 
         assert_eq!(&[ParserToken::OrgDirective(0xC000)], &result[..]);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn can_parse_expressions_with_precedence() {
+        let tokens = vec![vec![LexerToken::Ident("LDA".into()),
+                               LexerToken::Ident("TABLE".into()),
+                               LexerToken::Plus,
+                               LexerToken::Number("2".into()),
+                               LexerToken::Asterisk,
+                               LexerToken::Number("3".into()),
+                               LexerToken::Comma,
+                               LexerToken::Ident("X".into())]];
+
+        let mut parser = Parser::new();
+        let result = parser.parse(tokens).unwrap();
+
+        let offset = Expression::Binary(BinaryOperator::Multiply,
+                                        Box::new(Expression::Number(2)),
+                                        Box::new(Expression::Number(3)));
+        // Whether TABLE is in the zero page is up to the assembler
+        assert_eq!(&[ParserToken::ZeroPageOrAbsolute(OpCode::from_mnemonic_and_addressing_mode("LDA", AddressingMode::ZeroPageX).unwrap(),
+                                                     OpCode::from_mnemonic_and_addressing_mode("LDA", AddressingMode::AbsoluteX).unwrap(),
+                                                     Expression::Binary(BinaryOperator::Add,
+                                                                        Box::new(Expression::Symbol("TABLE".into())),
+                                                                        Box::new(offset)))],
+                   &result[..]);
+    }
+
+    #[test]
+    fn can_parse_constant_expressions_as_zero_page() {
+        let tokens = vec![vec![LexerToken::Ident("LDA".into()),
+                               LexerToken::OpenParenthesis,
+                               LexerToken::Address("10".into()),
+                               LexerToken::Plus,
+                               LexerToken::Number("1".into()),
+                               LexerToken::CloseParenthesis,
+                               LexerToken::Asterisk,
+                               LexerToken::Number("2".into()),
+                               LexerToken::Comma,
+                               LexerToken::Ident("X".into())]];
+
+        let mut parser = Parser::new();
+        let result = parser.parse(tokens).unwrap();
+
+        assert_eq!(&[ParserToken::OpCode(OpCode::from_mnemonic_and_addressing_mode("LDA", AddressingMode::ZeroPageX).unwrap()),
+                     ParserToken::RawByte(0x22)],
+                   &result[..]);
+    }
+
+    #[test]
+    fn can_parse_equ_constants_by_value() {
+        let tokens = vec![vec![LexerToken::Ident("POINTER".into()),
+                               LexerToken::Period,
+                               LexerToken::Ident("EQU".into()),
+                               LexerToken::Address("0010".into())],
+                          vec![LexerToken::Ident("LDA".into()),
+                               LexerToken::Ident("POINTER".into())]];
+
+        let mut parser = Parser::new();
+        let result = parser.parse(tokens).unwrap();
+
+        assert_eq!(&[ParserToken::OpCode(OpCode::from_mnemonic_and_addressing_mode("LDA", AddressingMode::ZeroPage).unwrap()),
+                     ParserToken::RawByte(0x10)],
+                   &result[..]);
+    }
+}
@@ -0,0 +1,86 @@
+/// The full names `name` could refer to from inside `scope`, innermost
+/// first: `a::b::x`, `a::x` and then `x` for `x` inside `a::b`. A name
+/// starting with `::` only refers to the outermost scope.
+pub fn scoped_names(scope: &str, name: &str) -> Vec<String> {
+    if let Some(name) = name.strip_prefix("::") {
+        return vec![name.to_string()];
+    }
+
+    let mut names = Vec::new();
+    let mut scope = scope;
+    while !scope.is_empty() {
+        names.push(format!("{}::{}", scope, name));
+        scope = scope.rfind("::").map_or("", |index| &scope[..index]);
+    }
+    names.push(name.to_string());
+
+    names
+}
+
+/// The labels of an assembled program and their addresses, for turning
+/// addresses back into names in backtraces and the like
+#[derive(Clone, Debug, Default)]
+pub struct SymbolTable {
+    // Sorted by address
+    labels: Vec<(u16, String)>,
+}
+
+impl SymbolTable {
+    pub fn new() -> SymbolTable {
+        SymbolTable { labels: Vec::new() }
+    }
+
+    pub fn insert<S>(&mut self, name: S, addr: u16)
+        where S: Into<String>
+    {
+        let index = self.labels
+            .iter()
+            .position(|&(label_addr, _)| label_addr > addr)
+            .unwrap_or(self.labels.len());
+        self.labels.insert(index, (addr, name.into()));
+    }
+
+    pub fn address_of(&self, name: &str) -> Option<u16> {
+        self.labels.iter().find(|(_, label)| label == name).map(|&(addr, _)| addr)
+    }
+
+    /// Names `addr` after the closest label at or before it, e.g. `MAIN`
+    /// or `MAIN+3`
+    pub fn describe(&self, addr: u16) -> Option<String> {
+        self.labels
+            .iter()
+            .rev()
+            .find(|&&(label_addr, _)| label_addr <= addr)
+            .map(|(label_addr, label)| if *label_addr == addr {
+                label.clone()
+            } else {
+                format!("{}+{}", label, addr - label_addr)
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn describes_addresses_relative_to_the_closest_label() {
+        let mut symbols = SymbolTable::new();
+        symbols.insert("SUB", 0xC010);
+        symbols.insert("MAIN", 0xC000);
+
+        assert_eq!(None, symbols.describe(0xBFFF));
+        assert_eq!(Some("MAIN".into()), symbols.describe(0xC000));
+        assert_eq!(Some("MAIN+15".into()), symbols.describe(0xC00F));
+        assert_eq!(Some("SUB+2".into()), symbols.describe(0xC012));
+        assert_eq!(Some(0xC010), symbols.address_of("SUB"));
+    }
+
+    #[test]
+    fn names_resolve_outward_from_their_scope() {
+        assert_eq!(vec!["game::player::x", "game::x", "x"], scoped_names("game::player", "x"));
+        assert_eq!(vec!["game::player::x", "player::x"], scoped_names("game", "player::x"));
+        assert_eq!(vec!["x"], scoped_names("", "x"));
+        assert_eq!(vec!["player::x"], scoped_names("game", "::player::x"));
+    }
+}
@@ -1,4 +1,5 @@
 use ::opcodes::{AddressingMode, OpCode};
+use assembler::expression::Expression;
 
 #[derive(Eq, PartialEq, Debug, Clone, Copy)]
 pub enum ImmediateBase {
@@ -16,16 +17,66 @@ pub enum LexerToken {
     Comma,
     Period,
     Immediate(String, ImmediateBase),
+    /// A base 10 number without a `#` in front
+    Number(String),
+    /// A `#` that isn't followed by a number, as in `#<LABEL`
+    Hash,
     Colon,
+    /// `::`, between the parts of a qualified name like `player::update`
+    DoubleColon,
+    Plus,
+    Minus,
+    Asterisk,
+    Slash,
+    Percent,
+    Ampersand,
+    Pipe,
+    Caret,
+    ShiftLeft,
+    ShiftRight,
+    LessThan,
+    GreaterThan,
+    LessThanOrEqual,
+    GreaterThanOrEqual,
+    NotEqual,
+    /// The text between double quotes, as in `.INCLUDE "file.asm"`
+    StringLiteral(String),
+    /// Ends a line the lexer couldn't make sense of, holding the error
+    Invalid(String),
 }
 
 #[derive(Eq, PartialEq, Debug, Clone)]
 pub enum ParserToken {
     Label(String),
-    LabelArg(String),
     OpCode(OpCode),
+    /// The operand of the opcode before it, when it refers to labels. Its
+    /// size comes from the opcode's addressing mode.
+    Operand(Expression),
     Absolute(String),
+    /// An instruction with zero page and absolute forms, whose operand
+    /// refers to labels. The assembler picks the zero page form if the
+    /// operand turns out to fit in a byte.
+    ZeroPageOrAbsolute(OpCode, OpCode, Expression),
     RawByte(u8),
     OrgDirective(u16),
     RawBytes(Vec<u8>),
+    /// Data bytes from `.BYTE` and the text directives, which can refer
+    /// to labels
+    Bytes(Vec<Expression>),
+    /// Little endian words from `.WORD`
+    Words(Vec<Expression>),
+    /// Big endian words from `.DBYTE`
+    DoubleBytes(Vec<Expression>),
+    /// `.FILL` and `.RES`: a count, which can depend on labels, and the
+    /// byte to repeat
+    Fill(Expression, Expression),
+    /// `.ALIGN`: bytes of the given value up to the next multiple of the
+    /// given size
+    Align(u32, u8),
+    /// The scope the lines after it are in, e.g. `player::update`, or an
+    /// empty string for the outermost scope
+    Scope(String),
+    /// Where the tokens after it came from, e.g. `Line 3, in macro 'WAIT'
+    /// called from line 9`
+    Location(String),
 }
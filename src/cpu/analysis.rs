@@ -0,0 +1,171 @@
+use std::ops::RangeInclusive;
+
+use ::assembler::CodeSegment;
+
+/// A read of memory that was never written, found with shadow memory
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct UninitializedRead {
+    /// Where the instruction that made the read begins
+    pub pc: u16,
+    /// The address that was read
+    pub addr: u16,
+    /// The disassembled instruction, e.g. `LDA $10`
+    pub instruction: String,
+}
+
+/// The kinds of suspicious code a `CodeReport` can contain
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum CodeFindingKind {
+    /// A write to a byte that had already been executed
+    SelfModifyingWrite,
+    /// An instruction fetched from a byte the program wrote as data
+    ExecutedData,
+    /// An instruction fetched from outside the code segments the program
+    /// was loaded from
+    ExecutedOutsideSegments,
+}
+
+/// Something suspicious the code analysis saw
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct CodeFinding {
+    pub kind: CodeFindingKind,
+    /// Where the instruction responsible begins
+    pub pc: u16,
+    /// The address written or executed
+    pub addr: u16,
+}
+
+/// What the code analysis recorded while the Cpu ran
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct CodeReport {
+    /// The address ranges instructions (opcodes and operands) were fetched
+    /// from
+    pub executed: Vec<RangeInclusive<u16>>,
+    /// The address ranges the Cpu wrote to
+    pub written: Vec<RangeInclusive<u16>>,
+    /// Each suspicious access, in the order they happened. Repeats of the
+    /// same instruction touching the same address are only listed once.
+    pub findings: Vec<CodeFinding>,
+}
+
+/// Records the addresses the Cpu executes and writes
+pub struct CodeAnalysis {
+    executed: Vec<bool>,
+    written: Vec<bool>,
+    segments: Vec<RangeInclusive<u16>>,
+    findings: Vec<CodeFinding>,
+}
+
+impl CodeAnalysis {
+    pub fn new(segments: &[CodeSegment]) -> CodeAnalysis {
+        CodeAnalysis {
+            executed: vec![false; 1024 * 64],
+            written: vec![false; 1024 * 64],
+            segments: segments.iter()
+                .filter(|segment| !segment.code.is_empty())
+                .map(|segment| {
+                    let end = segment.address as usize + segment.code.len() - 1;
+                    segment.address..=end.min(0xFFFF) as u16
+                })
+                .collect(),
+            findings: Vec::new(),
+        }
+    }
+
+    /// Records the fetch of an opcode (`opcode` true) or operand byte
+    pub fn execute(&mut self, pc: u16, addr: u16, opcode: bool) {
+        self.executed[addr as usize] = true;
+
+        if !opcode {
+            return;
+        }
+        if self.written[addr as usize] {
+            self.find(CodeFindingKind::ExecutedData, pc, addr);
+        }
+        if !self.segments.is_empty() && !self.segments.iter().any(|range| range.contains(&addr)) {
+            self.find(CodeFindingKind::ExecutedOutsideSegments, pc, addr);
+        }
+    }
+
+    pub fn write(&mut self, pc: u16, addr: u16) {
+        self.written[addr as usize] = true;
+
+        if self.executed[addr as usize] {
+            self.find(CodeFindingKind::SelfModifyingWrite, pc, addr);
+        }
+    }
+
+    pub fn report(&self) -> CodeReport {
+        CodeReport {
+            executed: CodeAnalysis::ranges(&self.executed),
+            written: CodeAnalysis::ranges(&self.written),
+            findings: self.findings.clone(),
+        }
+    }
+
+    fn find(&mut self, kind: CodeFindingKind, pc: u16, addr: u16) {
+        let finding = CodeFinding { kind, pc, addr };
+
+        if !self.findings.contains(&finding) {
+            self.findings.push(finding);
+        }
+    }
+
+    /// Collapses a flag per address into runs of set flags
+    fn ranges(flags: &[bool]) -> Vec<RangeInclusive<u16>> {
+        let mut ranges = Vec::new();
+        let mut start = None;
+
+        for (addr, &flag) in flags.iter().enumerate() {
+            match (start, flag) {
+                (None, true) => start = Some(addr),
+                (Some(first), false) => {
+                    ranges.push(first as u16..=(addr - 1) as u16);
+                    start = None;
+                }
+                _ => (),
+            }
+        }
+        if let Some(first) = start {
+            ranges.push(first as u16..=(flags.len() - 1) as u16);
+        }
+
+        ranges
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn flags_collapse_into_ranges() {
+        let mut flags = vec![false; 1024 * 64];
+        for addr in &[0x10, 0x11, 0x12, 0x20, 0xFFFF] {
+            flags[*addr] = true;
+        }
+
+        assert_eq!(vec![0x10..=0x12, 0x20..=0x20, 0xFFFF..=0xFFFF],
+                   CodeAnalysis::ranges(&flags));
+    }
+
+    #[test]
+    fn only_opcodes_outside_segments_are_found() {
+        let segments = [CodeSegment {
+                            address: 0xC000,
+                            code: vec![0xEA; 0x10],
+                        }];
+        let mut analysis = CodeAnalysis::new(&segments);
+
+        analysis.execute(0xC00F, 0xC00F, true);
+        analysis.execute(0xC00E, 0xC010, false);
+        analysis.execute(0xC010, 0xC010, true);
+
+        assert_eq!(vec![CodeFinding {
+                            kind: CodeFindingKind::ExecutedOutsideSegments,
+                            pc: 0xC010,
+                            addr: 0xC010,
+                        }],
+                   analysis.report().findings);
+    }
+}
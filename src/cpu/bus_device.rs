@@ -0,0 +1,69 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+/// A memory mapped device that can be attached to the MemoryBus. Reads and
+/// writes are allowed to have side effects (acknowledging an interrupt,
+/// popping a byte from a FIFO, etc), which is why every read the Cpu makes,
+/// including the dummy ones, is passed through to the device.
+pub trait BusDevice {
+    /// Reads a byte from the device. `addr` is relative to the start of
+    /// the range the device is mapped at.
+    fn read(&mut self, addr: u16) -> u8;
+
+    /// Writes a byte to the device. `addr` is relative to the start of
+    /// the range the device is mapped at.
+    fn write(&mut self, addr: u16, byte: u8);
+
+    /// Reads a byte without any side effects, for when the Cpu or a
+    /// debugger needs to look at memory without disturbing it. Devices
+    /// that can't do that return None, which is the default.
+    fn peek(&self, _addr: u16) -> Option<u8> {
+        None
+    }
+
+    /// Called once at the end of every Cpu clock cycle, for devices that
+    /// count time (timers, shift registers, etc)
+    fn tick(&mut self) {}
+
+    /// Whether the device is pulling the Cpu IRQ line low
+    fn irq(&self) -> bool {
+        false
+    }
+
+    /// For bank switched devices, the bank currently mapped at `addr`
+    /// (relative to the start of the device). Lets disassemblers and
+    /// debuggers tell banked addresses apart.
+    fn bank(&self, _addr: u16) -> Option<usize> {
+        None
+    }
+}
+
+/// Allows the host to keep a handle to a device after handing it to the
+/// MemoryBus, so that its state can be inspected or driven from outside.
+impl<T> BusDevice for Rc<RefCell<T>>
+    where T: BusDevice
+{
+    fn read(&mut self, addr: u16) -> u8 {
+        self.borrow_mut().read(addr)
+    }
+
+    fn write(&mut self, addr: u16, byte: u8) {
+        self.borrow_mut().write(addr, byte)
+    }
+
+    fn peek(&self, addr: u16) -> Option<u8> {
+        self.borrow().peek(addr)
+    }
+
+    fn tick(&mut self) {
+        self.borrow_mut().tick()
+    }
+
+    fn irq(&self) -> bool {
+        self.borrow().irq()
+    }
+
+    fn bank(&self, addr: u16) -> Option<usize> {
+        self.borrow().bank(addr)
+    }
+}
@@ -0,0 +1,241 @@
+use std::fmt;
+
+use ::assembler::SymbolTable;
+
+/// How a frame on the call stack was entered
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum FrameKind {
+    /// By JSR, and left by RTS
+    Subroutine,
+    /// By BRK, IRQ or NMI, and left by RTI
+    Interrupt,
+}
+
+/// A subroutine call or interrupt the Cpu hasn't returned from yet
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct CallFrame {
+    pub kind: FrameKind,
+    /// The JSR or BRK instruction, or the instruction an interrupt arrived
+    /// before
+    pub call_site: u16,
+    /// The subroutine or interrupt handler that was entered
+    pub target: u16,
+    /// Where execution carries on after returning
+    pub return_addr: u16,
+    /// The stack pointer once the return address (and flags) were pushed
+    pub stack_pointer: u8,
+}
+
+/// The ways code can use the stack that break the pairing of calls and
+/// returns
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum StackAnomalyKind {
+    /// An RTS that doesn't return from a JSR, like the trick of pushing an
+    /// address minus one and returning to jump to it
+    ReturnWithoutCall,
+    /// An RTI that doesn't return from an interrupt
+    ReturnWithoutInterrupt,
+    /// An RTS leaving an interrupt, or an RTI leaving a subroutine
+    MismatchedReturn,
+    /// A return address taken off the stack without returning, e.g. by
+    /// PLA PLA or TXS
+    DiscardedFrame,
+}
+
+/// Stack manipulation the call stack tracking noticed
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct StackAnomaly {
+    pub kind: StackAnomalyKind,
+    /// The instruction responsible
+    pub pc: u16,
+    /// The frame that was returned from or discarded, if there was one
+    pub frame: Option<CallFrame>,
+}
+
+/// One level of a backtrace
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct BacktraceFrame {
+    /// Where execution is in this frame: the current PC for the innermost
+    /// frame, and the call site of the frame inside it for the others
+    pub pc: u16,
+    /// The subroutine or handler this frame is running, or `None` for the
+    /// outermost code
+    pub entry: Option<u16>,
+    /// `pc` as a label and offset, if a symbol table was given
+    pub symbol: Option<String>,
+}
+
+impl fmt::Display for BacktraceFrame {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.symbol {
+            Some(ref symbol) => write!(f, "{:04X} {}", self.pc, symbol),
+            None => write!(f, "{:04X}", self.pc),
+        }
+    }
+}
+
+/// A shadow of the 6502 stack that only holds calls and interrupts, kept
+/// in step with the stack pointer
+pub struct CallStack {
+    frames: Vec<CallFrame>,
+    anomalies: Vec<StackAnomaly>,
+}
+
+impl CallStack {
+    pub fn new() -> CallStack {
+        CallStack {
+            frames: Vec::new(),
+            anomalies: Vec::new(),
+        }
+    }
+
+    pub fn frames(&self) -> &[CallFrame] {
+        &self.frames
+    }
+
+    pub fn anomalies(&self) -> &[StackAnomaly] {
+        &self.anomalies
+    }
+
+    pub fn clear(&mut self) {
+        self.frames.clear();
+    }
+
+    pub fn call(&mut self, frame: CallFrame) {
+        self.frames.push(frame);
+    }
+
+    /// Pops the frame an RTS or RTI returns from. `stack_pointer` is the
+    /// stack pointer before the return address was pulled.
+    pub fn ret(&mut self, kind: FrameKind, pc: u16, stack_pointer: u8) {
+        let returns_from_top = self.frames
+            .last()
+            .is_some_and(|top| top.stack_pointer == stack_pointer);
+
+        if !returns_from_top {
+            let anomaly = match kind {
+                FrameKind::Subroutine => StackAnomalyKind::ReturnWithoutCall,
+                FrameKind::Interrupt => StackAnomalyKind::ReturnWithoutInterrupt,
+            };
+            self.anomaly(anomaly, pc, None);
+            return;
+        }
+
+        let frame = self.frames.pop();
+        if frame.is_some_and(|frame| frame.kind != kind) {
+            self.anomaly(StackAnomalyKind::MismatchedReturn, pc, frame);
+        }
+    }
+
+    /// Drops the frames whose return addresses are no longer on the stack
+    pub fn stack_moved(&mut self, pc: u16, stack_pointer: u8) {
+        while let Some(&top) = self.frames.last() {
+            if top.stack_pointer >= stack_pointer {
+                break;
+            }
+
+            self.frames.pop();
+            self.anomaly(StackAnomalyKind::DiscardedFrame, pc, Some(top));
+        }
+    }
+
+    /// Innermost frame first
+    pub fn backtrace(&self, pc: u16, symbols: Option<&SymbolTable>) -> Vec<BacktraceFrame> {
+        let mut result = Vec::new();
+        let mut pc = pc;
+
+        for frame in self.frames.iter().rev() {
+            result.push(BacktraceFrame {
+                pc,
+                entry: Some(frame.target),
+                symbol: symbols.and_then(|symbols| symbols.describe(pc)),
+            });
+            pc = frame.call_site;
+        }
+        result.push(BacktraceFrame {
+            pc,
+            entry: None,
+            symbol: symbols.and_then(|symbols| symbols.describe(pc)),
+        });
+
+        result
+    }
+
+    /// Records an anomaly, once per kind and instruction
+    fn anomaly(&mut self, kind: StackAnomalyKind, pc: u16, frame: Option<CallFrame>) {
+        let seen = self.anomalies
+            .iter()
+            .any(|anomaly| anomaly.kind == kind && anomaly.pc == pc);
+
+        if !seen {
+            self.anomalies.push(StackAnomaly { kind, pc, frame });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn subroutine(call_site: u16, target: u16, stack_pointer: u8) -> CallFrame {
+        CallFrame {
+            kind: FrameKind::Subroutine,
+            call_site,
+            target,
+            return_addr: call_site + 3,
+            stack_pointer,
+        }
+    }
+
+    #[test]
+    fn returns_pop_matching_frames() {
+        let mut stack = CallStack::new();
+        stack.call(subroutine(0xC000, 0xC100, 0xFD));
+        stack.call(subroutine(0xC100, 0xC200, 0xFB));
+
+        stack.ret(FrameKind::Subroutine, 0xC200, 0xFB);
+
+        assert_eq!(&[subroutine(0xC000, 0xC100, 0xFD)], stack.frames());
+        assert!(stack.anomalies().is_empty());
+    }
+
+    #[test]
+    fn return_with_extra_bytes_pushed_is_not_paired() {
+        let mut stack = CallStack::new();
+        stack.call(subroutine(0xC000, 0xC100, 0xFD));
+
+        // Two bytes pushed on top of the return address, then RTS
+        stack.ret(FrameKind::Subroutine, 0xC105, 0xFB);
+
+        assert_eq!(1, stack.frames().len());
+        assert_eq!(StackAnomalyKind::ReturnWithoutCall, stack.anomalies()[0].kind);
+    }
+
+    #[test]
+    fn pulling_a_return_address_discards_its_frame() {
+        let mut stack = CallStack::new();
+        stack.call(subroutine(0xC000, 0xC100, 0xFD));
+        stack.call(subroutine(0xC100, 0xC200, 0xFB));
+
+        stack.stack_moved(0xC201, 0xFC);
+
+        assert_eq!(1, stack.frames().len());
+        assert_eq!(&[StackAnomaly {
+                         kind: StackAnomalyKind::DiscardedFrame,
+                         pc: 0xC201,
+                         frame: Some(subroutine(0xC100, 0xC200, 0xFB)),
+                     }],
+                   stack.anomalies());
+    }
+
+    #[test]
+    fn backtrace_lists_the_innermost_frame_first() {
+        let mut stack = CallStack::new();
+        stack.call(subroutine(0xC000, 0xC100, 0xFD));
+        stack.call(subroutine(0xC104, 0xC200, 0xFB));
+
+        let pcs: Vec<u16> = stack.backtrace(0xC202, None).iter().map(|frame| frame.pc).collect();
+
+        assert_eq!(vec![0xC202, 0xC104, 0xC000], pcs);
+    }
+}
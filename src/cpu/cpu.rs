@@ -1,27 +1,65 @@
+use std::mem;
+
 use byteorder::{LittleEndian, ByteOrder};
 
+use ::assembler::{CodeSegment, SymbolTable};
+use ::disassembler::Disassembler;
 use ::opcodes::{AddressingMode, OpCode};
 
+use cpu::analysis::{CodeAnalysis, CodeReport, UninitializedRead};
+use cpu::call_stack::{BacktraceFrame, CallFrame, CallStack, FrameKind, StackAnomaly};
+
 use cpu::cpu_error::CpuError;
 use cpu::flags::StatusFlags;
-use cpu::memory_bus::MemoryBus;
+use cpu::memory_bus::{BusAccess, MemoryBus};
+use cpu::memory_region::{FaultPolicy, MemoryFault};
+use cpu::pins::Pins;
+use cpu::power::{PatternBytes, PowerOnState};
+use cpu::scheduler::Scheduler;
 use cpu::registers::Registers;
 use cpu::stack::Stack;
+use cpu::variant::CpuVariant;
 
 const DEFAULT_CODE_SEGMENT_START_ADDRESS: u16 = 0xC000;  // Default to a 16KB ROM, leaving 48KB of main memory
 
-const STACK_START: usize = 0x100;
-const STACK_END: usize = 0x1FF;
+const STACK_START: u16 = 0x100;
 
-const RESET_VECTOR: usize = 0xFFFC;
-const NMI_VECTOR: usize = 0xFFFA;
-const IRQ_VECTOR: usize = 0xFFFE;
+const RESET_VECTOR: u16 = 0xFFFC;
+const NMI_VECTOR: u16 = 0xFFFA;
+const IRQ_VECTOR: u16 = 0xFFFE;
+
+/// The way an instruction uses the memory its operand points at. This
+/// decides which dummy cycles the 6502 inserts while it works out the
+/// effective address.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Access {
+    Read,
+    Write,
+    ReadModifyWrite,
+}
 
-#[derive(Debug)]
-pub enum Operand {
-    Immediate(u8),
-    Memory(u16),
-    Implied,
+/// A run of cycles the Cpu is part way through
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Sequence {
+    Instruction(OpCode),
+    Interrupt(u16),
+    Reset,
+}
+
+/// The internal latches a sequence uses to carry values from one cycle to
+/// the next
+#[derive(Clone, Copy, Debug, Default)]
+struct Microcode {
+    /// Number of cycles run since the sequence began (for instructions,
+    /// since the opcode was fetched)
+    step: u8,
+    /// The step the operation begins at, once the effective address of a
+    /// memory operand is known
+    operate_from: Option<u8>,
+    addr: u16,
+    base: u16,
+    pointer: u8,
+    value: u8,
 }
 
 /// A representation of a 6502 microprocessor
@@ -30,60 +68,117 @@ pub struct Cpu {
     pub registers: Registers,
     pub flags: StatusFlags,
     pub stack: Stack,
+    /// Total number of clock cycles executed since the Cpu was created
+    pub cycles: u64,
+    pub variant: CpuVariant,
+    pub pins: Pins,
+    pub scheduler: Scheduler,
+    /// The pin levels seen by the previous tick, used to find edges
+    sampled: Pins,
+    halted: bool,
+    nmi_pending: bool,
+    sequence: Option<Sequence>,
+    /// The PC the current sequence began at
+    sequence_pc: u16,
+    /// The stack pointer when the current sequence began
+    sequence_sp: u8,
+    micro: Microcode,
+    trace_bus: bool,
+    bus_trace: Vec<BusAccess>,
+    memory_faults: Vec<MemoryFault>,
+    /// A fault raised by `reset`, `nmi` or `irq`, returned by the next tick
+    pending_fault: Option<CpuError>,
+    uninitialized_reads: Vec<UninitializedRead>,
+    code_analysis: Option<CodeAnalysis>,
+    call_stack: CallStack,
 }
 
 pub type CpuLoadResult = Result<(), CpuError>;
 pub type CpuStepResult = Result<u8, CpuError>;
+pub type CpuTickResult = Result<bool, CpuError>;
 pub type CpuMultiStepResult = Result<u64, CpuError>;
 
 impl Cpu {
     /// Returns a default instance of a Cpu
     pub fn new() -> Cpu {
+        Cpu::with_variant(Default::default())
+    }
+
+    /// Returns an instance of a Cpu that emulates the quirks of a
+    /// specific 6502 variant
+    pub fn with_variant(variant: CpuVariant) -> Cpu {
         Cpu {
             memory: MemoryBus::new(),
             registers: Registers::new(),
             flags: Default::default(),
             stack: Stack::new(),
+            cycles: 0,
+            variant,
+            pins: Default::default(),
+            scheduler: Scheduler::new(),
+            sampled: Default::default(),
+            halted: false,
+            nmi_pending: false,
+            sequence: None,
+            sequence_pc: 0,
+            sequence_sp: 0,
+            micro: Default::default(),
+            trace_bus: false,
+            bus_trace: Vec::new(),
+            memory_faults: Vec::new(),
+            pending_fault: None,
+            uninitialized_reads: Vec::new(),
+            code_analysis: None,
+            call_stack: CallStack::new(),
         }
     }
 
     /// Loads code into the Cpu main memory at an optional offset. If no
     /// offset is provided, the Cpu will, by default, load the code into
-    /// main memory at 0xC000
+    /// main memory at 0xC000. The interrupt vectors are left untouched.
+    /// Code can be loaded into ROM regions.
     pub fn load<T>(&mut self, code: &[u8], addr: T) -> CpuLoadResult
         where T: Into<Option<u16>>
     {
-        let addr = addr.into();
-        let addr: u16 = if addr.is_some() {
-            let addr = addr.unwrap();
-            if addr as u32 + code.len() as u32 - 1 > u16::max_value() as u32 {
-                return Err(CpuError::code_segment_out_of_range(addr));
-            } else if addr == 0 {
-                DEFAULT_CODE_SEGMENT_START_ADDRESS
-            } else {
-                addr
-            }
-        } else {
-            DEFAULT_CODE_SEGMENT_START_ADDRESS
+        let addr = match addr.into() {
+            Some(0) | None => DEFAULT_CODE_SEGMENT_START_ADDRESS,
+            Some(addr) => addr,
         };
 
+        if addr as usize + code.len() > u16::max_value() as usize + 1 {
+            return Err(CpuError::code_segment_out_of_range(addr));
+        }
+
         for x in 0..code.len() {
-            self.memory.write_byte(addr + x as u16, code[x]);
+            self.memory.load_byte(addr + x as u16, code[x]);
         }
 
-        // Set the Program Counter to point at the
-        // start address of the code segment
-        self.set_start_vector(addr);
+        Ok(())
+    }
+
+    /// Loads code into main memory exactly like `load`, then points the
+    /// reset vector at the start of it so that the next `reset` begins
+    /// executing the loaded code
+    pub fn load_with_reset_vector<T>(&mut self, code: &[u8], addr: T) -> CpuLoadResult
+        where T: Into<Option<u16>>
+    {
+        let addr = match addr.into() {
+            Some(0) | None => DEFAULT_CODE_SEGMENT_START_ADDRESS,
+            Some(addr) => addr,
+        };
+
+        self.load(code, addr)?;
+        self.set_reset_vector(addr);
 
         Ok(())
     }
 
-    /// Sets the start vector in memory if its currently zero.
-    fn set_start_vector(&mut self, addr: u16) {
-        let current = LittleEndian::read_u16(&self.memory[RESET_VECTOR..]);
-        if current == 0 {
-            LittleEndian::write_u16(&mut self.memory[RESET_VECTOR..], addr);
-        }
+    /// Writes the address the Cpu starts executing from after a reset. The
+    /// vector counts as initialized for shadow memory.
+    pub fn set_reset_vector(&mut self, addr: u16) {
+        let vector = RESET_VECTOR as usize;
+        LittleEndian::write_u16(&mut self.memory[vector..], addr);
+        self.memory.mark_initialized(RESET_VECTOR, RESET_VECTOR + 1);
     }
 
     /// Runs N instructions of code through the Cpu
@@ -96,192 +191,826 @@ impl Cpu {
         Ok(v)
     }
 
-    pub fn reset(&mut self) {
-        self.registers = Default::default();
-        self.flags = Default::default();
-        self.registers.PC = LittleEndian::read_u16(&self.memory[RESET_VECTOR..]);
+    /// Runs the Cpu for a number of clock cycles, firing scheduled events
+    /// as they fall due. The Cpu may be left part way through an
+    /// instruction.
+    pub fn run_for(&mut self, cycles: u64) -> CpuMultiStepResult {
+        for _ in 0..cycles {
+            self.tick()?;
+        }
+
+        Ok(cycles)
+    }
+
+    /// Performs a warm reset. This follows the hardware sequence: the Cpu
+    /// runs a fake interrupt where the stack pushes are turned into reads,
+    /// so the stack pointer drops by 3 without anything being written.
+    /// A, X, Y and the remaining flags are left as they were. Any
+    /// instruction part way through is abandoned. Returns the number of
+    /// cycles the sequence takes. A memory fault raised along the way is
+    /// returned by the next `tick`.
+    pub fn reset(&mut self) -> u8 {
+        let start = self.cycles;
+
+        self.begin_sequence(Sequence::Reset);
+        self.run_sequence();
+
+        (self.cycles - start) as u8
+    }
+
+    /// Simulates applying power to the Cpu. RAM, the registers and the
+    /// stack pointer come up holding the patterns described by `state`,
+    /// then the reset sequence runs. Code should be loaded after this
+    /// is called, as all of memory is overwritten.
+    pub fn power_on(&mut self, state: PowerOnState) -> u8 {
+        for (cell, byte) in self.memory.iter_mut().zip(PatternBytes::new(state.ram)) {
+            *cell = byte;
+        }
+
+        let mut registers = PatternBytes::new(state.registers);
+        self.registers.A = registers.next().unwrap();
+        self.registers.X = registers.next().unwrap();
+        self.registers.Y = registers.next().unwrap();
+        self.stack.pointer = registers.next().unwrap() as usize;
+        self.flags = registers.next().unwrap().into();
+        self.cycles = 0;
+
+        self.reset()
     }
 
-    /// Runs a single instruction of code through the Cpu
+    /// Turns recording of bus activity on or off. While it is on, every
+    /// read and write the Cpu makes during an instruction (including the
+    /// dummy reads and writes the real hardware performs) is available
+    /// from `bus_trace` until the next instruction begins.
+    pub fn set_bus_trace(&mut self, enabled: bool) {
+        self.trace_bus = enabled;
+        self.bus_trace.clear();
+    }
+
+    /// The bus accesses made by the most recent instruction, one per cycle
+    pub fn bus_trace(&self) -> &[BusAccess] {
+        &self.bus_trace
+    }
+
+    /// Runs a single instruction of code through the Cpu by ticking it
+    /// until it reaches the next instruction boundary. If the Cpu is part
+    /// way through an instruction, only the rest of that instruction is
+    /// run. Returns the number of cycles spent, including any penalties for
+    /// crossing pages or taking branches. If the Cpu halts because RDY or
+    /// RESET is held low, it returns early as no progress can be made.
     pub fn step(&mut self) -> CpuStepResult {
-        let byte = self.memory.read_byte(self.registers.PC);
-
-        if let Some(opcode) = OpCode::from_raw_byte(byte) {
-            let operand = self.get_operand_from_opcode(&opcode);
-
-            self.registers.PC += opcode.length as u16;
-
-            match opcode.mnemonic {
-                "ADC" => self.adc(&operand),
-                "AND" => self.and(&operand),
-                "ASL" => self.asl(&operand),
-                "BCC" => self.bcc(&operand),
-                "BCS" => self.bcs(&operand),
-                "BEQ" => self.beq(&operand),
-                "BIT" => self.bit(&operand),
-                "BMI" => self.bmi(&operand),
-                "BNE" => self.bne(&operand),
-                "BPL" => self.bpl(&operand),
-                "BRK" => self.brk(),
-                "BVC" => self.bvc(&operand),
-                "BVS" => self.bvs(&operand),
-                "CLC" => self.set_carry_flag(false),
-                "CLD" => self.set_decimal_flag(false),
-                "CLI" => self.set_interrupt_flag(false),
-                "CLV" => self.set_overflow_flag(false),
-                "CMP" => {
-                    let a = self.registers.A;
-                    self.compare(&operand, a)
-                }
-                "CPX" => {
-                    let x = self.registers.X;
-                    self.compare(&operand, x)
-                }
-                "CPY" => {
-                    let y = self.registers.Y;
-                    self.compare(&operand, y)
+        let start = self.cycles;
+
+        while !self.tick()? && !self.halted {}
+
+        Ok((self.cycles - start) as u8)
+    }
+
+    /// Runs a single clock cycle. Every cycle performs exactly one read or
+    /// write on the bus, unless the Cpu is halted. Returns true when the
+    /// cycle finished an instruction, meaning the next tick fetches a new
+    /// opcode.
+    ///
+    /// Scheduled events that are due run first, then the pins are sampled.
+    /// Events scheduled by those handlers run on a later tick, even if they
+    /// are already due. Interrupts are taken at instruction boundaries.
+    pub fn tick(&mut self) -> CpuTickResult {
+        if let Some(error) = self.pending_fault.take() {
+            return Err(error);
+        }
+
+        for handler in self.scheduler.take_due(self.cycles) {
+            handler(self);
+        }
+
+        let pins = self.pins;
+        let sampled = self.sampled;
+        self.sampled = pins;
+        self.halted = false;
+
+        if !pins.reset {
+            // Whatever was running is abandoned while RESET is held
+            self.sequence = None;
+            return Ok(self.halt());
+        }
+
+        if !sampled.reset {
+            self.begin_sequence(Sequence::Reset);
+        }
+
+        if sampled.so && !pins.so {
+            self.flags.overflow = true;
+        }
+
+        if sampled.nmi && !pins.nmi {
+            self.nmi_pending = true;
+        }
+
+        // The NMOS 6502 ignores RDY during write cycles. The 65C02 stops
+        // wherever it is.
+        if !pins.rdy && (self.variant == CpuVariant::Cmos65C02 || !self.next_cycle_writes()) {
+            return Ok(self.halt());
+        }
+
+        if self.sequence.is_none() {
+            if let Some(vector) = self.pending_interrupt() {
+                self.begin_sequence(Sequence::Interrupt(vector));
+            }
+        }
+
+        let done = match self.sequence {
+            Some(sequence) => self.sequence_cycle(sequence),
+            None => {
+                self.fetch_opcode()?;
+                false
+            }
+        };
+
+        self.check_memory_faults()?;
+
+        Ok(done)
+    }
+
+    /// The bad memory accesses recorded in regions with the `Log` policy
+    pub fn memory_faults(&self) -> &[MemoryFault] {
+        &self.memory_faults
+    }
+
+    pub fn clear_memory_faults(&mut self) {
+        self.memory_faults.clear();
+    }
+
+    /// The reads of uninitialized memory found so far. Instructions read
+    /// their operands, pointers and pulled stack bytes from memory; if
+    /// shadow memory is turned on with `MemoryBus::set_shadow_memory` and
+    /// any of them was never written, the read is reported here. Each
+    /// instruction is only reported once per address.
+    pub fn uninitialized_reads(&self) -> &[UninitializedRead] {
+        &self.uninitialized_reads
+    }
+
+    /// Removes and returns the reads of uninitialized memory found so far,
+    /// for collecting after a run
+    pub fn take_uninitialized_reads(&mut self) -> Vec<UninitializedRead> {
+        mem::take(&mut self.uninitialized_reads)
+    }
+
+    /// Starts recording which addresses are executed and which are written,
+    /// looking for self modifying code and code run from data. `segments`
+    /// are the segments the program was loaded from; executing anything
+    /// outside of them is flagged too, unless no segments are given.
+    pub fn start_code_analysis(&mut self, segments: &[CodeSegment]) {
+        self.code_analysis = Some(CodeAnalysis::new(segments));
+    }
+
+    /// What the code analysis has recorded so far, if it is running
+    pub fn code_report(&self) -> Option<CodeReport> {
+        self.code_analysis.as_ref().map(|analysis| analysis.report())
+    }
+
+    /// Stops the code analysis, returning what it recorded
+    pub fn stop_code_analysis(&mut self) -> Option<CodeReport> {
+        self.code_analysis.take().map(|analysis| analysis.report())
+    }
+
+    /// The subroutine calls and interrupts the Cpu is inside of, outermost
+    /// first. Every JSR, BRK, IRQ and NMI pushes a frame, and every RTS or
+    /// RTI that pulls the return address a frame pushed pops it again.
+    pub fn call_stack(&self) -> &[CallFrame] {
+        self.call_stack.frames()
+    }
+
+    /// The places code used the stack in ways that don't pair calls with
+    /// returns, such as returning with RTS to an address pushed by hand,
+    /// or pulling a return address off the stack with PLA. Each
+    /// instruction is only reported once per kind of anomaly.
+    pub fn stack_anomalies(&self) -> &[StackAnomaly] {
+        self.call_stack.anomalies()
+    }
+
+    /// Where the Cpu is, and how it got there: the current PC followed by
+    /// the call site of each frame on the call stack, innermost first. If
+    /// `symbols` is given, each address is also named after a label.
+    pub fn backtrace(&self, symbols: Option<&SymbolTable>) -> Vec<BacktraceFrame> {
+        self.call_stack.backtrace(self.registers.PC, symbols)
+    }
+
+    /// Execute the Non-Maskable Interrupt handler. This ignores the interrupt
+    /// flag and forces execution to the NMI. An instruction that is part way
+    /// through is finished first. A memory fault raised along the way is
+    /// returned by the next `tick`.
+    pub fn nmi(&mut self) {
+        self.run_sequence();
+
+        // Always handle an NMI ..unless its not set to something other
+        // than zero
+        if !self.vector_set(NMI_VECTOR) {
+            return;
+        }
+
+        self.begin_sequence(Sequence::Interrupt(NMI_VECTOR));
+        self.run_sequence();
+    }
+
+    /// Execute the Interrupt ReQuest handler if we currently are accepting
+    /// maskable interrupts. Ignore it otherwise. An instruction that is part
+    /// way through is finished first. A memory fault raised along the way is
+    /// returned by the next `tick`.
+    pub fn irq(&mut self) {
+        self.run_sequence();
+
+        if !self.accepts_irq() {
+            return;
+        }
+
+        self.begin_sequence(Sequence::Interrupt(IRQ_VECTOR));
+        self.run_sequence();
+    }
+
+    /// True when the last tick was spent halted, because RDY or RESET was
+    /// held low. The Cpu leaves the bus alone on those cycles, so a DMA
+    /// engine can use `dma_read` and `dma_write` in its place.
+    pub fn halted(&self) -> bool {
+        self.halted
+    }
+
+    /// Reads a byte over the bus on behalf of a DMA engine. This should only
+    /// be used on cycles where the Cpu is `halted`, and doesn't count as a
+    /// cycle of its own.
+    pub fn dma_read(&mut self, addr: u16) -> u8 {
+        let value = self.memory.read_byte(addr);
+
+        if self.trace_bus {
+            self.bus_trace.push(BusAccess::read(addr, value));
+        }
+
+        value
+    }
+
+    /// Writes a byte over the bus on behalf of a DMA engine. This should only
+    /// be used on cycles where the Cpu is `halted`, and doesn't count as a
+    /// cycle of its own.
+    pub fn dma_write(&mut self, addr: u16, byte: u8) {
+        self.memory.write_byte(addr, byte);
+        if let Some(ref mut analysis) = self.code_analysis {
+            analysis.write(self.sequence_pc, addr);
+        }
+
+        if self.trace_bus {
+            self.bus_trace.push(BusAccess::write(addr, byte));
+        }
+    }
+
+    /// The vector of the interrupt to run before the next instruction, if
+    /// the IRQ or NMI pins are asking for one
+    fn pending_interrupt(&mut self) -> Option<u16> {
+        if self.nmi_pending {
+            self.nmi_pending = false;
+
+            if self.vector_set(NMI_VECTOR) {
+                return Some(NMI_VECTOR);
+            }
+        }
+
+        let irq = !self.pins.irq || self.memory.irq();
+        if irq && self.accepts_irq() {
+            return Some(IRQ_VECTOR);
+        }
+
+        None
+    }
+
+    fn accepts_irq(&self) -> bool {
+        // If interrupts are disabled, don't worry about this
+        if self.flags.interrupt_disabled {
+            return false;
+        }
+
+        // ..or if the handler isn't set to something other than zero
+        self.vector_set(IRQ_VECTOR)
+    }
+
+    /// Whether the interrupt vector at `vector` holds something other than
+    /// zero, as seen through the bus. A vector a device can't peek at is
+    /// taken to be set.
+    fn vector_set(&self, vector: u16) -> bool {
+        match (self.memory.peek(vector), self.memory.peek(vector + 1)) {
+            (Some(low_byte), Some(high_byte)) => low_byte != 0 || high_byte != 0,
+            _ => true,
+        }
+    }
+
+    // ## Sequencing ##
+
+    /// Spends a cycle without touching the bus. Never the end of an
+    /// instruction.
+    fn halt(&mut self) -> bool {
+        self.end_cycle();
+        self.halted = true;
+
+        false
+    }
+
+    /// Whether the coming cycle of the current sequence is a write
+    fn next_cycle_writes(&self) -> bool {
+        let step = self.micro.step;
+
+        match self.sequence {
+            None | Some(Sequence::Reset) => false,
+            Some(Sequence::Interrupt(_)) => (2..=4).contains(&step),
+            Some(Sequence::Instruction(opcode)) => {
+                match opcode.mnemonic {
+                    "BRK" => (1..=3).contains(&step),
+                    "JSR" => step == 2 || step == 3,
+                    "PHA" | "PHP" => step == 1,
+                    mnemonic => {
+                        match (Cpu::access(mnemonic), self.micro.operate_from) {
+                            (Access::Write, Some(_)) => true,
+                            (Access::ReadModifyWrite, Some(start)) => {
+                                step - start == 2 ||
+                                (step - start == 1 && self.variant == CpuVariant::Nmos6502)
+                            }
+                            _ => false,
+                        }
+                    }
                 }
-                "DEC" => self.dec(&operand),
-                "DEX" => self.dex(),
-                "DEY" => self.dey(),
-                "EOR" => self.eor(&operand),
-                "INC" => self.inc(&operand),
-                "INX" => self.inx(),
-                "INY" => self.iny(),
-                "JMP" => self.jmp(&operand),
-                "JSR" => self.jsr(&operand),
-                "LDA" => self.lda(&operand),
-                "LDX" => self.ldx(&operand),
-                "LDY" => self.ldy(&operand),
-                "LSR" => self.lsr(&operand),
-                "NOP" => self.nop(),
-                "ORA" => self.ora(&operand),
-                "PHA" => self.pha(),
-                "PHP" => self.php(),
-                "PLA" => self.pla(),
-                "PLP" => self.plp(),
-                "ROL" => self.rol(&operand),
-                "ROR" => self.ror(&operand),
-                "RTI" => self.rti(),
-                "RTS" => self.rts(),
-                "SBC" => self.sbc(&operand),
-                "SEC" => self.set_carry_flag(true),
-                "SED" => self.set_decimal_flag(true),
-                "SEI" => self.set_interrupt_flag(true),
-                "STA" => self.sta(&operand),
-                "STX" => self.stx(&operand),
-                "STY" => self.sty(&operand),
-                "TAX" => self.tax(),
-                "TAY" => self.tay(),
-                "TSX" => self.tsx(),
-                "TXA" => self.txa(),
-                "TXS" => self.txs(),
-                "TYA" => self.tya(),
-                _ => return Err(CpuError::unknown_opcode(self.registers.PC, opcode.code)),
-            }
-
-            Ok(opcode.time)
+            }
+        }
+    }
+
+    fn fetch_opcode(&mut self) -> Result<(), CpuError> {
+        if self.trace_bus {
+            self.bus_trace.clear();
+        }
+
+        let pc = self.registers.PC;
+        let byte = self.bus_read(pc);
+        self.sequence_pc = pc;
+        self.sequence_sp = self.stack.pointer as u8;
+        self.memory.check_execute(pc, byte);
+        self.record_execute(pc, true);
+
+        match OpCode::from_raw_byte(byte) {
+            Some(opcode) => {
+                self.registers.PC = pc.wrapping_add(1);
+                self.sequence = Some(Sequence::Instruction(*opcode));
+                self.micro = Default::default();
+
+                Ok(())
+            }
+            None => Err(CpuError::unknown_opcode(pc, byte)),
+        }
+    }
+
+    fn begin_sequence(&mut self, sequence: Sequence) {
+        if self.trace_bus {
+            self.bus_trace.clear();
+        }
+
+        self.sequence = Some(sequence);
+        self.sequence_pc = self.registers.PC;
+        self.sequence_sp = self.stack.pointer as u8;
+        self.micro = Default::default();
+    }
+
+    /// Logs the faults the last cycle caused on the bus, and turns the
+    /// first one that should be raised into an error
+    fn check_memory_faults(&mut self) -> Result<(), CpuError> {
+        let mut raised = None;
+
+        for mut fault in self.memory.take_faults() {
+            fault.pc = self.sequence_pc;
+
+            match fault.policy {
+                FaultPolicy::Log => self.memory_faults.push(fault),
+                FaultPolicy::Raise => raised = raised.or(Some(fault)),
+                FaultPolicy::Ignore => (),
+            }
+        }
+
+        match raised {
+            Some(fault) => Err(CpuError::memory_fault(&fault)),
+            None => Ok(()),
+        }
+    }
+
+    /// Runs the current sequence, if there is one, to completion. The first
+    /// fault raised is kept for the next tick to return.
+    fn run_sequence(&mut self) {
+        while let Some(sequence) = self.sequence {
+            self.sequence_cycle(sequence);
+
+            if let Err(error) = self.check_memory_faults() {
+                self.pending_fault = self.pending_fault.take().or(Some(error));
+            }
+        }
+    }
+
+    /// Runs one cycle of a sequence. Returns true if it was the last one.
+    fn sequence_cycle(&mut self, sequence: Sequence) -> bool {
+        let done = match sequence {
+            Sequence::Instruction(opcode) => self.instruction_cycle(opcode),
+            Sequence::Interrupt(vector) => self.interrupt_cycle(vector),
+            Sequence::Reset => self.reset_cycle(),
+        };
+
+        if done {
+            self.sequence = None;
+            self.track_calls(sequence);
         } else {
-            Err(CpuError::unknown_opcode(self.registers.PC, byte))
+            self.micro.step += 1;
         }
+
+        done
+    }
+
+    /// Keeps the shadow call stack in step with the sequence that just
+    /// finished
+    fn track_calls(&mut self, sequence: Sequence) {
+        let pc = self.sequence_pc;
+        let sp = self.stack.pointer as u8;
+        let target = self.registers.PC;
+        let frame = |kind, return_addr| {
+            CallFrame {
+                kind,
+                call_site: pc,
+                target,
+                return_addr,
+                stack_pointer: sp,
+            }
+        };
+
+        match sequence {
+            Sequence::Reset => {
+                self.call_stack.clear();
+                return;
+            }
+            Sequence::Interrupt(_) => {
+                let frame = frame(FrameKind::Interrupt, pc);
+                self.call_stack.call(frame);
+            }
+            Sequence::Instruction(opcode) => {
+                match opcode.mnemonic {
+                    "JSR" => {
+                        let frame = frame(FrameKind::Subroutine, pc.wrapping_add(3));
+                        self.call_stack.call(frame);
+                    }
+                    // BRK does nothing while interrupts are masked
+                    "BRK" if sp != self.sequence_sp => {
+                        let frame = frame(FrameKind::Interrupt, pc.wrapping_add(2));
+                        self.call_stack.call(frame);
+                    }
+                    "RTS" => self.call_stack.ret(FrameKind::Subroutine, pc, self.sequence_sp),
+                    "RTI" => self.call_stack.ret(FrameKind::Interrupt, pc, self.sequence_sp),
+                    _ => (),
+                }
+            }
+        }
+
+        self.call_stack.stack_moved(pc, sp);
     }
 
-    fn get_operand_from_opcode(&self, opcode: &OpCode) -> Operand {
-        use ::opcodes::AddressingMode::*;
+    fn reset_cycle(&mut self) -> bool {
+        match self.micro.step {
+            0 | 1 => {
+                self.dummy_read_pc();
+                false
+            }
+            2..=4 => {
+                // The pushes of an interrupt, with the write line held high
+                self.dummy_read_stack();
+                self.stack.pointer = self.stack.pointer.wrapping_sub(0x01) & 0xFF;
+                false
+            }
+            5 => {
+                self.flags.interrupt_disabled = true;
+                self.micro.addr = self.bus_read(RESET_VECTOR) as u16;
+                false
+            }
+            _ => {
+                let high_byte = self.bus_read(RESET_VECTOR + 1) as u16;
+                self.registers.PC = (high_byte << 8) | self.micro.addr;
+                true
+            }
+        }
+    }
 
-        let operand_start = self.registers.PC + 1;
+    fn interrupt_cycle(&mut self, vector: u16) -> bool {
+        match self.micro.step {
+            0 | 1 => {
+                self.dummy_read_pc();
+                false
+            }
+            step => self.vector_cycle(step - 2, vector),
+        }
+    }
 
-        match opcode.mode {
-            Unknown => unreachable!(),
-            Implied => Operand::Implied,
-            Immediate => Operand::Immediate(self.read_byte(operand_start)),
-            Relative => Operand::Immediate(self.read_byte(operand_start)),
-            Accumulator => Operand::Implied,
-            ZeroPage => Operand::Memory((self.read_byte(operand_start) as u16) & 0xFF),
-            ZeroPageX => {
-                Operand::Memory((self.registers.X as u16 + self.read_byte(operand_start) as u16) &
-                                0xFF)
+    /// The tail shared by BRK and hardware interrupts: push the return
+    /// address and flags, then jump through `vector`
+    fn vector_cycle(&mut self, step: u8, vector: u16) -> bool {
+        match step {
+            0 => {
+                let pc = self.registers.PC;
+                self.push((pc >> 8) as u8);
+                false
+            }
+            1 => {
+                let pc = self.registers.PC;
+                self.push(pc as u8);
+                false
             }
-            ZeroPageY => {
-                Operand::Memory((self.registers.Y as u16 + self.read_byte(operand_start) as u16) &
-                                0xFF)
+            2 => {
+                let flags = self.flags.to_u8();
+                self.push(flags);
+                false
             }
-            Absolute => Operand::Memory(self.read_u16(operand_start)),
-            AbsoluteX => Operand::Memory(self.registers.X as u16 + self.read_u16(operand_start)),
-            AbsoluteY => Operand::Memory(self.registers.Y as u16 + self.read_u16(operand_start)),
-            Indirect => Operand::Memory(self.read_u16(self.read_u16(operand_start))),
-            IndirectX => {
-                Operand::Memory(self.read_u16((self.registers.X as u16 +
-                                               self.read_byte(self.registers.PC + 1) as u16) &
-                                              0xFF))
+            3 => {
+                self.flags.interrupt_disabled = true;
+                self.micro.addr = self.bus_read(vector) as u16;
+                false
             }
-            IndirectY => {
-                Operand::Memory(self.registers.Y as u16 +
-                                self.read_u16(self.read_byte(self.registers.PC + 1) as u16))
+            _ => {
+                let high_byte = self.bus_read(vector + 1) as u16;
+                self.registers.PC = (high_byte << 8) | self.micro.addr;
+                true
             }
         }
     }
 
-    fn unwrap_immediate(&self, operand: &Operand) -> u8 {
-        match *operand {
-            Operand::Immediate(byte) => byte,
-            Operand::Memory(addr) => self.read_byte(addr),
-            Operand::Implied => 0,
+    fn instruction_cycle(&mut self, opcode: OpCode) -> bool {
+        use ::opcodes::AddressingMode::*;
+
+        match opcode.mnemonic {
+            "BCC" | "BCS" | "BEQ" | "BMI" | "BNE" | "BPL" | "BVC" | "BVS" => {
+                self.branch_cycle(opcode.mnemonic)
+            }
+            "BRK" => self.brk_cycle(),
+            "JMP" => self.jmp_cycle(opcode.mode),
+            "JSR" => self.jsr_cycle(),
+            "PHA" | "PHP" => self.push_cycle(opcode.mnemonic),
+            "PLA" | "PLP" => self.pull_cycle(opcode.mnemonic),
+            "RTI" => self.rti_cycle(),
+            "RTS" => self.rts_cycle(),
+            _ => {
+                match opcode.mode {
+                    Implied | Accumulator => {
+                        // The byte after the opcode is read and ignored
+                        self.dummy_read_pc();
+                        self.execute_implied(opcode.mnemonic);
+                        true
+                    }
+                    Immediate => {
+                        let value = self.fetch_byte();
+                        self.execute_read(opcode.mnemonic, value);
+                        true
+                    }
+                    _ => self.memory_cycle(opcode),
+                }
+            }
         }
     }
 
-    fn unwrap_address(&self, operand: &Operand) -> u16 {
-        match *operand {
-            Operand::Immediate(byte) => byte as u16,
-            Operand::Memory(addr) => addr,
-            Operand::Implied => 0,
+    // ## Addressing modes ##
+
+    /// Runs a cycle of an instruction with an operand in memory. The
+    /// effective address is worked out first, then the operation reads,
+    /// writes or modifies it.
+    fn memory_cycle(&mut self, opcode: OpCode) -> bool {
+        let access = Cpu::access(opcode.mnemonic);
+
+        let start = match self.micro.operate_from {
+            Some(start) => start,
+            None => {
+                self.address_cycle(opcode.mode, access);
+                return false;
+            }
+        };
+
+        let addr = self.micro.addr;
+        match (access, self.micro.step - start) {
+            (Access::Read, _) => {
+                let value = self.checked_read(addr);
+                self.execute_read(opcode.mnemonic, value);
+                true
+            }
+            (Access::Write, _) => {
+                let value = self.stored_value(opcode.mnemonic);
+                self.bus_write(addr, value);
+                true
+            }
+            (Access::ReadModifyWrite, 0) => {
+                self.micro.value = self.checked_read(addr);
+                false
+            }
+            (Access::ReadModifyWrite, 1) => {
+                // While the result is worked out the NMOS 6502 writes the
+                // unmodified value back. The 65C02 reads it a second time.
+                let value = self.micro.value;
+                if self.variant == CpuVariant::Nmos6502 {
+                    self.dummy_write(addr, value);
+                } else {
+                    self.dummy_read(addr);
+                }
+                false
+            }
+            (Access::ReadModifyWrite, _) => {
+                let value = self.micro.value;
+                let result = self.modify(opcode.mnemonic, value);
+                self.bus_write(addr, result);
+                true
+            }
         }
     }
 
-    /// Execute the Non-Maskable Interrupt handler. This ignores the interrupt
-    /// flag and forces execution to the NMI
-    pub fn nmi(&mut self) {
-        // Always handle an NMI
-        let handler_addr = LittleEndian::read_u16(&self.memory[NMI_VECTOR..]);
+    /// Runs a cycle of effective address calculation, making the same
+    /// dummy reads the 6502 makes while it does so
+    fn address_cycle(&mut self, mode: AddressingMode, access: Access) {
+        use ::opcodes::AddressingMode::*;
 
-        // ..unless its not set to something other than zero:
-        if handler_addr == 0 {
-            return;
+        match (mode, self.micro.step) {
+            (ZeroPage, _) => {
+                self.micro.addr = self.fetch_byte() as u16;
+                self.address_ready();
+            }
+            (ZeroPageX, 0) | (ZeroPageY, 0) |
+            (Absolute, 0) |
+            (AbsoluteX, 0) | (AbsoluteY, 0) => {
+                self.micro.base = self.fetch_byte() as u16;
+            }
+            (ZeroPageX, _) | (ZeroPageY, _) => {
+                // The unindexed address is read while the index is added
+                let base = self.micro.base;
+                self.dummy_read(base);
+
+                let index = if mode == ZeroPageX {
+                    self.registers.X
+                } else {
+                    self.registers.Y
+                };
+                self.micro.addr = (base as u8).wrapping_add(index) as u16;
+                self.address_ready();
+            }
+            (Absolute, _) => {
+                let high_byte = self.fetch_byte() as u16;
+                self.micro.addr = (high_byte << 8) | self.micro.base;
+                self.address_ready();
+            }
+            (AbsoluteX, 1) | (AbsoluteY, 1) => {
+                let high_byte = self.fetch_byte() as u16;
+                self.micro.base |= high_byte << 8;
+
+                let index = if mode == AbsoluteX {
+                    self.registers.X
+                } else {
+                    self.registers.Y
+                };
+                self.index_address(index, access);
+            }
+            (IndirectX, 0) | (IndirectY, 0) => {
+                self.micro.pointer = self.fetch_byte();
+            }
+            (IndirectX, 1) => {
+                let pointer = self.micro.pointer;
+                self.dummy_read(pointer as u16);
+                self.micro.pointer = pointer.wrapping_add(self.registers.X);
+            }
+            (IndirectX, 2) | (IndirectY, 1) => {
+                let pointer = self.micro.pointer;
+                self.micro.base = self.checked_read(pointer as u16) as u16;
+            }
+            (IndirectX, _) => {
+                // The 6502 never carries into the high byte of a zero page
+                // pointer, so a pointer at $FF takes its high byte from $00
+                let pointer = self.micro.pointer.wrapping_add(1);
+                let high_byte = self.checked_read(pointer as u16) as u16;
+                self.micro.addr = (high_byte << 8) | self.micro.base;
+                self.address_ready();
+            }
+            (IndirectY, 2) => {
+                let pointer = self.micro.pointer.wrapping_add(1);
+                let high_byte = self.checked_read(pointer as u16) as u16;
+                self.micro.base |= high_byte << 8;
+
+                let y = self.registers.Y;
+                self.index_address(y, access);
+            }
+            (AbsoluteX, _) | (AbsoluteY, _) | (IndirectY, _) => {
+                // Fix up the high byte, reading from the wrong page first
+                let unfixed = (self.micro.base & 0xFF00) | (self.micro.addr & 0x00FF);
+                self.dummy_read(unfixed);
+                self.address_ready();
+            }
+            _ => unreachable!(),
         }
-        let mem = &mut self.memory[STACK_START..STACK_END + 0x01];
+    }
+
+    /// Adds an index register to the base address. The 6502 adds the index
+    /// to the low byte first and reads from that (possibly wrong) address
+    /// while it fixes up the high byte. Reads that don't cross a page skip
+    /// the fix-up cycle; writes always pay for it.
+    fn index_address(&mut self, index: u8, access: Access) {
+        let base = self.micro.base;
+        let addr = base.wrapping_add(index as u16);
+        self.micro.addr = addr;
 
-        self.stack.push_u16(mem, self.registers.PC);
-        self.stack.push(mem, self.flags.to_u8());
-        self.flags.interrupt_disabled = true;
-        self.registers.PC = handler_addr;
+        if addr & 0xFF00 == base & 0xFF00 && access == Access::Read {
+            self.address_ready();
+        }
     }
 
-    /// Execute the Interrupt ReQuest handler if we currently are accepting
-    /// maskable interrupts. Ignore it otherwise.
-    pub fn irq(&mut self) {
-        // If interrupts are disabled, don't worry about this
-        if self.flags.interrupt_disabled {
-            return;
+    fn address_ready(&mut self) {
+        self.micro.operate_from = Some(self.micro.step + 1);
+    }
+
+    fn access(mnemonic: &str) -> Access {
+        match mnemonic {
+            "STA" | "STX" | "STY" => Access::Write,
+            "ASL" | "LSR" | "ROL" | "ROR" | "INC" | "DEC" => Access::ReadModifyWrite,
+            _ => Access::Read,
         }
+    }
 
-        let handler_addr = LittleEndian::read_u16(&self.memory[IRQ_VECTOR..]);
+    // ## Operations ##
 
-        // ..unless its not set to something other than zero:
-        if handler_addr == 0 {
-            return;
+    fn execute_read(&mut self, mnemonic: &str, value: u8) {
+        match mnemonic {
+            "ADC" => self.adc(value),
+            "AND" => self.and(value),
+            "BIT" => self.bit(value),
+            "CMP" => {
+                let a = self.registers.A;
+                self.compare(value, a)
+            }
+            "CPX" => {
+                let x = self.registers.X;
+                self.compare(value, x)
+            }
+            "CPY" => {
+                let y = self.registers.Y;
+                self.compare(value, y)
+            }
+            "EOR" => self.eor(value),
+            "LDA" => self.lda(value),
+            "LDX" => self.ldx(value),
+            "LDY" => self.ldy(value),
+            "ORA" => self.ora(value),
+            "SBC" => self.sbc(value),
+            _ => unreachable!(),
         }
+    }
 
-        let mem = &mut self.memory[STACK_START..STACK_END + 0x01];
+    fn stored_value(&self, mnemonic: &str) -> u8 {
+        match mnemonic {
+            "STA" => self.registers.A,
+            "STX" => self.registers.X,
+            "STY" => self.registers.Y,
+            _ => unreachable!(),
+        }
+    }
 
-        self.stack.push_u16(mem, self.registers.PC);
-        self.stack.push(mem, self.flags.to_u8());
-        self.flags.interrupt_disabled = true;
-        self.registers.PC = handler_addr;
+    fn modify(&mut self, mnemonic: &str, value: u8) -> u8 {
+        match mnemonic {
+            "ASL" => self.asl(value),
+            "DEC" => self.dec(value),
+            "INC" => self.inc(value),
+            "LSR" => self.lsr(value),
+            "ROL" => self.rol(value),
+            "ROR" => self.ror(value),
+            _ => unreachable!(),
+        }
+    }
+
+    fn execute_implied(&mut self, mnemonic: &str) {
+        match mnemonic {
+            "ASL" | "LSR" | "ROL" | "ROR" => {
+                let a = self.registers.A;
+                self.registers.A = self.modify(mnemonic, a);
+            }
+            "CLC" => self.set_carry_flag(false),
+            "CLD" => self.set_decimal_flag(false),
+            "CLI" => self.set_interrupt_flag(false),
+            "CLV" => self.set_overflow_flag(false),
+            "DEX" => self.dex(),
+            "DEY" => self.dey(),
+            "INX" => self.inx(),
+            "INY" => self.iny(),
+            "NOP" => self.nop(),
+            "SEC" => self.set_carry_flag(true),
+            "SED" => self.set_decimal_flag(true),
+            "SEI" => self.set_interrupt_flag(true),
+            "TAX" => self.tax(),
+            "TAY" => self.tay(),
+            "TSX" => self.tsx(),
+            "TXA" => self.txa(),
+            "TXS" => self.txs(),
+            "TYA" => self.tya(),
+            _ => unreachable!(),
+        }
     }
 
     // ## OpCode handlers ##
 
-    fn adc(&mut self, operand: &Operand) {
+    fn adc(&mut self, value: u8) {
         // This is implemented on the information provided here:
         // http://www.electrical4u.com/bcd-or-binary-coded-decimal-bcd-conversion-addition-subtraction/
         // and here:
@@ -293,11 +1022,11 @@ impl Cpu {
 
         let carry = if self.flags.carry { 1 } else { 0 };
 
-        let value = self.unwrap_immediate(&operand) as u16;
+        let value = value as u16;
         let value_signs = self.registers.A & 0x80 == 0x80 && value & 0x80 == 0x80;
 
         // Do normal binary arithmetic first
-        let mut result = self.registers.A as u16 + value as u16 + carry as u16;
+        let mut result = self.registers.A as u16 + value + carry;
 
         // Handle packed binary coded decimal
         if self.flags.decimal {
@@ -311,78 +1040,82 @@ impl Cpu {
         }
 
         self.flags.carry = (result & 0x100) == 0x100;
-        self.flags.zero = result as u8 & 0xFF == 0x00;
+        self.flags.zero = result & 0xFF == 0x00;
         self.flags.sign = result & 0x80 == 0x80;
 
         if self.flags.sign != value_signs {
             self.flags.overflow = true;
         }
 
-        self.registers.A = result as u8 & 0xFF;
+        self.registers.A = result as u8;
     }
 
-    fn and(&mut self, operand: &Operand) {
-        let value = self.unwrap_immediate(&operand);
+    fn and(&mut self, value: u8) {
         let result = self.registers.A & value;
 
         self.registers.A = result;
 
-        self.flags.zero = result as u8 & 0xFF == 0;
+        self.flags.zero = result == 0;
         self.flags.sign = result & 0x80 == 0x80;
     }
 
-    fn asl(&mut self, operand: &Operand) {
-        let mut value = if let &Operand::Implied = operand {
-            // Implied ASL uses the A register
-            self.registers.A
-        } else {
-            self.unwrap_immediate(&operand)
-        };
-
+    fn asl(&mut self, value: u8) -> u8 {
         // Test the seventh bit - if its set, shift it
         // into the carry flag
         self.flags.carry = (value & 0x80) == 0x80;
 
         // Shift the value left
-        value = value << 0x01;
+        let value = value << 0x01;
         self.flags.sign = value & 0x80 == 0x80;
-        self.flags.zero = value as u8 & 0xFF == 0;
+        self.flags.zero = value == 0;
 
-        if let &Operand::Implied = operand {
-            self.registers.A = value;
-        } else {
-            let addr = self.unwrap_address(&operand);
-            self.write_byte(addr, value);
-        }
+        value
     }
 
-    fn bcc(&mut self, operand: &Operand) {
-        // Branch if the carry flag is not set
-        if !self.flags.carry {
-            let offset = self.unwrap_immediate(&operand);
-            self.relative_jump(offset);
-        }
-    }
+    fn branch_cycle(&mut self, mnemonic: &str) -> bool {
+        match self.micro.step {
+            0 => {
+                self.micro.value = self.fetch_byte();
+                !self.branch_condition(mnemonic)
+            }
+            1 => {
+                // Taking the branch costs a cycle reading the next opcode
+                // while the low byte of the PC is adjusted..
+                let pc = self.registers.PC;
+                self.dummy_read(pc);
+                self.micro.addr = pc;
+
+                let offset = self.micro.value;
+                self.relative_jump(offset);
 
-    fn bcs(&mut self, operand: &Operand) {
-        // Branch if the carry flag is set
-        if self.flags.carry {
-            let offset = self.unwrap_immediate(&operand);
-            self.relative_jump(offset);
+                self.registers.PC & 0xFF00 == pc & 0xFF00
+            }
+            _ => {
+                // ..and another reading from the wrong page if the high
+                // byte needs fixing up
+                let unfixed = (self.micro.addr & 0xFF00) | (self.registers.PC & 0x00FF);
+                self.dummy_read(unfixed);
+                true
+            }
         }
     }
 
-    fn beq(&mut self, operand: &Operand) {
-        // Branch if the zero flag is set
-        if self.flags.zero {
-            let offset = self.unwrap_immediate(&operand);
-            self.relative_jump(offset);
+    fn branch_condition(&self, mnemonic: &str) -> bool {
+        match mnemonic {
+            "BCC" => !self.flags.carry,
+            "BCS" => self.flags.carry,
+            "BEQ" => self.flags.zero,
+            "BMI" => self.flags.sign,
+            "BNE" => !self.flags.zero,
+            "BPL" => !self.flags.sign,
+            "BVC" => !self.flags.overflow,
+            "BVS" => self.flags.overflow,
+            _ => unreachable!(),
         }
     }
 
-    fn bit(&mut self, operand: &Operand) {
+    fn bit(&mut self, value: u8) {
         let a = self.registers.A;
-        let value = self.unwrap_immediate(&operand);
         let result = value & a;
 
         self.flags.zero = result == 0x00;
@@ -390,48 +1123,16 @@ impl Cpu {
         self.flags.sign = value & 0x80 == 0x80;
     }
 
-    fn bmi(&mut self, operand: &Operand) {
-        // Branch if the sign flag is set
-        if self.flags.sign {
-            let offset = self.unwrap_immediate(&operand);
-            self.relative_jump(offset);
-        }
-    }
-
-    fn bne(&mut self, operand: &Operand) {
-        // Branch if the zero flag is not set
-        if !self.flags.zero {
-            let offset = self.unwrap_immediate(&operand);
-            self.relative_jump(offset);
-        }
-    }
+    fn brk_cycle(&mut self) -> bool {
+        match self.micro.step {
+            0 => {
+                // BRK skips over the byte that follows it
+                self.fetch_byte();
 
-    fn bpl(&mut self, operand: &Operand) {
-        // Branch if the sign flag is not set
-        if !self.flags.sign {
-            let offset = self.unwrap_immediate(&operand);
-            self.relative_jump(offset);
-        }
-    }
-
-    fn brk(&mut self) {
-        // Just call the IRQ handler - they're the same thing
-        self.irq();
-    }
-
-    fn bvc(&mut self, operand: &Operand) {
-        // Branch if the overflow flag is not set
-        if !self.flags.overflow {
-            let offset = self.unwrap_immediate(&operand);
-            self.relative_jump(offset);
-        }
-    }
-
-    fn bvs(&mut self, operand: &Operand) {
-        // Branch if the overflow flag is set
-        if self.flags.overflow {
-            let offset = self.unwrap_immediate(&operand);
-            self.relative_jump(offset);
+                // Then its the same as the IRQ handler
+                !self.accepts_irq()
+            }
+            step => self.vector_cycle(step - 1, IRQ_VECTOR),
         }
     }
 
@@ -451,8 +1152,7 @@ impl Cpu {
         self.flags.overflow = value;
     }
 
-    fn compare(&mut self, operand: &Operand, byte: u8) {
-        let value = self.unwrap_immediate(&operand);
+    fn compare(&mut self, value: u8, byte: u8) {
         let result: i16 = byte as i16 - value as i16;
 
         self.flags.carry = (result as u16) < 0x100;
@@ -460,182 +1160,266 @@ impl Cpu {
         self.flags.sign = result & 0x80 == 0x80;
     }
 
-    fn dec(&mut self, operand: &Operand) {
-        let value = self.unwrap_immediate(&operand);
-        let addr = self.unwrap_address(&operand);
-        let result = value - 1;
-
-        self.write_byte(addr, result);
+    fn dec(&mut self, value: u8) -> u8 {
+        let result = value.wrapping_sub(1);
 
         self.flags.sign = result & 0x80 == 0x80;
-        self.flags.zero = result & 0xFF == 0x00;
+        self.flags.zero = result == 0x00;
+
+        result
     }
 
     fn dex(&mut self) {
-        self.registers.X -= 0x01;
+        self.registers.X = self.registers.X.wrapping_sub(0x01);
 
         self.flags.sign = self.registers.X & 0x80 == 0x80;
-        self.flags.zero = self.registers.X & 0xFF == 0x00;
+        self.flags.zero = self.registers.X == 0x00;
     }
 
     fn dey(&mut self) {
-        self.registers.Y -= 0x01;
+        self.registers.Y = self.registers.Y.wrapping_sub(0x01);
 
         self.flags.sign = self.registers.Y & 0x80 == 0x80;
-        self.flags.zero = self.registers.Y & 0xFF == 0x00;
+        self.flags.zero = self.registers.Y == 0x00;
     }
 
-    fn eor(&mut self, operand: &Operand) {
-        let value = self.unwrap_immediate(&operand);
+    fn eor(&mut self, value: u8) {
         let result = self.registers.A ^ value;
 
         self.registers.A = result;
 
         self.flags.sign = result & 0x80 == 0x80;
-        self.flags.zero = result & 0xFF == 0x00;
+        self.flags.zero = result == 0x00;
     }
 
-    fn inc(&mut self, operand: &Operand) {
-        let value = self.unwrap_immediate(&operand);
-        let addr = self.unwrap_address(&operand);
-        let result = value + 1;
-
-        self.write_byte(addr, result);
+    fn inc(&mut self, value: u8) -> u8 {
+        let result = value.wrapping_add(1);
 
         self.flags.sign = result & 0x80 == 0x80;
-        self.flags.zero = result & 0xFF == 0x00;
+        self.flags.zero = result == 0x00;
+
+        result
     }
 
     fn inx(&mut self) {
-        self.registers.X += 0x01;
+        self.registers.X = self.registers.X.wrapping_add(0x01);
 
         self.flags.sign = self.registers.X & 0x80 == 0x80;
-        self.flags.zero = self.registers.X & 0xFF == 0x00;
+        self.flags.zero = self.registers.X == 0x00;
     }
 
     fn iny(&mut self) {
-        self.registers.Y += 0x01;
+        self.registers.Y = self.registers.Y.wrapping_add(0x01);
 
         self.flags.sign = self.registers.Y & 0x80 == 0x80;
-        self.flags.zero = self.registers.Y & 0xFF == 0x00;
+        self.flags.zero = self.registers.Y == 0x00;
     }
 
-    fn jmp(&mut self, operand: &Operand) {
-        let value = self.unwrap_address(&operand);
-        self.registers.PC = value;
+    fn jmp_cycle(&mut self, mode: AddressingMode) -> bool {
+        match self.micro.step {
+            0 => {
+                self.micro.addr = self.fetch_byte() as u16;
+                false
+            }
+            1 => {
+                let high_byte = self.fetch_byte() as u16;
+                self.micro.addr |= high_byte << 8;
+
+                if mode == AddressingMode::Absolute {
+                    self.registers.PC = self.micro.addr;
+                    true
+                } else {
+                    false
+                }
+            }
+            step => self.indirect_jump_cycle(step - 2),
+        }
     }
 
-    fn jsr(&mut self, operand: &Operand) {
-        let addr = self.unwrap_address(&operand);
-        let mut mem = &mut self.memory[STACK_START..STACK_END + 0x01];
+    /// Reads the target of an indirect JMP. The NMOS 6502 doesn't carry
+    /// into the high byte of the pointer, so JMP ($10FF) fetches its high
+    /// byte from $1000. The 65C02 fixed this, at the cost of a cycle.
+    fn indirect_jump_cycle(&mut self, step: u8) -> bool {
+        let step = if self.variant == CpuVariant::Nmos6502 {
+            step + 1
+        } else {
+            step
+        };
 
-        self.stack.push_u16(mem, self.registers.PC);
-        self.registers.PC = addr;
+        let addr = self.micro.addr;
+        match step {
+            0 => {
+                let pc = self.registers.PC.wrapping_sub(1);
+                self.dummy_read(pc);
+                false
+            }
+            1 => {
+                self.micro.base = self.checked_read(addr) as u16;
+                false
+            }
+            _ => {
+                let high_addr = if self.variant == CpuVariant::Nmos6502 {
+                    (addr & 0xFF00) | (addr.wrapping_add(1) & 0x00FF)
+                } else {
+                    addr.wrapping_add(1)
+                };
+                let high_byte = self.checked_read(high_addr) as u16;
+
+                self.registers.PC = (high_byte << 8) | self.micro.base;
+                true
+            }
+        }
     }
 
-    fn lda(&mut self, operand: &Operand) {
-        let value = self.unwrap_immediate(&operand);
+    fn jsr_cycle(&mut self) -> bool {
+        match self.micro.step {
+            0 => {
+                self.micro.addr = self.fetch_byte() as u16;
+                false
+            }
+            1 => {
+                // Internal cycle while the stack pointer is readied
+                self.dummy_read_stack();
+                false
+            }
+            2 => {
+                // The address pushed is that of the last byte of the JSR.
+                // RTS adds one to it on the way back.
+                let pc = self.registers.PC;
+                self.push((pc >> 8) as u8);
+                false
+            }
+            3 => {
+                let pc = self.registers.PC;
+                self.push(pc as u8);
+                false
+            }
+            _ => {
+                // The last byte of the JSR is only fetched once the return
+                // address is on the stack
+                let pc = self.registers.PC;
+                let high_byte = self.bus_read(pc) as u16;
+                self.record_execute(pc, false);
+                self.registers.PC = (high_byte << 8) | self.micro.addr;
+                true
+            }
+        }
+    }
 
+    fn lda(&mut self, value: u8) {
         self.registers.A = value;
         self.flags.sign = value & 0x80 == 0x80;
-        self.flags.zero = value & 0xFF == 0x00;
+        self.flags.zero = value == 0x00;
     }
 
-    fn ldx(&mut self, operand: &Operand) {
-        let value = self.unwrap_immediate(&operand);
-
+    fn ldx(&mut self, value: u8) {
         self.registers.X = value;
         self.flags.sign = value & 0x80 == 0x80;
-        self.flags.zero = value & 0xFF == 0x00;
+        self.flags.zero = value == 0x00;
     }
 
-    fn ldy(&mut self, operand: &Operand) {
-        let value = self.unwrap_immediate(&operand);
-
+    fn ldy(&mut self, value: u8) {
         self.registers.Y = value;
         self.flags.sign = value & 0x80 == 0x80;
-        self.flags.zero = value & 0xFF == 0x00;
+        self.flags.zero = value == 0x00;
     }
 
-    fn lsr(&mut self, operand: &Operand) {
-        // Accumulator is the implied register here
-        let value = if let &Operand::Implied = operand {
-            self.registers.A
-        } else {
-            self.unwrap_immediate(&operand)
-        };
-
+    fn lsr(&mut self, value: u8) -> u8 {
         self.flags.carry = value & 0x01 == 0x01;
 
         let value = value >> 0x01;
 
         self.flags.sign = value & 0x80 == 0x80;
-        self.flags.zero = value & 0xFF == 0x00;
+        self.flags.zero = value == 0x00;
 
-        if let &Operand::Implied = operand {
-            self.registers.A = value;
-        } else {
-            let addr = self.unwrap_address(&operand);
-            self.memory.write_byte(addr, value);
-        }
+        value
     }
 
-    fn nop(&self) {
+    fn nop(&mut self) {
         // Nothing. No Operation.
     }
 
-    fn ora(&mut self, operand: &Operand) {
-        let value = self.unwrap_immediate(&operand);
+    fn ora(&mut self, value: u8) {
         let result = self.registers.A | value;
 
         self.flags.sign = result & 0x80 == 0x80;
-        self.flags.zero = result & 0xFF == 0x00;
+        self.flags.zero = result == 0x00;
 
         self.registers.A = result;
     }
 
-    fn pha(&mut self) {
-        let mut mem = &mut self.memory[STACK_START..STACK_END + 0x01];
-
-        self.stack.push(mem, self.registers.A).unwrap();
-    }
-
-    fn php(&mut self) {
-        let mut mem = &mut self.memory[STACK_START..STACK_END + 0x01];
-
-        self.stack.push(mem, self.flags.to_u8()).unwrap();
-    }
-
-    fn pla(&mut self) {
-        let mem = &mut self.memory[STACK_START..STACK_END + 0x01];
-
-        let value = self.stack.pop(mem).unwrap();
-
-        self.registers.A = value;
+    /// PHA and PHP
+    fn push_cycle(&mut self, mnemonic: &str) -> bool {
+        match self.micro.step {
+            0 => {
+                self.dummy_read_pc();
+                false
+            }
+            _ => {
+                let value = if mnemonic == "PHA" {
+                    self.registers.A
+                } else {
+                    self.flags.to_u8()
+                };
+                self.push(value);
+                true
+            }
+        }
     }
 
-    fn plp(&mut self) {
-        let mem = &mut self.memory[STACK_START..STACK_END + 0x01];
-
-        let value = self.stack.pop(mem).unwrap();
-
-        self.flags = value.into();
+    /// PLA and PLP
+    fn pull_cycle(&mut self, mnemonic: &str) -> bool {
+        match self.micro.step {
+            0 => {
+                self.dummy_read_pc();
+                false
+            }
+            1 => {
+                self.dummy_read_stack();
+                false
+            }
+            _ => {
+                let value = self.pop();
+                if mnemonic == "PLA" {
+                    self.registers.A = value;
+                } else {
+                    self.flags = value.into();
+                }
+                true
+            }
+        }
     }
 
-    fn rts(&mut self) {
-        let mut mem = &mut self.memory[STACK_START..STACK_END + 0x01];
-        let addr = self.stack.pop_u16(mem).unwrap();
-
-        self.registers.PC = addr;
+    fn rts_cycle(&mut self) -> bool {
+        match self.micro.step {
+            0 => {
+                self.dummy_read_pc();
+                false
+            }
+            1 => {
+                self.dummy_read_stack();
+                false
+            }
+            2 => {
+                self.micro.addr = self.pop() as u16;
+                false
+            }
+            3 => {
+                let high_byte = self.pop() as u16;
+                self.micro.addr |= high_byte << 8;
+                false
+            }
+            _ => {
+                // Step over the last byte of the JSR
+                let addr = self.micro.addr;
+                self.dummy_read(addr);
+                self.registers.PC = addr.wrapping_add(1);
+                true
+            }
+        }
     }
 
-    fn rol(&mut self, operand: &Operand) {
-        let value = if let &Operand::Implied = operand {
-            self.registers.A
-        } else {
-            self.unwrap_immediate(&operand)
-        };
-
+    fn rol(&mut self, value: u8) -> u8 {
         let carry = value & 0x80 == 0x80;
 
         let value = if self.flags.carry {
@@ -646,22 +1430,12 @@ impl Cpu {
 
         self.flags.carry = carry;
         self.flags.sign = value & 0x80 == 0x80;
-        self.flags.zero = value & 0xFF == 0x00;
+        self.flags.zero = value == 0x00;
 
-        if let &Operand::Implied = operand {
-            self.registers.A = value;
-        } else {
-            let addr = self.unwrap_address(&operand);
-            self.memory.write_byte(addr, value);
-        }
+        value
     }
-    fn ror(&mut self, operand: &Operand) {
-        let value = if let &Operand::Implied = operand {
-            self.registers.A
-        } else {
-            self.unwrap_immediate(&operand)
-        };
 
+    fn ror(&mut self, value: u8) -> u8 {
         let carry = value & 0x01 == 0x01;   // Carry flag is the low bit in a ROR
 
         let value = if self.flags.carry {
@@ -672,36 +1446,48 @@ impl Cpu {
 
         self.flags.carry = carry;
         self.flags.sign = value & 0x80 == 0x80;
-        self.flags.zero = value & 0xFF == 0x00;
+        self.flags.zero = value == 0x00;
 
-        if let &Operand::Implied = operand {
-            self.registers.A = value;
-        } else {
-            let addr = self.unwrap_address(&operand);
-            self.memory.write_byte(addr, value);
-        }
+        value
     }
 
-    fn rti(&mut self) {
-        let mem = &mut self.memory[STACK_START..STACK_END + 0x01];
-
-        let value = self.stack.pop(mem).expect("ERR: Returning from an interrupt with an empty stack. Did you forget to set the interrupt handler address?");
-        let pc = self.stack.pop_u16(mem).expect("ERR: Returning from an interrupt with an empty stack. Did you forget to set the interrupt handler address?");
-
-        self.flags = value.into();
-        self.registers.PC = pc;
+    fn rti_cycle(&mut self) -> bool {
+        match self.micro.step {
+            0 => {
+                self.dummy_read_pc();
+                false
+            }
+            1 => {
+                self.dummy_read_stack();
+                false
+            }
+            2 => {
+                let value = self.pop();
+                self.flags = value.into();
+                false
+            }
+            3 => {
+                self.micro.addr = self.pop() as u16;
+                false
+            }
+            _ => {
+                let high_byte = self.pop() as u16;
+                self.registers.PC = (high_byte << 8) | self.micro.addr;
+                true
+            }
+        }
     }
 
-    fn sbc(&mut self, operand: &Operand) {
+    fn sbc(&mut self, value: u8) {
         let carry = if self.flags.carry { 0 } else { 1 };
 
-        let value = self.unwrap_immediate(&operand) as i16;
+        let value = value as i16;
         let value_signs = self.registers.A & 0x80 == 0x80 && value & 0x80 == 0x80;
 
         // Do normal binary arithmetic first
-        let mut result = self.registers.A as i16 - value as i16 - carry as i16;
+        let mut result = self.registers.A as i16 - value - carry;
 
-        self.flags.zero = result as u8 & 0xFF == 0x00;
+        self.flags.zero = result & 0xFF == 0x00;
         self.flags.sign = result & 0x80 == 0x80;
 
         if self.flags.sign != value_signs {
@@ -709,7 +1495,7 @@ impl Cpu {
         }
 
         if self.flags.decimal {
-            if (((self.registers.A as i16) & 0x0F) - carry as i16) < ((value as i16) & 0x0F) {
+            if ((self.registers.A as i16) & 0x0F) - carry < value & 0x0F {
                 result -= 0x06;
             }
             if (result as u16) > 0x99 {
@@ -721,39 +1507,18 @@ impl Cpu {
         self.registers.A = result as u8;
     }
 
-    fn sta(&mut self, operand: &Operand) {
-        let addr = self.unwrap_address(&operand);
-        let value = self.registers.A;
-
-        self.write_byte(addr, value);
-    }
-
-    fn stx(&mut self, operand: &Operand) {
-        let addr = self.unwrap_address(&operand);
-        let value = self.registers.X;
-
-        self.write_byte(addr, value);
-    }
-
-    fn sty(&mut self, operand: &Operand) {
-        let addr = self.unwrap_address(&operand);
-        let value = self.registers.Y;
-
-        self.write_byte(addr, value);
-    }
-
     fn tax(&mut self) {
         self.registers.X = self.registers.A;
 
         self.flags.sign = self.registers.A & 0x80 == 0x80;
-        self.flags.zero = self.registers.A & 0xFF == 0x00;
+        self.flags.zero = self.registers.A == 0x00;
     }
 
     fn tay(&mut self) {
         self.registers.Y = self.registers.A;
 
         self.flags.sign = self.registers.A & 0x80 == 0x80;
-        self.flags.zero = self.registers.A & 0xFF == 0x00;
+        self.flags.zero = self.registers.A == 0x00;
     }
 
     fn tsx(&mut self) {
@@ -761,14 +1526,14 @@ impl Cpu {
         self.registers.X = value;
 
         self.flags.sign = value & 0x80 == 0x80;
-        self.flags.zero = value & 0xFF == 0x00;
+        self.flags.zero = value == 0x00;
     }
 
     fn txa(&mut self) {
         self.registers.A = self.registers.X;
 
         self.flags.sign = self.registers.X & 0x80 == 0x80;
-        self.flags.zero = self.registers.X & 0xFF == 0x00;
+        self.flags.zero = self.registers.X == 0x00;
     }
 
     fn txs(&mut self) {
@@ -779,34 +1544,156 @@ impl Cpu {
         self.registers.A = self.registers.Y;
 
         self.flags.sign = self.registers.Y & 0x80 == 0x80;
-        self.flags.zero = self.registers.Y & 0xFF == 0x00;
+        self.flags.zero = self.registers.Y == 0x00;
     }
 
     fn relative_jump(&mut self, offset: u8) {
         // If the sign bit is there, negate the PC by the difference
         // between 256 and the offset
         if offset & 0x80 == 0x80 {
-            self.registers.PC -= 0x100 - offset as u16;
+            self.registers.PC = self.registers.PC.wrapping_sub(0x100 - offset as u16);
         } else {
-            self.registers.PC += offset as u16;
+            self.registers.PC = self.registers.PC.wrapping_add(offset as u16);
         }
     }
 
-    /// Convenience wrapper for accessing a byte
-    /// in memory
-    fn read_byte(&self, addr: u16) -> u8 {
-        self.memory.read_byte(addr)
+    // ## Bus access ##
+
+    /// Counts a clock cycle and lets the devices on the bus see it
+    fn end_cycle(&mut self) {
+        self.cycles += 1;
+        self.memory.tick_devices();
     }
 
-    /// Convenience wrapper for writing a byte
-    /// to memory
-    fn write_byte(&mut self, addr: u16, byte: u8) {
+    /// Reads a byte over the bus. Every call is one clock cycle.
+    fn bus_read(&mut self, addr: u16) -> u8 {
+        let value = self.memory.read_byte(addr);
+
+        self.end_cycle();
+        if self.trace_bus {
+            self.bus_trace.push(BusAccess::read(addr, value));
+        }
+
+        value
+    }
+
+    /// Writes a byte over the bus. Every call is one clock cycle.
+    fn bus_write(&mut self, addr: u16, byte: u8) {
         self.memory.write_byte(addr, byte);
+        if let Some(ref mut analysis) = self.code_analysis {
+            analysis.write(self.sequence_pc, addr);
+        }
+
+        self.end_cycle();
+        if self.trace_bus {
+            self.bus_trace.push(BusAccess::write(addr, byte));
+        }
+    }
+
+    /// Reads a byte the current instruction uses, reporting it if it was
+    /// never initialized
+    fn checked_read(&mut self, addr: u16) -> u8 {
+        if !self.memory.is_initialized(addr) {
+            self.report_uninitialized_read(addr);
+        }
+
+        self.bus_read(addr)
+    }
+
+    fn report_uninitialized_read(&mut self, addr: u16) {
+        let pc = self.sequence_pc;
+        let reported = self.uninitialized_reads
+            .iter()
+            .any(|read| read.pc == pc && read.addr == addr);
+        if reported {
+            return;
+        }
+
+        let instruction = self.disassemble_at(pc);
+        self.uninitialized_reads.push(UninitializedRead {
+            pc,
+            addr,
+            instruction,
+        });
+    }
+
+    /// Disassembles the instruction at `addr`, without touching the bus
+    fn disassemble_at(&self, addr: u16) -> String {
+        let length = OpCode::from_raw_byte(self.memory[addr as usize])
+            .map_or(1, |opcode| opcode.length as usize);
+        let code: Vec<u8> = (0..length)
+            .map(|offset| self.memory[addr.wrapping_add(offset as u16) as usize])
+            .collect();
+
+        Disassembler::with_code_only().disassemble(&code).trim().into()
+    }
+
+    /// Reads the byte at the program counter and moves past it
+    fn fetch_byte(&mut self) -> u8 {
+        let pc = self.registers.PC;
+        let byte = self.bus_read(pc);
+        self.registers.PC = pc.wrapping_add(1);
+        self.record_execute(pc, false);
+
+        byte
+    }
+
+    /// Tells the code analysis, if it is running, that the byte at `addr`
+    /// was fetched as part of an instruction
+    fn record_execute(&mut self, addr: u16, opcode: bool) {
+        if let Some(ref mut analysis) = self.code_analysis {
+            analysis.execute(self.sequence_pc, addr, opcode);
+        }
+    }
+
+    /// Reads a byte the Cpu throws away. These never fault, as the program
+    /// didn't ask for them.
+    fn dummy_read(&mut self, addr: u16) {
+        let value = self.memory.dummy_read(addr);
+
+        self.end_cycle();
+        if self.trace_bus {
+            self.bus_trace.push(BusAccess::read(addr, value));
+        }
+    }
+
+    /// Writes back a byte the Cpu is about to overwrite. Like dummy reads,
+    /// these never fault.
+    fn dummy_write(&mut self, addr: u16, byte: u8) {
+        self.memory.dummy_write(addr, byte);
+        if let Some(ref mut analysis) = self.code_analysis {
+            analysis.write(self.sequence_pc, addr);
+        }
+
+        self.end_cycle();
+        if self.trace_bus {
+            self.bus_trace.push(BusAccess::write(addr, byte));
+        }
+    }
+
+    fn dummy_read_pc(&mut self) {
+        let pc = self.registers.PC;
+        self.dummy_read(pc);
+    }
+
+    fn dummy_read_stack(&mut self) {
+        let addr = self.stack_address();
+        self.dummy_read(addr);
+    }
+
+    fn stack_address(&self) -> u16 {
+        STACK_START | self.stack.pointer as u16
+    }
+
+    fn push(&mut self, byte: u8) {
+        let addr = self.stack_address();
+        self.bus_write(addr, byte);
+        self.stack.pointer = self.stack.pointer.wrapping_sub(0x01) & 0xFF;
     }
 
-    /// Convenience wrapper for accessing a word
-    /// in memory
-    fn read_u16(&self, addr: u16) -> u16 {
-        self.memory.read_u16(addr)
+    fn pop(&mut self) -> u8 {
+        self.stack.pointer = self.stack.pointer.wrapping_add(0x01) & 0xFF;
+        let addr = self.stack_address();
+        self.checked_read(addr)
     }
 }
@@ -1,7 +1,11 @@
+use cpu::memory_region::{FaultKind, MemoryFault};
+
 #[derive(Debug, PartialEq)]
 pub enum CpuErrorKind {
     SegFault,
     InvalidOpCode,
+    /// A bad access to a memory region with the `Raise` policy
+    MemoryFault(FaultKind),
 }
 
 #[derive(Debug, PartialEq)]
@@ -27,4 +31,31 @@ impl CpuError {
             kind: CpuErrorKind::InvalidOpCode,
         }
     }
+
+    pub fn memory_fault(fault: &MemoryFault) -> CpuError {
+        let access = match fault.kind {
+            FaultKind::WriteToRom => format!("Write of {:02X} to ROM", fault.value),
+            FaultKind::ExecuteFromIo => "Execution from I/O".into(),
+            FaultKind::UnmappedRead => "Read of unmapped memory".into(),
+            FaultKind::UnmappedWrite => format!("Write of {:02X} to unmapped memory", fault.value),
+        };
+
+        CpuError {
+            message: format!("{} at {:04X} (PC {:04X})", access, fault.addr, fault.pc),
+            addr: fault.addr,
+            kind: CpuErrorKind::MemoryFault(fault.kind),
+        }
+    }
+
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    pub fn addr(&self) -> u16 {
+        self.addr
+    }
+
+    pub fn kind(&self) -> &CpuErrorKind {
+        &self.kind
+    }
 }
\ No newline at end of file
@@ -1,30 +1,278 @@
-use byteorder::{ByteOrder, LittleEndian};
-
+use std::mem;
 use std::ops::{Deref, DerefMut};
 
+use cpu::bus_device::BusDevice;
+use cpu::memory_region::{FaultKind, FaultPolicy, MemoryFault, MemoryRegion, RegionKind};
+
+/// Whether a bus cycle read from or wrote to memory
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum BusAccessKind {
+    Read,
+    Write,
+}
+
+/// A single cycle of bus activity, as seen from the pins of the Cpu
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct BusAccess {
+    pub kind: BusAccessKind,
+    pub addr: u16,
+    pub value: u8,
+}
+
+impl BusAccess {
+    pub fn read(addr: u16, value: u8) -> BusAccess {
+        BusAccess {
+            kind: BusAccessKind::Read,
+            addr,
+            value,
+        }
+    }
+
+    pub fn write(addr: u16, value: u8) -> BusAccess {
+        BusAccess {
+            kind: BusAccessKind::Write,
+            addr,
+            value,
+        }
+    }
+}
+
+struct MappedDevice {
+    start: u16,
+    end: u16,
+    device: Box<dyn BusDevice>,
+}
+
 /// Default, 64kb memory bus
 pub struct MemoryBus {
     ram: [u8; 1024 * 64],
+    devices: Vec<MappedDevice>,
+    regions: Vec<MemoryRegion>,
+    faults: Vec<MemoryFault>,
+    /// One flag per byte of RAM, set once it has been written
+    shadow: Option<Vec<bool>>,
 }
 
 impl MemoryBus {
     pub fn new() -> MemoryBus {
-        MemoryBus { ram: [0; 1024 * 64] }
+        MemoryBus {
+            ram: [0; 1024 * 64],
+            devices: Vec::new(),
+            regions: Vec::new(),
+            faults: Vec::new(),
+            shadow: None,
+        }
+    }
+
+    /// Maps a device over the addresses `start` to `end` (inclusive). Reads
+    /// and writes in that range go to the device instead of RAM. Devices
+    /// mapped later take priority over earlier ones when ranges overlap.
+    pub fn map_device<D>(&mut self, start: u16, end: u16, device: D)
+        where D: BusDevice + 'static
+    {
+        self.devices.insert(0,
+                            MappedDevice {
+                                start,
+                                end,
+                                device: Box::new(device),
+                            });
+    }
+
+    /// Marks the addresses `start` to `end` (inclusive) as holding `kind`
+    /// of memory, with `policy` deciding what happens on a bad access.
+    /// Addresses outside of any region are plain RAM. Regions marked later
+    /// take priority over earlier ones when ranges overlap.
+    pub fn map_region(&mut self, start: u16, end: u16, kind: RegionKind, policy: FaultPolicy) {
+        self.regions.insert(0,
+                            MemoryRegion {
+                                start,
+                                end,
+                                kind,
+                                policy,
+                            });
+    }
+
+    /// The kind of memory at `addr`
+    pub fn region_kind(&self, addr: u16) -> RegionKind {
+        self.region_at(addr).map_or(RegionKind::Ram, |region| region.kind)
     }
 
     pub fn write_byte(&mut self, addr: u16, byte: u8) {
-        let addr = addr as usize;
-        self.ram[addr] = byte;
+        match self.region_kind(addr) {
+            RegionKind::Rom => self.fault(FaultKind::WriteToRom, addr, byte),
+            RegionKind::Unmapped => self.fault(FaultKind::UnmappedWrite, addr, byte),
+            _ => (),
+        }
+
+        self.store(addr, byte);
+    }
+
+    pub fn read_byte(&mut self, addr: u16) -> u8 {
+        let value = self.fetch(addr);
+
+        if self.region_kind(addr) == RegionKind::Unmapped {
+            self.fault(FaultKind::UnmappedRead, addr, value);
+        }
+
+        value
     }
 
-    pub fn read_byte(&self, addr: u16) -> u8 {
-        let addr = addr as usize;
-        self.ram[addr]
+    /// Reads a byte like `read_byte`, but never records a fault. The Cpu
+    /// uses this for the dummy reads it makes while it is busy working
+    /// something else out, which the program being run never asked for.
+    pub fn dummy_read(&mut self, addr: u16) -> u8 {
+        self.fetch(addr)
     }
 
-    pub fn read_u16(&self, addr: u16) -> u16 {
-        let addr = addr as usize;
-        LittleEndian::read_u16(&self.ram[addr..])
+    /// Writes a byte like `write_byte`, but never records a fault. The Cpu
+    /// uses this for the NMOS 6502's dummy write of the unmodified value
+    /// in read-modify-write instructions, so each one faults only once.
+    pub fn dummy_write(&mut self, addr: u16, byte: u8) {
+        self.store(addr, byte);
+    }
+
+    /// Called by the Cpu when it fetches `opcode` from `addr`, as executing
+    /// code is a fault in I/O regions
+    pub fn check_execute(&mut self, addr: u16, opcode: u8) {
+        if self.region_kind(addr) == RegionKind::Io {
+            self.fault(FaultKind::ExecuteFromIo, addr, opcode);
+        }
+    }
+
+    /// Writes a byte without checking the region it lands in, so that ROM
+    /// can be filled. Mapped devices still see the write.
+    pub fn load_byte(&mut self, addr: u16, byte: u8) {
+        if let Some(mapped) = self.device_at(addr) {
+            let offset = addr - mapped.start;
+            return mapped.device.write(offset, byte);
+        }
+
+        self.ram[addr as usize] = byte;
+        self.mark_initialized(addr, addr);
+    }
+
+    /// Turns shadow memory on or off. While it is on, the bus remembers
+    /// which bytes of RAM have been written (or loaded), so that reads of
+    /// memory that was never initialized can be found. Turning it on
+    /// forgets everything written before.
+    pub fn set_shadow_memory(&mut self, enabled: bool) {
+        self.shadow = if enabled {
+            Some(vec![false; 1024 * 64])
+        } else {
+            None
+        };
+    }
+
+    /// Marks the addresses `start` to `end` (inclusive) as initialized, for
+    /// memory filled in without going through the bus
+    pub fn mark_initialized(&mut self, start: u16, end: u16) {
+        if let Some(ref mut shadow) = self.shadow {
+            for flag in &mut shadow[start as usize..end as usize + 1] {
+                *flag = true;
+            }
+        }
+    }
+
+    /// Whether `addr` has been written since shadow memory was turned on.
+    /// Always true while it is off, and for addresses a device answers.
+    pub fn is_initialized(&self, addr: u16) -> bool {
+        match self.shadow {
+            Some(ref shadow) => shadow[addr as usize] || self.has_device_at(addr),
+            None => true,
+        }
+    }
+
+    /// Removes and returns the faults recorded since this was last called.
+    /// Faults in regions with the `Ignore` policy are never recorded. The
+    /// Cpu calls this after every cycle, filling in each fault's `pc`.
+    pub fn take_faults(&mut self) -> Vec<MemoryFault> {
+        mem::take(&mut self.faults)
+    }
+
+    /// Reads a byte without side effects. None if a device is mapped at
+    /// `addr` that can't be read that way.
+    pub fn peek(&self, addr: u16) -> Option<u8> {
+        match self.devices.iter().find(|mapped| addr >= mapped.start && addr <= mapped.end) {
+            Some(mapped) => mapped.device.peek(addr - mapped.start),
+            None => Some(self.ram[addr as usize]),
+        }
+    }
+
+    /// Reads a little endian word. The high byte of a word at $FFFF
+    /// wraps around to $0000.
+    pub fn read_u16(&mut self, addr: u16) -> u16 {
+        let low_byte = self.read_byte(addr) as u16;
+        let high_byte = self.read_byte(addr.wrapping_add(1)) as u16;
+
+        (high_byte << 8) | low_byte
+    }
+
+    /// Clocks every mapped device once
+    pub fn tick_devices(&mut self) {
+        for mapped in &mut self.devices {
+            mapped.device.tick();
+        }
+    }
+
+    /// True if any mapped device is pulling the IRQ line low
+    pub fn irq(&self) -> bool {
+        self.devices.iter().any(|mapped| mapped.device.irq())
+    }
+
+    /// The bank mapped at `addr`, if a bank switched device is mapped there
+    pub fn bank_at(&self, addr: u16) -> Option<usize> {
+        self.devices
+            .iter()
+            .find(|mapped| addr >= mapped.start && addr <= mapped.end)
+            .and_then(|mapped| mapped.device.bank(addr - mapped.start))
+    }
+
+    fn has_device_at(&self, addr: u16) -> bool {
+        self.devices.iter().any(|mapped| addr >= mapped.start && addr <= mapped.end)
+    }
+
+    fn region_at(&self, addr: u16) -> Option<&MemoryRegion> {
+        self.regions.iter().find(|region| region.contains(addr))
+    }
+
+    fn fault(&mut self, kind: FaultKind, addr: u16, value: u8) {
+        let policy = self.region_at(addr).map_or(FaultPolicy::Ignore, |region| region.policy);
+
+        if policy != FaultPolicy::Ignore {
+            self.faults.push(MemoryFault {
+                kind,
+                addr,
+                value,
+                pc: 0,
+                policy,
+            });
+        }
+    }
+
+    fn fetch(&mut self, addr: u16) -> u8 {
+        match self.device_at(addr) {
+            Some(mapped) => {
+                let offset = addr - mapped.start;
+                mapped.device.read(offset)
+            }
+            None => self.ram[addr as usize],
+        }
+    }
+
+    fn store(&mut self, addr: u16, byte: u8) {
+        if let Some(mapped) = self.device_at(addr) {
+            let offset = addr - mapped.start;
+            return mapped.device.write(offset, byte);
+        }
+
+        if self.region_kind(addr) != RegionKind::Rom {
+            self.ram[addr as usize] = byte;
+            self.mark_initialized(addr, addr);
+        }
+    }
+
+    fn device_at(&mut self, addr: u16) -> Option<&mut MappedDevice> {
+        self.devices.iter_mut().find(|mapped| addr >= mapped.start && addr <= mapped.end)
     }
 }
 
@@ -41,4 +289,4 @@ impl DerefMut for MemoryBus {
     fn deref_mut(&mut self) -> &mut Self::Target {
         &mut self.ram
     }
-}
\ No newline at end of file
+}
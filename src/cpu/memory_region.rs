@@ -0,0 +1,62 @@
+/// What lives in a range of the address space
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum RegionKind {
+    Ram,
+    /// Read only memory. Writes are faults, and are dropped unless a
+    /// device is mapped there to see them.
+    Rom,
+    /// Memory mapped I/O. Executing code from here is a fault.
+    Io,
+    /// Nothing answers on the bus here. Any read or write is a fault.
+    Unmapped,
+}
+
+/// What the Cpu does about a bad access to a region
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum FaultPolicy {
+    /// Carry on as if nothing happened
+    Ignore,
+    /// Carry on, but keep a record of the fault in `Cpu::memory_faults`
+    Log,
+    /// Stop, returning a `CpuErrorKind::MemoryFault` error from `step`
+    /// (or `tick`) at the end of the cycle that caused it
+    Raise,
+}
+
+/// The kinds of bad access a region can catch
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum FaultKind {
+    WriteToRom,
+    ExecuteFromIo,
+    UnmappedRead,
+    UnmappedWrite,
+}
+
+/// A bad access to memory
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct MemoryFault {
+    pub kind: FaultKind,
+    /// The address that was accessed
+    pub addr: u16,
+    /// The byte read or written
+    pub value: u8,
+    /// Where the instruction (or interrupt) that made the access began
+    pub pc: u16,
+    /// The policy of the region the fault happened in
+    pub policy: FaultPolicy,
+}
+
+/// A range of addresses (inclusive) and what it holds
+#[derive(Clone, Copy, Debug)]
+pub struct MemoryRegion {
+    pub start: u16,
+    pub end: u16,
+    pub kind: RegionKind,
+    pub policy: FaultPolicy,
+}
+
+impl MemoryRegion {
+    pub fn contains(&self, addr: u16) -> bool {
+        addr >= self.start && addr <= self.end
+    }
+}
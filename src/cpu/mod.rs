@@ -1,13 +1,29 @@
 
+mod analysis;
+mod bus_device;
+mod call_stack;
 mod cpu;
 mod cpu_error;
 mod flags;
 mod memory_bus;
+mod memory_region;
+mod pins;
+mod power;
 mod registers;
+mod scheduler;
 mod stack;
+mod variant;
 
-pub use self::cpu::{Cpu, CpuStepResult};
-pub use self::cpu_error::CpuError;
+pub use self::analysis::{CodeFinding, CodeFindingKind, CodeReport, UninitializedRead};
+pub use self::call_stack::{BacktraceFrame, CallFrame, FrameKind, StackAnomaly, StackAnomalyKind};
+pub use self::cpu::{Cpu, CpuStepResult, CpuTickResult};
+pub use self::cpu_error::{CpuError, CpuErrorKind};
 pub use self::flags::StatusFlags;
-pub use self::memory_bus::MemoryBus;
-pub use self::registers::Registers;
\ No newline at end of file
+pub use self::bus_device::BusDevice;
+pub use self::memory_bus::{BusAccess, BusAccessKind, MemoryBus};
+pub use self::memory_region::{FaultKind, FaultPolicy, MemoryFault, RegionKind};
+pub use self::pins::Pins;
+pub use self::power::{PowerOnPattern, PowerOnState};
+pub use self::registers::Registers;
+pub use self::scheduler::{EventHandler, EventId, Scheduler};
+pub use self::variant::CpuVariant;
\ No newline at end of file
@@ -0,0 +1,35 @@
+/// The levels of the Cpu input pins that the host, devices on the board or
+/// scheduled events can drive. `true` is a high level. All of these pins
+/// are active low, so the defaults leave the Cpu running normally.
+///
+/// The pins are sampled at the start of every `tick`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Pins {
+    /// Ready. While it is held low the Cpu halts on its next read cycle
+    /// (the 65C02 halts on any cycle), leaving the bus free for DMA.
+    pub rdy: bool,
+    /// Set Overflow. A falling edge sets the overflow flag.
+    pub so: bool,
+    /// Reset. While it is held low the Cpu does nothing; when it is
+    /// released the reset sequence runs.
+    pub reset: bool,
+    /// Interrupt request. While it is held low, and interrupts are enabled,
+    /// the Cpu runs the IRQ handler between instructions. Devices on the
+    /// bus pull it low through `BusDevice::irq`.
+    pub irq: bool,
+    /// Non-maskable interrupt. A falling edge runs the NMI handler once the
+    /// current instruction finishes.
+    pub nmi: bool,
+}
+
+impl Default for Pins {
+    fn default() -> Pins {
+        Pins {
+            rdy: true,
+            so: true,
+            reset: true,
+            irq: true,
+            nmi: true,
+        }
+    }
+}
@@ -0,0 +1,97 @@
+/// A pattern used to fill RAM or registers when the Cpu is powered on.
+/// Real hardware comes up with whatever garbage is sitting in its cells,
+/// and some software (accidentally or otherwise) depends on that.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PowerOnPattern {
+    /// Every byte is $00
+    Zero,
+    /// Every byte is $FF
+    Ones,
+    /// Bytes are generated from a seeded pseudo-random sequence, so
+    /// runs are repeatable
+    Random(u64),
+}
+
+/// Describes the state of the Cpu immediately after power is applied
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PowerOnState {
+    pub ram: PowerOnPattern,
+    pub registers: PowerOnPattern,
+}
+
+impl Default for PowerOnState {
+    fn default() -> PowerOnState {
+        PowerOnState {
+            ram: PowerOnPattern::Zero,
+            registers: PowerOnPattern::Zero,
+        }
+    }
+}
+
+/// An endless source of bytes following a PowerOnPattern
+pub struct PatternBytes {
+    pattern: PowerOnPattern,
+    state: u64,
+}
+
+impl PatternBytes {
+    pub fn new(pattern: PowerOnPattern) -> PatternBytes {
+        let state = match pattern {
+            // Xorshift gets stuck at zero, so nudge the seed away from it
+            PowerOnPattern::Random(seed) => (seed ^ 0x9E37_79B9_7F4A_7C15).max(1),
+            _ => 0,
+        };
+
+        PatternBytes { pattern, state }
+    }
+}
+
+impl Iterator for PatternBytes {
+    type Item = u8;
+
+    fn next(&mut self) -> Option<u8> {
+        match self.pattern {
+            PowerOnPattern::Zero => Some(0x00),
+            PowerOnPattern::Ones => Some(0xFF),
+            PowerOnPattern::Random(_) => {
+                // xorshift64*: http://vigna.di.unimi.it/ftp/papers/xorshift.pdf
+                self.state ^= self.state >> 12;
+                self.state ^= self.state << 25;
+                self.state ^= self.state >> 27;
+
+                Some((self.state.wrapping_mul(0x2545_F491_4F6C_DD1D) >> 56) as u8)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fixed_patterns_repeat() {
+        let zeroes: Vec<u8> = PatternBytes::new(PowerOnPattern::Zero).take(4).collect();
+        let ones: Vec<u8> = PatternBytes::new(PowerOnPattern::Ones).take(4).collect();
+
+        assert_eq!(&[0x00, 0x00, 0x00, 0x00], &zeroes[..]);
+        assert_eq!(&[0xFF, 0xFF, 0xFF, 0xFF], &ones[..]);
+    }
+
+    #[test]
+    fn random_pattern_is_repeatable() {
+        let first: Vec<u8> = PatternBytes::new(PowerOnPattern::Random(42)).take(64).collect();
+        let second: Vec<u8> = PatternBytes::new(PowerOnPattern::Random(42)).take(64).collect();
+        let other: Vec<u8> = PatternBytes::new(PowerOnPattern::Random(43)).take(64).collect();
+
+        assert_eq!(first, second);
+        assert!(first != other);
+    }
+
+    #[test]
+    fn random_pattern_survives_zero_seed() {
+        let bytes: Vec<u8> = PatternBytes::new(PowerOnPattern::Random(0)).take(64).collect();
+
+        assert!(bytes.iter().any(|b| *b != 0));
+    }
+}
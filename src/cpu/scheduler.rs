@@ -0,0 +1,177 @@
+use cpu::cpu::Cpu;
+
+/// Identifies a scheduled event so that it can be cancelled
+pub type EventId = u64;
+
+/// The code run when an event falls due. It is handed the Cpu, so it can
+/// drive pins (IRQ, NMI, etc), touch memory or schedule further events.
+pub type EventHandler = Box<dyn FnOnce(&mut Cpu)>;
+
+struct Event {
+    cycle: u64,
+    id: EventId,
+    handler: EventHandler,
+}
+
+/// Holds events that devices want run at a specific Cpu cycle. The Cpu
+/// fires due events at the start of every `tick`, so timers and the like
+/// don't need to poll after every instruction.
+pub struct Scheduler {
+    // Kept in reverse order, so the next event due is at the end
+    events: Vec<Event>,
+    next_id: EventId,
+}
+
+impl Scheduler {
+    pub fn new() -> Scheduler {
+        Scheduler {
+            events: Vec::new(),
+            next_id: 0,
+        }
+    }
+
+    /// Schedules `handler` to run once `cycle` cycles have been executed.
+    /// Events due on the same cycle run in the order they were scheduled.
+    /// An event scheduled in the past runs on the next tick.
+    pub fn schedule<F>(&mut self, cycle: u64, handler: F) -> EventId
+        where F: FnOnce(&mut Cpu) + 'static
+    {
+        let id = self.next_id;
+        self.next_id += 1;
+
+        let index = self.events
+            .iter()
+            .position(|event| event.cycle <= cycle)
+            .unwrap_or(self.events.len());
+        self.events.insert(index,
+                           Event {
+                               cycle,
+                               id,
+                               handler: Box::new(handler),
+                           });
+
+        id
+    }
+
+    /// Removes an event before it runs. Returns false if the event has
+    /// already run or was never scheduled.
+    pub fn cancel(&mut self, id: EventId) -> bool {
+        match self.events.iter().position(|event| event.id == id) {
+            Some(index) => {
+                self.events.remove(index);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// The cycle the next event is due at
+    pub fn next_due(&self) -> Option<u64> {
+        self.events.last().map(|event| event.cycle)
+    }
+
+    pub fn len(&self) -> usize {
+        self.events.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.events.is_empty()
+    }
+
+    /// Removes and returns the handler of the next event, if it is due at
+    /// or before `cycle`
+    pub fn pop_due(&mut self, cycle: u64) -> Option<EventHandler> {
+        if self.next_due().is_some_and(|due| due <= cycle) {
+            self.events.pop().map(|event| event.handler)
+        } else {
+            None
+        }
+    }
+
+    /// Removes and returns the handlers of every event due at or before
+    /// `cycle`, in the order they should run. Events the handlers schedule
+    /// aren't among them, so they wait for the next tick even if they are
+    /// already due.
+    pub fn take_due(&mut self, cycle: u64) -> Vec<EventHandler> {
+        let first = self.events
+            .iter()
+            .position(|event| event.cycle <= cycle)
+            .unwrap_or(self.events.len());
+
+        self.events.drain(first..).rev().map(|event| event.handler).collect()
+    }
+}
+
+impl Default for Scheduler {
+    fn default() -> Scheduler {
+        Scheduler::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn events_come_out_in_cycle_order() {
+        let mut scheduler = Scheduler::new();
+        scheduler.schedule(30, |cpu| cpu.registers.A = 3);
+        scheduler.schedule(10, |cpu| cpu.registers.A = 1);
+        scheduler.schedule(20, |cpu| cpu.registers.A = 2);
+
+        assert_eq!(Some(10), scheduler.next_due());
+        assert!(scheduler.pop_due(9).is_none());
+
+        let mut cpu = Cpu::new();
+        for expected in 1..4 {
+            let handler = scheduler.pop_due(30).unwrap();
+            handler(&mut cpu);
+
+            assert_eq!(expected, cpu.registers.A);
+        }
+
+        assert!(scheduler.is_empty());
+    }
+
+    #[test]
+    fn events_on_the_same_cycle_keep_their_order() {
+        let mut scheduler = Scheduler::new();
+        scheduler.schedule(10, |cpu| cpu.registers.A = 1);
+        scheduler.schedule(10, |cpu| cpu.registers.A = 2);
+
+        let mut cpu = Cpu::new();
+        scheduler.pop_due(10).unwrap()(&mut cpu);
+        assert_eq!(1, cpu.registers.A);
+        scheduler.pop_due(10).unwrap()(&mut cpu);
+        assert_eq!(2, cpu.registers.A);
+    }
+
+    #[test]
+    fn takes_every_due_event_in_order() {
+        let mut scheduler = Scheduler::new();
+        scheduler.schedule(20, |cpu| cpu.registers.A = 3);
+        scheduler.schedule(10, |cpu| cpu.registers.A = 1);
+        scheduler.schedule(10, |cpu| cpu.registers.A = 2);
+        scheduler.schedule(30, |_| {});
+
+        let mut cpu = Cpu::new();
+        let handlers = scheduler.take_due(20);
+        assert_eq!(3, handlers.len());
+        for (expected, handler) in (1..4).zip(handlers) {
+            handler(&mut cpu);
+            assert_eq!(expected, cpu.registers.A);
+        }
+
+        assert_eq!(Some(30), scheduler.next_due());
+    }
+
+    #[test]
+    fn can_cancel_an_event() {
+        let mut scheduler = Scheduler::new();
+        let id = scheduler.schedule(10, |_| {});
+
+        assert!(scheduler.cancel(id));
+        assert!(!scheduler.cancel(id));
+        assert_eq!(0, scheduler.len());
+    }
+}
@@ -1,23 +1,5 @@
-use byteorder::{ByteOrder, LittleEndian};
-
-#[derive(Debug, PartialEq)]
-pub struct StackError {
-    message: String,
-}
-
-impl StackError {
-    pub fn overflow() -> StackError {
-        StackError { message: "Stack overflow occurred".into() }
-    }
-
-    pub fn underflow() -> StackError {
-        StackError { message: "Stack underflow; unable to pop from empty stack".into() }
-    }
-}
-
-pub type StackPushResult = Result<(), StackError>;
-pub type StackPopResult<T> = Result<T, StackError>;
-
+/// The hardware stack. It lives in page one of memory and the stack
+/// pointer wraps around within that page, exactly like the real thing.
 pub struct Stack {
     pub pointer: usize,
 }
@@ -27,49 +9,26 @@ impl Stack {
         Stack { pointer: 0xFF }
     }
 
-    pub fn push(&mut self, stack_area: &mut [u8], val: u8) -> StackPushResult {
-        if self.pointer > 0x00 {
-            stack_area[self.pointer] = val;
-            self.pointer -= 0x01;
-
-            Ok(())
-        } else {
-            Err(StackError::overflow())
-        }
+    pub fn push(&mut self, stack_area: &mut [u8], val: u8) {
+        stack_area[self.pointer & 0xFF] = val;
+        self.pointer = self.pointer.wrapping_sub(0x01) & 0xFF;
     }
 
-    pub fn push_u16(&mut self, stack_area: &mut [u8], val: u16) -> StackPushResult {
-        if self.pointer >= 0x01 {
-            LittleEndian::write_u16(&mut stack_area[self.pointer - 0x01..], val);
-            self.pointer -= 0x02;
-
-            Ok(())
-        } else {
-            Err(StackError::overflow())
-        }
+    pub fn push_u16(&mut self, stack_area: &mut [u8], val: u16) {
+        self.push(stack_area, (val >> 0x08) as u8);
+        self.push(stack_area, val as u8);
     }
 
-    pub fn pop(&mut self, stack_area: &[u8]) -> StackPopResult<u8> {
-        if self.pointer == 0xFF {
-            Err(StackError::underflow())
-        } else {
-            self.pointer += 0x01;
-            let val = stack_area[self.pointer];
-
-            Ok(val)
-        }
+    pub fn pop(&mut self, stack_area: &[u8]) -> u8 {
+        self.pointer = self.pointer.wrapping_add(0x01) & 0xFF;
+        stack_area[self.pointer]
     }
 
-    pub fn pop_u16(&mut self, stack_area: &mut [u8]) -> StackPopResult<u16> {
-        if self.pointer <= 0xFE {
-            self.pointer += 0x01;
-            let result = LittleEndian::read_u16(&stack_area[self.pointer..]);
-            self.pointer += 0x01;
+    pub fn pop_u16(&mut self, stack_area: &[u8]) -> u16 {
+        let low_byte = self.pop(stack_area) as u16;
+        let high_byte = self.pop(stack_area) as u16;
 
-            Ok(result)
-        } else {
-            Err(StackError::underflow())
-        }
+        (high_byte << 0x08) | low_byte
     }
 }
 
@@ -93,7 +52,7 @@ mod tests {
         let mut stack = Stack::new();
 
         stack.push(&mut stack_area, 55);
-        let val = stack.pop(&mut stack_area).unwrap();
+        let val = stack.pop(&stack_area);
 
         assert_eq!(55, val);
     }
@@ -108,10 +67,10 @@ mod tests {
         stack.push(&mut stack_area, 15);
         stack.push(&mut stack_area, 20);
 
-        let twenty = stack.pop(&mut stack_area).unwrap();
-        let fifteen = stack.pop(&mut stack_area).unwrap();
-        let ten = stack.pop(&mut stack_area).unwrap();
-        let five = stack.pop(&mut stack_area).unwrap();
+        let twenty = stack.pop(&stack_area);
+        let fifteen = stack.pop(&stack_area);
+        let ten = stack.pop(&stack_area);
+        let five = stack.pop(&stack_area);
 
         assert_eq!(20, twenty);
         assert_eq!(15, fifteen);
@@ -120,27 +79,44 @@ mod tests {
     }
 
     #[test]
-    fn can_not_pop_empty_stack() {
+    fn pop_from_empty_stack_wraps_around() {
         let mut stack_area = [0u8; 0x100];
         let mut stack = Stack::new();
 
-        let result = stack.pop(&mut stack_area);
+        stack_area[0x00] = 0x42;
+        let result = stack.pop(&stack_area);
 
-        assert_eq!(Err(StackError::underflow()), result);
+        assert_eq!(0x42, result);
+        assert_eq!(0x00, stack.pointer);
     }
 
     #[test]
-    fn can_not_push_to_full_stack() {
+    fn push_to_full_stack_wraps_around() {
         let mut stack_area = [0u8; 0x100];
         let mut stack = Stack::new();
 
-        for _ in 0..0xFF {
+        for _ in 0..0x100 {
             stack.push(&mut stack_area, 5);
         }
 
-        let result = stack.push(&mut stack_area, 5);
+        assert_eq!(0xFF, stack.pointer);
+
+        stack.push(&mut stack_area, 6);
+
+        assert_eq!(6, stack_area[0xFF]);
+    }
+
+    #[test]
+    fn can_push_then_pop_u16_across_page_boundary() {
+        let mut stack_area = [0u8; 0x100];
+        let mut stack = Stack::new();
+        stack.pointer = 0x00;
+
+        stack.push_u16(&mut stack_area, 0x1234);
 
-        assert_eq!(Err(StackError::overflow()), result);
+        assert_eq!(0x12, stack_area[0x00]);
+        assert_eq!(0x34, stack_area[0xFF]);
+        assert_eq!(0x1234, stack.pop_u16(&stack_area));
     }
 
     #[test]
@@ -160,7 +136,7 @@ mod tests {
         let mut stack = Stack::new();
 
         stack.push_u16(&mut stack_area, 0x4400);
-        let result = stack.pop_u16(&mut stack_area).unwrap();
+        let result = stack.pop_u16(&stack_area);
 
         assert_eq!(0x4400, result);
     }
@@ -0,0 +1,10 @@
+/// The flavour of 6502 being emulated. The variants differ in a handful
+/// of hardware quirks that some programs rely on.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum CpuVariant {
+    /// The original NMOS 6502, bugs and all
+    #[default]
+    Nmos6502,
+    /// The CMOS 65C02, which fixes the JMP ($xxFF) page wrapping bug
+    Cmos65C02,
+}
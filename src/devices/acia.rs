@@ -0,0 +1,353 @@
+use std::io::{self, BufReader, ErrorKind, Read, Write};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::thread;
+
+use cpu::BusDevice;
+
+// Register offsets
+const DATA: u16 = 0x00;
+const STATUS: u16 = 0x01;
+const COMMAND: u16 = 0x02;
+const CONTROL: u16 = 0x03;
+
+// Status register bits
+const STATUS_OVERRUN: u8 = 0x04;
+const STATUS_RECEIVE_FULL: u8 = 0x08;
+const STATUS_TRANSMIT_EMPTY: u8 = 0x10;
+const STATUS_IRQ: u8 = 0x80;
+
+// Command register bits
+const COMMAND_DTR: u8 = 0x01;
+const COMMAND_IRQ_DISABLED: u8 = 0x02;
+const COMMAND_ECHO: u8 = 0x10;
+
+/// A MOS 6551 Asynchronous Communications Interface Adapter. Bytes written
+/// to the data register go straight to `writer`, and bytes from `reader`
+/// arrive in the receive register whenever it is empty and the receiver is
+/// enabled (DTR set in the command register). When the receiver interrupt
+/// is enabled, each received byte pulls the IRQ line low until the status
+/// register is read.
+///
+/// The reader is polled every cycle while the receive register is empty,
+/// so it must not block, or the Cpu stops until it returns. A reader
+/// returning `Ok(0)` or `WouldBlock` simply has nothing to send yet. Wrap
+/// a blocking stream like stdin in a `NonBlockingReader`:
+///
+/// ```no_run
+/// # use std::io;
+/// # use rs6502::{Acia, NonBlockingReader};
+/// let acia = Acia::new(NonBlockingReader::new(io::stdin()), io::stdout());
+/// ```
+///
+/// Baud rates and framing are not emulated; the control register only
+/// stores what is written to it.
+pub struct Acia<R, W>
+    where R: Read,
+          W: Write
+{
+    reader: R,
+    writer: W,
+    receive: u8,
+    status: u8,
+    command: u8,
+    control: u8,
+}
+
+impl<R, W> Acia<R, W>
+    where R: Read,
+          W: Write
+{
+    pub fn new(reader: R, writer: W) -> Acia<R, W> {
+        Acia {
+            reader,
+            writer,
+            receive: 0,
+            status: STATUS_TRANSMIT_EMPTY,
+            command: COMMAND_IRQ_DISABLED,
+            control: 0,
+        }
+    }
+
+    pub fn reader(&self) -> &R {
+        &self.reader
+    }
+
+    pub fn writer(&self) -> &W {
+        &self.writer
+    }
+
+    fn receiver_enabled(&self) -> bool {
+        self.command & COMMAND_DTR != 0
+    }
+
+    fn poll_receiver(&mut self) {
+        let mut buf = [0; 1];
+        let byte = match self.reader.read(&mut buf) {
+            Ok(1) => buf[0],
+            // Nothing to receive yet, or the stream has ended
+            _ => return,
+        };
+
+        self.receive = byte;
+        self.status |= STATUS_RECEIVE_FULL;
+
+        if self.command & COMMAND_IRQ_DISABLED == 0 {
+            self.status |= STATUS_IRQ;
+        }
+        if self.command & COMMAND_ECHO != 0 {
+            self.transmit(byte);
+        }
+    }
+
+    fn transmit(&mut self, byte: u8) {
+        // The serial line has nowhere to report errors to, so a failing
+        // writer simply drops the byte like a disconnected terminal would
+        let _ = self.writer.write_all(&[byte]).and_then(|_| self.writer.flush());
+    }
+}
+
+/// A reader that never blocks, for feeding a blocking stream to an `Acia`.
+/// A thread reads the stream into a queue, and reads return `WouldBlock`
+/// while the queue is empty. Once the stream ends and the queue is drained,
+/// reads return `Ok(0)`.
+pub struct NonBlockingReader {
+    bytes: Receiver<u8>,
+}
+
+impl NonBlockingReader {
+    pub fn new<R>(reader: R) -> NonBlockingReader
+        where R: Read + Send + 'static
+    {
+        let (sender, bytes) = mpsc::channel();
+        thread::spawn(move || {
+            for byte in BufReader::new(reader).bytes() {
+                // Stop at the end of the stream, on an error, or once the
+                // reader has been dropped
+                match byte {
+                    Ok(byte) if sender.send(byte).is_ok() => (),
+                    _ => break,
+                }
+            }
+        });
+
+        NonBlockingReader { bytes }
+    }
+}
+
+impl Read for NonBlockingReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        match self.bytes.try_recv() {
+            Ok(byte) => {
+                buf[0] = byte;
+                Ok(1)
+            }
+            Err(TryRecvError::Empty) => Err(io::Error::new(ErrorKind::WouldBlock, "no data yet")),
+            Err(TryRecvError::Disconnected) => Ok(0),
+        }
+    }
+}
+
+impl<R, W> BusDevice for Acia<R, W>
+    where R: Read,
+          W: Write
+{
+    fn read(&mut self, addr: u16) -> u8 {
+        match addr & 0x03 {
+            DATA => {
+                self.status &= !(STATUS_RECEIVE_FULL | STATUS_OVERRUN);
+                self.receive
+            }
+            STATUS => {
+                let status = self.status;
+                self.status &= !STATUS_IRQ;
+                status
+            }
+            COMMAND => self.command,
+            CONTROL => self.control,
+            _ => unreachable!(),
+        }
+    }
+
+    fn write(&mut self, addr: u16, byte: u8) {
+        match addr & 0x03 {
+            DATA => self.transmit(byte),
+            STATUS => {
+                // A programmed reset
+                self.command &= 0xE0;
+                self.status &= !(STATUS_OVERRUN | STATUS_IRQ);
+            }
+            COMMAND => {
+                self.command = byte;
+                if byte & COMMAND_IRQ_DISABLED != 0 {
+                    self.status &= !STATUS_IRQ;
+                }
+            }
+            CONTROL => self.control = byte,
+            _ => unreachable!(),
+        }
+    }
+
+    fn tick(&mut self) {
+        if self.receiver_enabled() && self.status & STATUS_RECEIVE_FULL == 0 {
+            self.poll_receiver();
+        }
+    }
+
+    fn irq(&self) -> bool {
+        self.status & STATUS_IRQ != 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+    use std::sync::mpsc::Sender;
+    use std::time::{Duration, Instant};
+
+    use super::*;
+
+    fn acia(input: &[u8]) -> Acia<Cursor<Vec<u8>>, Vec<u8>> {
+        Acia::new(Cursor::new(input.to_vec()), Vec::new())
+    }
+
+    #[test]
+    fn writes_to_data_register_are_transmitted() {
+        let mut acia = acia(b"");
+
+        acia.write(DATA, b'H');
+        acia.write(DATA, b'i');
+
+        assert_eq!(b"Hi", &acia.writer()[..]);
+        assert_eq!(STATUS_TRANSMIT_EMPTY, acia.read(STATUS) & STATUS_TRANSMIT_EMPTY);
+    }
+
+    #[test]
+    fn receiver_is_disabled_until_dtr_is_set() {
+        let mut acia = acia(b"A");
+
+        acia.tick();
+        assert_eq!(0, acia.read(STATUS) & STATUS_RECEIVE_FULL);
+
+        acia.write(COMMAND, 0x0B);
+        acia.tick();
+        assert_eq!(STATUS_RECEIVE_FULL, acia.read(STATUS) & STATUS_RECEIVE_FULL);
+        assert_eq!(b'A', acia.read(DATA));
+        assert_eq!(0, acia.read(STATUS) & STATUS_RECEIVE_FULL);
+    }
+
+    #[test]
+    fn bytes_wait_for_the_receive_register_to_empty() {
+        let mut acia = acia(b"AB");
+        acia.write(COMMAND, 0x0B);
+
+        acia.tick();
+        acia.tick();
+        assert_eq!(b'A', acia.read(DATA));
+
+        acia.tick();
+        assert_eq!(b'B', acia.read(DATA));
+    }
+
+    #[test]
+    fn received_bytes_raise_an_interrupt_when_enabled() {
+        let mut acia = acia(b"AB");
+        acia.write(COMMAND, 0x0B);
+        acia.tick();
+        assert!(!acia.irq());
+        acia.read(DATA);
+
+        acia.write(COMMAND, 0x09);
+        acia.tick();
+        assert!(acia.irq());
+
+        // Reading the status register acknowledges the interrupt
+        assert_eq!(STATUS_IRQ, acia.read(STATUS) & STATUS_IRQ);
+        assert!(!acia.irq());
+    }
+
+    #[test]
+    fn echo_mode_retransmits_received_bytes() {
+        let mut acia = acia(b"A");
+        acia.write(COMMAND, 0x1B);
+        acia.tick();
+
+        assert_eq!(b"A", &acia.writer()[..]);
+    }
+
+    #[test]
+    fn programmed_reset_disables_the_receiver() {
+        let mut acia = acia(b"A");
+        acia.write(COMMAND, 0xEB);
+        acia.write(STATUS, 0x00);
+
+        assert_eq!(0xE0, acia.read(COMMAND));
+        acia.tick();
+        assert_eq!(0, acia.read(STATUS) & STATUS_RECEIVE_FULL);
+    }
+
+    struct WouldBlock;
+
+    impl Read for WouldBlock {
+        fn read(&mut self, _: &mut [u8]) -> io::Result<usize> {
+            Err(io::Error::new(ErrorKind::WouldBlock, "no data"))
+        }
+    }
+
+    #[test]
+    fn a_reader_with_nothing_to_send_is_ignored() {
+        let mut acia = Acia::new(WouldBlock, Vec::new());
+        acia.write(COMMAND, 0x09);
+        acia.tick();
+
+        assert_eq!(0, acia.read(STATUS) & STATUS_RECEIVE_FULL);
+        assert!(!acia.irq());
+    }
+
+    /// A reader that blocks until the test sends it something, like stdin
+    /// waiting for a key
+    struct Keyboard(Receiver<u8>);
+
+    impl Read for Keyboard {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            match self.0.recv() {
+                Ok(byte) => {
+                    buf[0] = byte;
+                    Ok(1)
+                }
+                Err(_) => Ok(0),
+            }
+        }
+    }
+
+    fn keyboard_acia() -> (Sender<u8>, Acia<NonBlockingReader, Vec<u8>>) {
+        let (keys, receiver) = mpsc::channel();
+        let mut acia = Acia::new(NonBlockingReader::new(Keyboard(receiver)), Vec::new());
+        acia.write(COMMAND, 0x09);
+
+        (keys, acia)
+    }
+
+    #[test]
+    fn blocking_readers_do_not_stop_the_clock() {
+        let (keys, mut acia) = keyboard_acia();
+
+        // Nothing has been typed, so these return straight away
+        for _ in 0..1000 {
+            acia.tick();
+        }
+        assert_eq!(0, acia.read(STATUS) & STATUS_RECEIVE_FULL);
+
+        keys.send(b'A').unwrap();
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while !acia.irq() && Instant::now() < deadline {
+            acia.tick();
+            thread::sleep(Duration::from_millis(1));
+        }
+        assert!(acia.irq());
+        assert_eq!(b'A', acia.read(DATA));
+    }
+}
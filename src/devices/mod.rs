@@ -0,0 +1,7 @@
+mod acia;
+mod riot;
+mod via;
+
+pub use self::acia::{Acia, NonBlockingReader};
+pub use self::riot::{Riot, RiotRam};
+pub use self::via::Via;
@@ -0,0 +1,329 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use cpu::BusDevice;
+
+/// Bytes of RAM inside the chip
+pub const RIOT_RAM_SIZE: usize = 128;
+
+// Port register offsets (A2 low)
+const ORA: u16 = 0x00;
+const DDRA: u16 = 0x01;
+const ORB: u16 = 0x02;
+const DDRB: u16 = 0x03;
+
+// Address bits selecting the timer and interrupt registers
+const ADDR_TIMER: u16 = 0x04;
+const ADDR_TIMER_IRQ: u16 = 0x08;
+const ADDR_TIMER_WRITE: u16 = 0x10;
+const ADDR_READ_FLAGS: u16 = 0x01;
+const ADDR_PA7_IRQ: u16 = 0x02;
+const ADDR_PA7_RISING: u16 = 0x01;
+
+// Interrupt flag bits
+const FLAG_TIMER: u8 = 0x80;
+const FLAG_PA7: u8 = 0x40;
+
+// The timer prescalers, selected by A0 and A1 of a timer write
+const PRESCALERS: [u16; 4] = [1, 8, 64, 1024];
+
+/// A MOS 6532 RAM-I/O-Timer. The chip decodes its RAM and its registers
+/// separately, so it is mapped onto the bus in two parts: the `Riot` itself
+/// covers the I/O and timer registers, and `Riot::ram` hands out a view of
+/// its 128 bytes of RAM that can be mapped anywhere else:
+///
+/// ```
+/// # use std::cell::RefCell;
+/// # use std::rc::Rc;
+/// # use rs6502::{Cpu, Riot};
+/// let mut cpu = Cpu::new();
+/// let riot = Rc::new(RefCell::new(Riot::new()));
+///
+/// cpu.memory.map_device(0x0080, 0x00FF, Riot::ram(&riot));
+/// cpu.memory.map_device(0x0280, 0x029F, riot.clone());
+/// ```
+///
+/// The interval timer counts Cpu cycles. Once it counts through zero it
+/// sets its interrupt flag and carries on counting down once per cycle
+/// until it is written again.
+pub struct Riot {
+    ram: [u8; RIOT_RAM_SIZE],
+
+    ora: u8,
+    orb: u8,
+    ddra: u8,
+    ddrb: u8,
+    port_a_input: u8,
+    port_b_input: u8,
+
+    timer: u8,
+    prescaler: u16,
+    prescale_count: u16,
+    timer_irq_enabled: bool,
+
+    pa7: bool,
+    pa7_rising: bool,
+    pa7_irq_enabled: bool,
+
+    flags: u8,
+}
+
+impl Riot {
+    pub fn new() -> Riot {
+        Riot {
+            ram: [0; RIOT_RAM_SIZE],
+
+            ora: 0,
+            orb: 0,
+            ddra: 0,
+            ddrb: 0,
+            port_a_input: 0xFF,
+            port_b_input: 0xFF,
+
+            timer: 0,
+            prescaler: 1,
+            prescale_count: 1,
+            timer_irq_enabled: false,
+
+            pa7: true,
+            pa7_rising: false,
+            pa7_irq_enabled: false,
+
+            flags: 0,
+        }
+    }
+
+    /// A bus device for the RAM inside `riot`, for mapping separately from
+    /// its registers
+    pub fn ram(riot: &Rc<RefCell<Riot>>) -> RiotRam {
+        RiotRam(riot.clone())
+    }
+
+    /// The levels on the port A pins. Output bits come from ORA and input
+    /// bits from whatever is driving the port.
+    pub fn port_a(&self) -> u8 {
+        (self.ora & self.ddra) | (self.port_a_input & !self.ddra)
+    }
+
+    /// The levels on the port B pins
+    pub fn port_b(&self) -> u8 {
+        (self.orb & self.ddrb) | (self.port_b_input & !self.ddrb)
+    }
+
+    /// Drives the port A pins from outside. An edge on PA7 sets its
+    /// interrupt flag, if it is the edge the Cpu asked for.
+    pub fn set_port_a_input(&mut self, value: u8) {
+        self.port_a_input = value;
+        self.detect_pa7_edge();
+    }
+
+    /// Drives the port B pins from outside
+    pub fn set_port_b_input(&mut self, value: u8) {
+        self.port_b_input = value;
+    }
+
+    fn detect_pa7_edge(&mut self) {
+        let level = self.port_a() & 0x80 != 0;
+
+        if level != self.pa7 && level == self.pa7_rising {
+            self.flags |= FLAG_PA7;
+        }
+        self.pa7 = level;
+    }
+
+    fn write_timer(&mut self, addr: u16, byte: u8) {
+        self.timer = byte;
+        self.prescaler = PRESCALERS[(addr & 0x03) as usize];
+        self.prescale_count = self.prescaler;
+        self.timer_irq_enabled = addr & ADDR_TIMER_IRQ != 0;
+        self.flags &= !FLAG_TIMER;
+    }
+}
+
+impl Default for Riot {
+    fn default() -> Riot {
+        Riot::new()
+    }
+}
+
+impl BusDevice for Riot {
+    fn read(&mut self, addr: u16) -> u8 {
+        if addr & ADDR_TIMER == 0 {
+            return match addr & 0x03 {
+                ORA => self.port_a(),
+                DDRA => self.ddra,
+                ORB => self.port_b(),
+                DDRB => self.ddrb,
+                _ => unreachable!(),
+            };
+        }
+
+        if addr & ADDR_READ_FLAGS != 0 {
+            let flags = self.flags;
+            self.flags &= !FLAG_PA7;
+            flags
+        } else {
+            self.timer_irq_enabled = addr & ADDR_TIMER_IRQ != 0;
+            self.flags &= !FLAG_TIMER;
+            self.timer
+        }
+    }
+
+    fn write(&mut self, addr: u16, byte: u8) {
+        if addr & ADDR_TIMER == 0 {
+            match addr & 0x03 {
+                ORA => self.ora = byte,
+                DDRA => self.ddra = byte,
+                ORB => self.orb = byte,
+                DDRB => self.ddrb = byte,
+                _ => unreachable!(),
+            }
+            self.detect_pa7_edge();
+        } else if addr & ADDR_TIMER_WRITE != 0 {
+            self.write_timer(addr, byte);
+        } else {
+            // Edge detect control
+            self.pa7_irq_enabled = addr & ADDR_PA7_IRQ != 0;
+            self.pa7_rising = addr & ADDR_PA7_RISING != 0;
+        }
+    }
+
+    fn tick(&mut self) {
+        self.prescale_count -= 1;
+        if self.prescale_count > 0 {
+            return;
+        }
+
+        if self.timer == 0 {
+            // Counting through zero sets the flag and drops the prescaler,
+            // so software can tell how long ago the timer expired
+            self.flags |= FLAG_TIMER;
+            self.prescaler = 1;
+        }
+        self.timer = self.timer.wrapping_sub(1);
+        self.prescale_count = self.prescaler;
+    }
+
+    fn irq(&self) -> bool {
+        (self.timer_irq_enabled && self.flags & FLAG_TIMER != 0) ||
+        (self.pa7_irq_enabled && self.flags & FLAG_PA7 != 0)
+    }
+}
+
+/// The RAM inside a `Riot`, as a device that can be mapped onto the bus.
+/// Only the low 7 address bits are decoded, so larger mappings mirror it.
+pub struct RiotRam(Rc<RefCell<Riot>>);
+
+impl BusDevice for RiotRam {
+    fn read(&mut self, addr: u16) -> u8 {
+        self.0.borrow().ram[addr as usize % RIOT_RAM_SIZE]
+    }
+
+    fn peek(&self, addr: u16) -> Option<u8> {
+        Some(self.0.borrow().ram[addr as usize % RIOT_RAM_SIZE])
+    }
+
+    fn write(&mut self, addr: u16, byte: u8) {
+        self.0.borrow_mut().ram[addr as usize % RIOT_RAM_SIZE] = byte;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Timer write addresses, as decoded by the 6532
+    const TIM1T: u16 = 0x14;
+    const TIM8T: u16 = 0x15;
+    const TIM64T_IRQ: u16 = 0x1E;
+    const INTIM: u16 = 0x04;
+    const INSTAT: u16 = 0x05;
+
+    fn tick_n(riot: &mut Riot, n: u32) {
+        for _ in 0..n {
+            riot.tick();
+        }
+    }
+
+    #[test]
+    fn ram_is_shared_with_the_chip() {
+        let riot = Rc::new(RefCell::new(Riot::new()));
+        let mut ram = Riot::ram(&riot);
+
+        ram.write(0x05, 0x42);
+
+        assert_eq!(0x42, riot.borrow().ram[0x05]);
+        assert_eq!(0x42, ram.read(0x85));
+    }
+
+    #[test]
+    fn port_pins_follow_data_direction() {
+        let mut riot = Riot::new();
+        riot.set_port_b_input(0x0F);
+
+        riot.write(DDRB, 0xF0);
+        riot.write(ORB, 0xA5);
+
+        assert_eq!(0xAF, riot.port_b());
+        assert_eq!(0xAF, riot.read(ORB));
+    }
+
+    #[test]
+    fn timer_counts_down_once_per_prescaled_period() {
+        let mut riot = Riot::new();
+        riot.write(TIM8T, 10);
+
+        tick_n(&mut riot, 7);
+        assert_eq!(10, riot.read(INTIM));
+        riot.tick();
+        assert_eq!(9, riot.read(INTIM));
+        tick_n(&mut riot, 16);
+        assert_eq!(7, riot.read(INTIM));
+    }
+
+    #[test]
+    fn timer_sets_its_flag_and_counts_every_cycle_after_zero() {
+        let mut riot = Riot::new();
+        riot.write(TIM8T, 1);
+
+        tick_n(&mut riot, 15);
+        assert_eq!(0, riot.read(INSTAT) & FLAG_TIMER);
+
+        riot.tick();
+        assert_eq!(FLAG_TIMER, riot.read(INSTAT) & FLAG_TIMER);
+        assert_eq!(0xFF, riot.timer);
+
+        tick_n(&mut riot, 3);
+        assert_eq!(0xFC, riot.read(INTIM));
+        assert_eq!(0, riot.read(INSTAT) & FLAG_TIMER);
+    }
+
+    #[test]
+    fn timer_interrupt_is_enabled_by_the_write_address() {
+        let mut riot = Riot::new();
+        riot.write(TIM1T, 0);
+        riot.tick();
+        assert!(!riot.irq());
+
+        riot.write(TIM64T_IRQ, 0);
+        tick_n(&mut riot, 64);
+        assert!(riot.irq());
+
+        riot.write(TIM64T_IRQ, 2);
+        assert!(!riot.irq());
+    }
+
+    #[test]
+    fn pa7_edge_sets_its_flag() {
+        let mut riot = Riot::new();
+        // Positive edge, interrupt enabled
+        riot.write(0x07, 0);
+        riot.set_port_a_input(0x00);
+        assert!(!riot.irq());
+
+        riot.set_port_a_input(0x80);
+        assert!(riot.irq());
+        assert_eq!(FLAG_PA7, riot.read(INSTAT) & FLAG_PA7);
+        assert!(!riot.irq());
+    }
+}
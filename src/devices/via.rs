@@ -0,0 +1,722 @@
+use cpu::BusDevice;
+
+// Register offsets
+const ORB: u16 = 0x00;
+const ORA: u16 = 0x01;
+const DDRB: u16 = 0x02;
+const DDRA: u16 = 0x03;
+const T1C_L: u16 = 0x04;
+const T1C_H: u16 = 0x05;
+const T1L_L: u16 = 0x06;
+const T1L_H: u16 = 0x07;
+const T2C_L: u16 = 0x08;
+const T2C_H: u16 = 0x09;
+const SR: u16 = 0x0A;
+const ACR: u16 = 0x0B;
+const PCR: u16 = 0x0C;
+const IFR: u16 = 0x0D;
+const IER: u16 = 0x0E;
+const ORA_NO_HANDSHAKE: u16 = 0x0F;
+
+// Interrupt flag (and enable) bits
+const INT_CA2: u8 = 0x01;
+const INT_CA1: u8 = 0x02;
+const INT_SR: u8 = 0x04;
+const INT_CB2: u8 = 0x08;
+const INT_CB1: u8 = 0x10;
+const INT_T2: u8 = 0x20;
+const INT_T1: u8 = 0x40;
+const INT_ANY: u8 = 0x80;
+
+// Auxiliary control register bits
+const ACR_PA_LATCH: u8 = 0x01;
+const ACR_PB_LATCH: u8 = 0x02;
+const ACR_T2_PULSE_COUNT: u8 = 0x20;
+const ACR_T1_FREE_RUN: u8 = 0x40;
+const ACR_T1_PB7: u8 = 0x80;
+
+// CA2/CB2 control modes, from the peripheral control register
+const CONTROL_INDEPENDENT_NEGATIVE: u8 = 0x01;
+const CONTROL_INDEPENDENT_POSITIVE: u8 = 0x03;
+const CONTROL_HANDSHAKE: u8 = 0x04;
+const CONTROL_PULSE: u8 = 0x05;
+const CONTROL_LOW: u8 = 0x06;
+
+// Shift register modes, from the auxiliary control register
+const SHIFT_DISABLED: u8 = 0x00;
+const SHIFT_IN_PHI2: u8 = 0x02;
+const SHIFT_IN_CB1: u8 = 0x03;
+const SHIFT_OUT_FREE_RUN: u8 = 0x04;
+const SHIFT_OUT_PHI2: u8 = 0x06;
+const SHIFT_OUT_CB1: u8 = 0x07;
+
+/// A MOS 6522 Versatile Interface Adapter. Map it onto the bus with
+/// `MemoryBus::map_device`, usually wrapped in an `Rc<RefCell<_>>` so that
+/// the host can drive its port and control line inputs.
+///
+/// The timers count Cpu cycles. Timer 2 and the shift register share the
+/// low byte of the timer 2 latch as their clock divider, and the shift
+/// register takes two clock periods (one CB1 pulse) per bit.
+pub struct Via {
+    ora: u8,
+    orb: u8,
+    ddra: u8,
+    ddrb: u8,
+    port_a_input: u8,
+    port_b_input: u8,
+    ira_latch: u8,
+    irb_latch: u8,
+
+    t1_counter: u16,
+    t1_latch: u16,
+    t1_reload: bool,
+    t1_armed: bool,
+    pb7: bool,
+
+    t2_counter: u16,
+    t2_latch_low: u8,
+    t2_reload: bool,
+    t2_armed: bool,
+
+    sr: u8,
+    sr_bits: u8,
+    sr_running: bool,
+    sr_timer: u16,
+    sr_clock: bool,
+
+    acr: u8,
+    pcr: u8,
+    ifr: u8,
+    ier: u8,
+
+    ca1: bool,
+    ca2: bool,
+    cb1: bool,
+    cb2: bool,
+    ca2_out: bool,
+    cb2_out: bool,
+    ca2_pulse: u8,
+    cb2_pulse: u8,
+}
+
+impl Via {
+    pub fn new() -> Via {
+        Via {
+            ora: 0,
+            orb: 0,
+            ddra: 0,
+            ddrb: 0,
+            port_a_input: 0xFF,
+            port_b_input: 0xFF,
+            ira_latch: 0,
+            irb_latch: 0,
+
+            t1_counter: 0,
+            t1_latch: 0,
+            t1_reload: false,
+            t1_armed: false,
+            pb7: true,
+
+            t2_counter: 0,
+            t2_latch_low: 0,
+            t2_reload: false,
+            t2_armed: false,
+
+            sr: 0,
+            sr_bits: 0,
+            sr_running: false,
+            sr_timer: 0,
+            sr_clock: true,
+
+            acr: 0,
+            pcr: 0,
+            ifr: 0,
+            ier: 0,
+
+            ca1: true,
+            ca2: true,
+            cb1: true,
+            cb2: true,
+            ca2_out: true,
+            cb2_out: true,
+            ca2_pulse: 0,
+            cb2_pulse: 0,
+        }
+    }
+
+    /// The levels on the port A pins. Output bits come from ORA and input
+    /// bits from whatever is driving the port.
+    pub fn port_a(&self) -> u8 {
+        (self.ora & self.ddra) | (self.port_a_input & !self.ddra)
+    }
+
+    /// The levels on the port B pins. PB7 is driven by timer 1 when the
+    /// auxiliary control register asks for it.
+    pub fn port_b(&self) -> u8 {
+        let pins = (self.orb & self.ddrb) | (self.port_b_input & !self.ddrb);
+
+        if self.acr & ACR_T1_PB7 != 0 {
+            (pins & 0x7F) | if self.pb7 { 0x80 } else { 0x00 }
+        } else {
+            pins
+        }
+    }
+
+    /// Drives the port A pins from outside. Only bits set as inputs in
+    /// DDRA are seen by the Cpu.
+    pub fn set_port_a_input(&mut self, value: u8) {
+        self.port_a_input = value;
+    }
+
+    /// Drives the port B pins from outside. When timer 2 is counting
+    /// pulses, every falling edge on PB6 counts it down.
+    pub fn set_port_b_input(&mut self, value: u8) {
+        let falling_pb6 = self.port_b_input & 0x40 != 0 && value & 0x40 == 0;
+        self.port_b_input = value;
+
+        if falling_pb6 && self.acr & ACR_T2_PULSE_COUNT != 0 {
+            self.t2_counter = self.t2_counter.wrapping_sub(1);
+
+            if self.t2_counter == 0 && self.t2_armed {
+                self.t2_armed = false;
+                self.set_flag(INT_T2);
+            }
+        }
+    }
+
+    pub fn set_ca1(&mut self, level: bool) {
+        let rising = self.pcr & 0x01 != 0;
+        let active = self.ca1 != level && level == rising;
+        self.ca1 = level;
+
+        if active {
+            self.set_flag(INT_CA1);
+
+            if self.acr & ACR_PA_LATCH != 0 {
+                self.ira_latch = self.port_a();
+            }
+            if self.ca2_control() == CONTROL_HANDSHAKE {
+                self.ca2_out = true;
+            }
+        }
+    }
+
+    pub fn set_ca2(&mut self, level: bool) {
+        let control = self.ca2_control();
+        let active = self.ca2 != level && control < CONTROL_HANDSHAKE &&
+                     level == (control & 0x02 != 0);
+        self.ca2 = level;
+
+        if active {
+            self.set_flag(INT_CA2);
+        }
+    }
+
+    pub fn set_cb1(&mut self, level: bool) {
+        let rising = self.pcr & 0x10 != 0;
+        let active = self.cb1 != level && level == rising;
+        let clocked = self.cb1 != level && level;
+        self.cb1 = level;
+
+        if active {
+            self.set_flag(INT_CB1);
+
+            if self.acr & ACR_PB_LATCH != 0 {
+                self.irb_latch = self.port_b();
+            }
+            if self.cb2_control() == CONTROL_HANDSHAKE {
+                self.cb2_out = true;
+            }
+        }
+
+        let mode = self.shift_mode();
+        if clocked && self.sr_running && (mode == SHIFT_IN_CB1 || mode == SHIFT_OUT_CB1) {
+            self.shift_bit();
+        }
+    }
+
+    pub fn set_cb2(&mut self, level: bool) {
+        let control = self.cb2_control();
+        let active = self.cb2 != level && control < CONTROL_HANDSHAKE &&
+                     level == (control & 0x02 != 0);
+        self.cb2 = level;
+
+        if active {
+            self.set_flag(INT_CB2);
+        }
+    }
+
+    /// The level of CA2, when it is an output
+    pub fn ca2_output(&self) -> bool {
+        self.ca2_out
+    }
+
+    /// The level of CB2, when it is an output (including when the shift
+    /// register is shifting out)
+    pub fn cb2_output(&self) -> bool {
+        self.cb2_out
+    }
+
+    fn ca2_control(&self) -> u8 {
+        (self.pcr >> 1) & 0x07
+    }
+
+    fn cb2_control(&self) -> u8 {
+        (self.pcr >> 5) & 0x07
+    }
+
+    fn shift_mode(&self) -> u8 {
+        (self.acr >> 2) & 0x07
+    }
+
+    fn set_flag(&mut self, flag: u8) {
+        self.ifr |= flag;
+    }
+
+    fn clear_flag(&mut self, flag: u8) {
+        self.ifr &= !flag;
+    }
+
+    /// Reading or writing ORA clears the CA flags and drives the CA2
+    /// handshake
+    fn port_a_accessed(&mut self) {
+        let control = self.ca2_control();
+
+        self.clear_flag(INT_CA1);
+        if control != CONTROL_INDEPENDENT_NEGATIVE && control != CONTROL_INDEPENDENT_POSITIVE {
+            self.clear_flag(INT_CA2);
+        }
+
+        if control == CONTROL_HANDSHAKE || control == CONTROL_PULSE {
+            self.ca2_out = false;
+        }
+        if control == CONTROL_PULSE {
+            self.ca2_pulse = 2;
+        }
+    }
+
+    /// Reading or writing ORB clears the CB flags. Only writes drive the
+    /// CB2 handshake.
+    fn port_b_accessed(&mut self, write: bool) {
+        let control = self.cb2_control();
+
+        self.clear_flag(INT_CB1);
+        if control != CONTROL_INDEPENDENT_NEGATIVE && control != CONTROL_INDEPENDENT_POSITIVE {
+            self.clear_flag(INT_CB2);
+        }
+
+        if write && (control == CONTROL_HANDSHAKE || control == CONTROL_PULSE) {
+            self.cb2_out = false;
+        }
+        if write && control == CONTROL_PULSE {
+            self.cb2_pulse = 2;
+        }
+    }
+
+    fn read_port_a(&self) -> u8 {
+        if self.acr & ACR_PA_LATCH != 0 {
+            self.ira_latch
+        } else {
+            self.port_a()
+        }
+    }
+
+    fn read_port_b(&self) -> u8 {
+        let input = if self.acr & ACR_PB_LATCH != 0 {
+            self.irb_latch
+        } else {
+            self.port_b()
+        };
+
+        (self.orb & self.ddrb) | (input & !self.ddrb)
+    }
+
+    fn write_pcr(&mut self, value: u8) {
+        self.pcr = value;
+
+        self.ca2_out = self.ca2_control() != CONTROL_LOW;
+        self.cb2_out = self.cb2_control() != CONTROL_LOW;
+    }
+
+    /// Reading or writing the shift register starts a new byte
+    fn start_shift(&mut self) {
+        self.clear_flag(INT_SR);
+
+        self.sr_bits = 0;
+        self.sr_running = self.shift_mode() != SHIFT_DISABLED;
+        self.sr_timer = self.shift_half_period();
+        self.sr_clock = true;
+    }
+
+    fn shift_half_period(&self) -> u16 {
+        match self.shift_mode() {
+            SHIFT_IN_PHI2 | SHIFT_OUT_PHI2 => 1,
+            _ => self.t2_latch_low as u16 + 2,
+        }
+    }
+
+    fn shift_bit(&mut self) {
+        let mode = self.shift_mode();
+
+        if mode & 0x04 != 0 {
+            // The 6522 recirculates the bits it shifts out
+            self.cb2_out = self.sr & 0x80 != 0;
+            self.sr = self.sr.rotate_left(1);
+        } else {
+            self.sr = (self.sr << 1) | if self.cb2 { 0x01 } else { 0x00 };
+        }
+
+        self.sr_bits += 1;
+        if self.sr_bits == 8 {
+            self.sr_bits = 0;
+
+            // Free running mode shifts forever, without interrupting
+            if mode != SHIFT_OUT_FREE_RUN {
+                self.sr_running = false;
+                self.set_flag(INT_SR);
+            }
+        }
+    }
+
+    fn tick_timer1(&mut self) {
+        if self.t1_reload {
+            self.t1_reload = false;
+            self.t1_counter = self.t1_latch;
+            return;
+        }
+
+        let underflow = self.t1_counter == 0;
+        self.t1_counter = self.t1_counter.wrapping_sub(1);
+
+        if underflow {
+            let free_run = self.acr & ACR_T1_FREE_RUN != 0;
+            if free_run {
+                // Reloading from the latch takes a cycle of its own
+                self.t1_reload = true;
+            }
+
+            if self.t1_armed {
+                self.set_flag(INT_T1);
+                self.pb7 = if free_run { !self.pb7 } else { true };
+                self.t1_armed = free_run;
+            }
+        }
+    }
+
+    fn tick_timer2(&mut self) {
+        if self.acr & ACR_T2_PULSE_COUNT != 0 {
+            return;
+        }
+
+        if self.t2_reload {
+            self.t2_reload = false;
+            return;
+        }
+
+        let underflow = self.t2_counter == 0;
+        self.t2_counter = self.t2_counter.wrapping_sub(1);
+
+        if underflow && self.t2_armed {
+            self.t2_armed = false;
+            self.set_flag(INT_T2);
+        }
+    }
+
+    fn tick_shift_register(&mut self) {
+        let mode = self.shift_mode();
+        if !self.sr_running || mode == SHIFT_IN_CB1 || mode == SHIFT_OUT_CB1 {
+            return;
+        }
+
+        self.sr_timer -= 1;
+        if self.sr_timer > 0 {
+            return;
+        }
+
+        self.sr_timer = self.shift_half_period();
+        self.sr_clock = !self.sr_clock;
+        if self.sr_clock {
+            self.shift_bit();
+        }
+    }
+
+    fn tick_pulses(&mut self) {
+        if self.ca2_pulse > 0 {
+            self.ca2_pulse -= 1;
+            if self.ca2_pulse == 0 {
+                self.ca2_out = true;
+            }
+        }
+
+        if self.cb2_pulse > 0 {
+            self.cb2_pulse -= 1;
+            if self.cb2_pulse == 0 {
+                self.cb2_out = true;
+            }
+        }
+    }
+}
+
+impl Default for Via {
+    fn default() -> Via {
+        Via::new()
+    }
+}
+
+impl BusDevice for Via {
+    fn read(&mut self, addr: u16) -> u8 {
+        match addr & 0x0F {
+            ORB => {
+                self.port_b_accessed(false);
+                self.read_port_b()
+            }
+            ORA => {
+                self.port_a_accessed();
+                self.read_port_a()
+            }
+            DDRB => self.ddrb,
+            DDRA => self.ddra,
+            T1C_L => {
+                self.clear_flag(INT_T1);
+                self.t1_counter as u8
+            }
+            T1C_H => (self.t1_counter >> 8) as u8,
+            T1L_L => self.t1_latch as u8,
+            T1L_H => (self.t1_latch >> 8) as u8,
+            T2C_L => {
+                self.clear_flag(INT_T2);
+                self.t2_counter as u8
+            }
+            T2C_H => (self.t2_counter >> 8) as u8,
+            SR => {
+                self.start_shift();
+                self.sr
+            }
+            ACR => self.acr,
+            PCR => self.pcr,
+            IFR => {
+                if self.irq() {
+                    self.ifr | INT_ANY
+                } else {
+                    self.ifr
+                }
+            }
+            IER => self.ier | INT_ANY,
+            _ => self.read_port_a(),
+        }
+    }
+
+    fn write(&mut self, addr: u16, byte: u8) {
+        match addr & 0x0F {
+            ORB => {
+                self.port_b_accessed(true);
+                self.orb = byte;
+            }
+            ORA => {
+                self.port_a_accessed();
+                self.ora = byte;
+            }
+            DDRB => self.ddrb = byte,
+            DDRA => self.ddra = byte,
+            T1C_L | T1L_L => self.t1_latch = (self.t1_latch & 0xFF00) | byte as u16,
+            T1C_H => {
+                self.t1_latch = (self.t1_latch & 0x00FF) | ((byte as u16) << 8);
+                self.t1_counter = self.t1_latch;
+                self.t1_reload = true;
+                self.t1_armed = true;
+                self.clear_flag(INT_T1);
+
+                if self.acr & ACR_T1_PB7 != 0 {
+                    self.pb7 = false;
+                }
+            }
+            T1L_H => {
+                self.t1_latch = (self.t1_latch & 0x00FF) | ((byte as u16) << 8);
+                self.clear_flag(INT_T1);
+            }
+            T2C_L => self.t2_latch_low = byte,
+            T2C_H => {
+                self.t2_counter = ((byte as u16) << 8) | self.t2_latch_low as u16;
+                self.t2_reload = true;
+                self.t2_armed = true;
+                self.clear_flag(INT_T2);
+            }
+            SR => {
+                self.sr = byte;
+                self.start_shift();
+            }
+            ACR => self.acr = byte,
+            PCR => self.write_pcr(byte),
+            IFR => self.clear_flag(byte & !INT_ANY),
+            IER => {
+                if byte & INT_ANY != 0 {
+                    self.ier |= byte & !INT_ANY;
+                } else {
+                    self.ier &= !byte;
+                }
+            }
+            ORA_NO_HANDSHAKE => self.ora = byte,
+            _ => unreachable!(),
+        }
+    }
+
+    fn tick(&mut self) {
+        self.tick_timer1();
+        self.tick_timer2();
+        self.tick_shift_register();
+        self.tick_pulses();
+    }
+
+    fn irq(&self) -> bool {
+        self.ifr & self.ier & !INT_ANY != 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tick_n(via: &mut Via, n: u32) {
+        for _ in 0..n {
+            via.tick();
+        }
+    }
+
+    #[test]
+    fn port_pins_follow_data_direction() {
+        let mut via = Via::new();
+        via.set_port_a_input(0x0F);
+
+        via.write(DDRA, 0xF0);
+        via.write(ORA, 0xA5);
+
+        assert_eq!(0xAF, via.port_a());
+        assert_eq!(0xAF, via.read(ORA));
+    }
+
+    #[test]
+    fn interrupt_enable_register_sets_and_clears_bits() {
+        let mut via = Via::new();
+
+        via.write(IER, 0x80 | INT_T1 | INT_CA1);
+        via.write(IER, INT_CA1);
+
+        assert_eq!(0x80 | INT_T1, via.read(IER));
+    }
+
+    #[test]
+    fn timer1_one_shot_interrupts_once() {
+        let mut via = Via::new();
+        via.write(IER, 0x80 | INT_T1);
+        via.write(T1C_L, 0x05);
+        via.write(T1C_H, 0x00);
+        via.tick();
+
+        tick_n(&mut via, 5);
+        assert!(!via.irq());
+        via.tick();
+        assert!(via.irq());
+        assert_eq!(0x80 | INT_T1, via.read(IFR));
+
+        via.read(T1C_L);
+        assert!(!via.irq());
+
+        tick_n(&mut via, 0x10000);
+        assert!(!via.irq());
+    }
+
+    #[test]
+    fn timer1_free_run_toggles_pb7() {
+        let mut via = Via::new();
+        via.write(ACR, ACR_T1_FREE_RUN | ACR_T1_PB7);
+        via.write(T1C_L, 0x03);
+        via.write(T1C_H, 0x00);
+        via.tick();
+
+        assert_eq!(0x00, via.port_b() & 0x80);
+
+        // Each period is the latch value plus two cycles
+        tick_n(&mut via, 4);
+        assert_eq!(0x80, via.port_b() & 0x80);
+        tick_n(&mut via, 5);
+        assert_eq!(0x00, via.port_b() & 0x80);
+        tick_n(&mut via, 5);
+        assert_eq!(0x80, via.port_b() & 0x80);
+    }
+
+    #[test]
+    fn timer2_counts_pulses_on_pb6() {
+        let mut via = Via::new();
+        via.write(ACR, ACR_T2_PULSE_COUNT);
+        via.write(T2C_L, 0x03);
+        via.write(T2C_H, 0x00);
+
+        for _ in 0..3 {
+            assert_eq!(0, via.read(IFR) & INT_T2);
+            via.set_port_b_input(0xBF);
+            via.set_port_b_input(0xFF);
+        }
+
+        assert_eq!(INT_T2, via.read(IFR) & INT_T2);
+    }
+
+    #[test]
+    fn shift_register_shifts_out_under_phi2() {
+        let mut via = Via::new();
+        via.write(ACR, SHIFT_OUT_PHI2 << 2);
+        via.write(SR, 0x81);
+
+        via.tick();
+        via.tick();
+        assert!(via.cb2_output());
+        via.tick();
+        via.tick();
+        assert!(!via.cb2_output());
+
+        tick_n(&mut via, 12);
+        assert_eq!(INT_SR, via.read(IFR) & INT_SR);
+        assert_eq!(0x81, via.sr);
+    }
+
+    #[test]
+    fn shift_register_shifts_in_on_cb1() {
+        let mut via = Via::new();
+        via.write(ACR, SHIFT_IN_CB1 << 2);
+        via.read(SR);
+
+        for bit in &[true, false, true, false, false, false, false, true] {
+            via.set_cb2(*bit);
+            via.set_cb1(false);
+            via.set_cb1(true);
+        }
+
+        assert_eq!(0xA1, via.read(SR));
+        assert_eq!(0, via.read(IFR) & INT_SR);
+    }
+
+    #[test]
+    fn ca2_handshakes_with_ca1() {
+        let mut via = Via::new();
+        via.write(PCR, CONTROL_HANDSHAKE << 1);
+
+        via.write(ORA, 0x55);
+        assert!(!via.ca2_output());
+
+        // Data taken: the peripheral acknowledges on CA1 (negative edge)
+        via.set_ca1(false);
+        assert!(via.ca2_output());
+        assert_eq!(INT_CA1, via.read(IFR) & INT_CA1);
+    }
+
+    #[test]
+    fn ca2_pulse_lasts_one_cycle() {
+        let mut via = Via::new();
+        via.write(PCR, CONTROL_PULSE << 1);
+
+        via.read(ORA);
+        via.tick();
+        assert!(!via.ca2_output());
+        via.tick();
+        assert!(via.ca2_output());
+    }
+}
@@ -15,6 +15,10 @@ pub struct Disassembler {
     /// in memory so that it can adjust its memory
     /// offsets
     code_offset: u16,
+
+    /// The bank the code was read from, for bank switched
+    /// memory. Shown in front of each address.
+    bank: Option<usize>,
 }
 
 /// A 6502 instruction disassembler
@@ -42,6 +46,7 @@ impl Disassembler {
             disable_offsets: false,
             disable_opcodes: true,
             code_offset: 0,
+            bank: None,
         }
     }
 
@@ -69,6 +74,7 @@ impl Disassembler {
             disable_offsets: true,
             disable_opcodes: true,
             code_offset: 0,
+            bank: None,
         }
     }
 
@@ -96,6 +102,7 @@ impl Disassembler {
             disable_offsets: false,
             disable_opcodes: false,
             code_offset: 0,
+            bank: None,
         }
     }
 
@@ -104,6 +111,35 @@ impl Disassembler {
             disable_offsets: false,
             disable_opcodes: false,
             code_offset: offset,
+            bank: None,
+        }
+    }
+
+    /// Creates an instance of the Disassembler for code read from
+    /// `bank` of bank switched memory, mapped in at `offset`
+    ///
+    /// # Example
+    /// ```
+    /// use rs6502::Disassembler;
+    ///
+    /// let dasm = Disassembler::with_bank(0x8000, 2);
+    ///
+    /// let code: Vec<u8> = vec![0xA9, 0x20, 0x8D, 0x00, 0x44];
+    /// let asm = dasm.disassemble(&code);
+    ///
+    /// assert_eq!(Disassembler::clean_asm("
+    ///
+    ///     02:8000 A9 20    LDA #$20
+    ///     02:8002 8D 00 44 STA $4400
+    ///
+    /// "), Disassembler::clean_asm(asm));
+    /// ```
+    pub fn with_bank(offset: u16, bank: usize) -> Disassembler {
+        Disassembler {
+            disable_offsets: false,
+            disable_opcodes: false,
+            code_offset: offset,
+            bank: Some(bank),
         }
     }
 
@@ -225,13 +261,13 @@ impl Disassembler {
                     }
                 } else {
                     if self.disable_opcodes {
-                        format!("{:04X} {}{}\n",
-                                i + self.code_offset as usize,
+                        format!("{} {}{}\n",
+                                self.format_address(i),
                                 opcode.mnemonic,
                                 val.1)
                     } else {
-                        format!("{:04X} {:<8} {}{}\n",
-                                i + self.code_offset as usize,
+                        format!("{} {:<8} {}{}\n",
+                                self.format_address(i),
                                 val.0,
                                 opcode.mnemonic,
                                 val.1)
@@ -243,8 +279,8 @@ impl Disassembler {
                 let opcode_text = if self.disable_offsets {
                     format!("{:02X}\n", raw[i] as u8)
                 } else {
-                    format!("{:04X} {:02X}\n",
-                            i + self.code_offset as usize,
+                    format!("{} {:02X}\n",
+                            self.format_address(i),
                             raw[i] as u8)
                 };
                 result.push((opcode_text, i as u16));
@@ -255,6 +291,15 @@ impl Disassembler {
         result
     }
 
+    fn format_address(&self, i: usize) -> String {
+        let addr = i + self.code_offset as usize;
+
+        match self.bank {
+            Some(bank) => format!("{:02X}:{:04X}", bank, addr),
+            None => format!("{:04X}", addr),
+        }
+    }
+
     /// Returns a Vector of Strings where each entry
     /// is a non-empty line of assembly instructions, with
     /// all leading and trailing whitespace removed.
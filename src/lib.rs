@@ -3,9 +3,17 @@ extern crate byteorder;
 mod assembler;
 mod disassembler;
 mod cpu;
+mod devices;
+mod mappers;
 mod opcodes;
 
-pub use assembler::{Assembler, CodeSegment};
-pub use cpu::{Cpu, CpuError, CpuStepResult};
+pub use assembler::{Assembler, CodeSegment, SymbolTable};
+pub use cpu::{BacktraceFrame, BusAccess, BusAccessKind, BusDevice, CallFrame, CodeFinding,
+              CodeFindingKind, CodeReport, Cpu, CpuError, CpuErrorKind, CpuStepResult,
+              CpuTickResult, CpuVariant, EventHandler, EventId, FaultKind, FaultPolicy, FrameKind,
+              MemoryBus, MemoryFault, Pins, PowerOnPattern, PowerOnState, RegionKind, Scheduler,
+              StackAnomaly, StackAnomalyKind, UninitializedRead};
+pub use devices::{Acia, NonBlockingReader, Riot, RiotRam, Via};
 pub use disassembler::Disassembler;
+pub use mappers::{Banked16k, MapperError, Mmc1, Slots8k};
 pub use opcodes::OpCode;
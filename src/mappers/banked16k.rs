@@ -0,0 +1,128 @@
+use cpu::BusDevice;
+
+use super::{bank_count, MapperError, BANK_16K};
+
+/// A 32KB window split into two 16KB halves: the first half shows any bank
+/// of the ROM, and the second is fixed to the last bank, which usually
+/// holds the reset code and vectors. Map it over the top 32KB of memory.
+///
+/// Writing a bank number to the select address switches the first half.
+/// ROM can't be written, so other writes are ignored.
+pub struct Banked16k {
+    rom: Vec<u8>,
+    banks: usize,
+    selected: usize,
+    select: Option<u16>,
+}
+
+impl Banked16k {
+    /// Creates a mapper that switches banks on writes to `select`,
+    /// relative to the start of the window
+    pub fn new(rom: Vec<u8>, select: u16) -> Result<Banked16k, MapperError> {
+        Banked16k::create(rom, Some(select))
+    }
+
+    /// Creates a NES UxROM mapper, where a write anywhere in the window
+    /// selects the bank
+    pub fn uxrom(rom: Vec<u8>) -> Result<Banked16k, MapperError> {
+        Banked16k::create(rom, None)
+    }
+
+    fn create(rom: Vec<u8>, select: Option<u16>) -> Result<Banked16k, MapperError> {
+        let banks = bank_count(&rom, BANK_16K)?;
+
+        Ok(Banked16k {
+            rom,
+            banks,
+            selected: 0,
+            select,
+        })
+    }
+
+    fn byte_at(&self, addr: u16) -> u8 {
+        let bank = self.bank_index(addr);
+        self.rom[bank * BANK_16K + (addr as usize & (BANK_16K - 1))]
+    }
+
+    fn bank_index(&self, addr: u16) -> usize {
+        if (addr as usize) & BANK_16K == 0 {
+            self.selected
+        } else {
+            self.banks - 1
+        }
+    }
+}
+
+impl BusDevice for Banked16k {
+    fn read(&mut self, addr: u16) -> u8 {
+        self.byte_at(addr)
+    }
+
+    fn write(&mut self, addr: u16, byte: u8) {
+        if self.select.map_or(true, |select| select == addr) {
+            self.selected = byte as usize % self.banks;
+        }
+    }
+
+    fn peek(&self, addr: u16) -> Option<u8> {
+        Some(self.byte_at(addr))
+    }
+
+    fn bank(&self, addr: u16) -> Option<usize> {
+        Some(self.bank_index(addr))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A ROM where every byte holds the number of its bank
+    fn rom(banks: usize) -> Vec<u8> {
+        (0..banks * BANK_16K).map(|i| (i / BANK_16K) as u8).collect()
+    }
+
+    #[test]
+    fn last_bank_is_fixed_in_the_upper_half() {
+        let mut mapper = Banked16k::new(rom(4), 0x7FFF).unwrap();
+
+        assert_eq!(0, mapper.read(0x0000));
+        assert_eq!(3, mapper.read(0x4000));
+        assert_eq!(Some(3), mapper.bank(0x7FFF));
+    }
+
+    #[test]
+    fn writing_the_select_address_switches_the_lower_half() {
+        let mut mapper = Banked16k::new(rom(4), 0x7FFF).unwrap();
+
+        mapper.write(0x1234, 2);
+        assert_eq!(0, mapper.read(0x3FFF));
+
+        mapper.write(0x7FFF, 2);
+        assert_eq!(2, mapper.read(0x3FFF));
+        assert_eq!(3, mapper.read(0x4000));
+        assert_eq!(Some(2), mapper.bank(0x0000));
+    }
+
+    #[test]
+    fn uxrom_switches_on_any_write() {
+        let mut mapper = Banked16k::uxrom(rom(8)).unwrap();
+
+        mapper.write(0x4321, 5);
+        assert_eq!(5, mapper.read(0x0000));
+
+        // Bank numbers wrap around the size of the ROM
+        mapper.write(0x0000, 9);
+        assert_eq!(1, mapper.read(0x0000));
+    }
+
+    #[test]
+    fn errors_on_roms_that_are_not_whole_banks() {
+        let mut partial = rom(2);
+        partial.pop();
+
+        assert_eq!("Banked data must be a multiple of 16384 bytes, got 32767",
+                   Banked16k::uxrom(partial).err().unwrap().message);
+        assert!(Banked16k::new(Vec::new(), 0x7FFF).is_err());
+    }
+}
@@ -0,0 +1,236 @@
+use cpu::BusDevice;
+
+use super::{bank_count, MapperError, BANK_16K};
+
+// Control register bits
+const CONTROL_PRG_MODE: u8 = 0x0C;
+const CONTROL_RESET: u8 = 0x0C;
+
+// PRG bank modes, other than switching 32KB at a time
+const PRG_FIX_FIRST: u8 = 0x08;
+const PRG_FIX_LAST: u8 = 0x0C;
+
+// Writes with this bit set reset the shift register
+const WRITE_RESET: u8 = 0x80;
+
+/// The NES MMC1 mapper, covering the 32KB of PRG ROM at $8000-$FFFF.
+///
+/// Registers are loaded serially: five writes each shift one bit (bit 0 of
+/// the byte written) into a shift register, and the fifth write copies it
+/// into the register chosen by address bits 13 and 14. A write with bit 7
+/// set resets the shift register. Like the real chip, the second of two
+/// writes on consecutive cycles (from a read-modify-write instruction) is
+/// ignored.
+///
+/// There is no PPU here, so the CHR bank registers are only stored for the
+/// host to read.
+pub struct Mmc1 {
+    rom: Vec<u8>,
+    banks: usize,
+    shift: u8,
+    shift_count: u8,
+    control: u8,
+    chr_bank0: u8,
+    chr_bank1: u8,
+    prg_bank: u8,
+    cycles_since_write: u32,
+}
+
+impl Mmc1 {
+    pub fn new(rom: Vec<u8>) -> Result<Mmc1, MapperError> {
+        let banks = bank_count(&rom, BANK_16K)?;
+
+        Ok(Mmc1 {
+            rom,
+            banks,
+            shift: 0,
+            shift_count: 0,
+            control: CONTROL_RESET,
+            chr_bank0: 0,
+            chr_bank1: 0,
+            prg_bank: 0,
+            cycles_since_write: u32::MAX,
+        })
+    }
+
+    pub fn control(&self) -> u8 {
+        self.control
+    }
+
+    pub fn chr_bank0(&self) -> u8 {
+        self.chr_bank0
+    }
+
+    pub fn chr_bank1(&self) -> u8 {
+        self.chr_bank1
+    }
+
+    fn byte_at(&self, addr: u16) -> u8 {
+        let bank = self.bank_index(addr);
+        self.rom[bank * BANK_16K + (addr as usize & (BANK_16K - 1))]
+    }
+
+    fn bank_index(&self, addr: u16) -> usize {
+        let upper = (addr as usize) & BANK_16K != 0;
+        let selected = (self.prg_bank & 0x0F) as usize;
+
+        let bank = match self.control & CONTROL_PRG_MODE {
+            PRG_FIX_FIRST => if upper { selected } else { 0 },
+            PRG_FIX_LAST => if upper { self.banks - 1 } else { selected },
+            // 32KB mode ignores the low bit of the bank number
+            _ => (selected & !1) + if upper { 1 } else { 0 },
+        };
+
+        bank % self.banks
+    }
+
+    fn load_register(&mut self, addr: u16, value: u8) {
+        match (addr >> 13) & 0x03 {
+            0 => self.control = value,
+            1 => self.chr_bank0 = value,
+            2 => self.chr_bank1 = value,
+            _ => self.prg_bank = value,
+        }
+    }
+}
+
+impl BusDevice for Mmc1 {
+    fn read(&mut self, addr: u16) -> u8 {
+        self.byte_at(addr)
+    }
+
+    fn write(&mut self, addr: u16, byte: u8) {
+        let consecutive = self.cycles_since_write <= 1;
+        self.cycles_since_write = 0;
+        if consecutive {
+            return;
+        }
+
+        if byte & WRITE_RESET != 0 {
+            self.shift = 0;
+            self.shift_count = 0;
+            self.control |= CONTROL_RESET;
+            return;
+        }
+
+        self.shift |= (byte & 0x01) << self.shift_count;
+        self.shift_count += 1;
+
+        if self.shift_count == 5 {
+            let value = self.shift;
+            self.shift = 0;
+            self.shift_count = 0;
+            self.load_register(addr, value);
+        }
+    }
+
+    fn tick(&mut self) {
+        self.cycles_since_write = self.cycles_since_write.saturating_add(1);
+    }
+
+    fn peek(&self, addr: u16) -> Option<u8> {
+        Some(self.byte_at(addr))
+    }
+
+    fn bank(&self, addr: u16) -> Option<usize> {
+        Some(self.bank_index(addr))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PRG_32K: u8 = 0x00;
+
+    /// A ROM where every byte holds the number of its bank
+    fn rom(banks: usize) -> Vec<u8> {
+        (0..banks * BANK_16K).map(|i| (i / BANK_16K) as u8).collect()
+    }
+
+    /// Loads a register the way software does, one bit per write
+    fn load(mapper: &mut Mmc1, addr: u16, value: u8) {
+        for bit in 0..5 {
+            mapper.write(addr, value >> bit);
+            mapper.tick();
+            mapper.tick();
+        }
+    }
+
+    #[test]
+    fn powers_up_with_the_last_bank_fixed() {
+        let mut mapper = Mmc1::new(rom(8)).unwrap();
+
+        assert_eq!(0, mapper.read(0x0000));
+        assert_eq!(7, mapper.read(0x4000));
+    }
+
+    #[test]
+    fn prg_bank_switches_the_lower_half() {
+        let mut mapper = Mmc1::new(rom(8)).unwrap();
+        load(&mut mapper, 0x6000, 5);
+
+        assert_eq!(5, mapper.read(0x0000));
+        assert_eq!(7, mapper.read(0x4000));
+        assert_eq!(Some(5), mapper.bank(0x0000));
+    }
+
+    #[test]
+    fn can_fix_the_first_bank() {
+        let mut mapper = Mmc1::new(rom(8)).unwrap();
+        load(&mut mapper, 0x0000, PRG_FIX_FIRST);
+        load(&mut mapper, 0x6000, 3);
+
+        assert_eq!(0, mapper.read(0x0000));
+        assert_eq!(3, mapper.read(0x4000));
+    }
+
+    #[test]
+    fn can_switch_32k_at_a_time() {
+        let mut mapper = Mmc1::new(rom(8)).unwrap();
+        load(&mut mapper, 0x0000, PRG_32K);
+        load(&mut mapper, 0x6000, 5);
+
+        assert_eq!(4, mapper.read(0x0000));
+        assert_eq!(5, mapper.read(0x4000));
+    }
+
+    #[test]
+    fn chr_banks_are_stored() {
+        let mut mapper = Mmc1::new(rom(2)).unwrap();
+        load(&mut mapper, 0x2000, 0x11);
+        load(&mut mapper, 0x4000, 0x07);
+
+        assert_eq!(0x11, mapper.chr_bank0());
+        assert_eq!(0x07, mapper.chr_bank1());
+    }
+
+    #[test]
+    fn reset_write_clears_the_shift_register() {
+        let mut mapper = Mmc1::new(rom(8)).unwrap();
+        mapper.write(0x6000, 1);
+        mapper.tick();
+        mapper.tick();
+        mapper.write(0x6000, WRITE_RESET);
+        mapper.tick();
+        mapper.tick();
+        load(&mut mapper, 0x6000, 2);
+
+        assert_eq!(2, mapper.read(0x0000));
+    }
+
+    #[test]
+    fn ignores_writes_on_consecutive_cycles() {
+        let mut mapper = Mmc1::new(rom(8)).unwrap();
+        load(&mut mapper, 0x0000, PRG_32K);
+
+        // The double write of a read-modify-write instruction
+        mapper.write(0x0000, WRITE_RESET);
+        mapper.tick();
+        mapper.write(0x0000, 0x00);
+        mapper.tick();
+
+        assert_eq!(CONTROL_RESET, mapper.control() & CONTROL_PRG_MODE);
+        assert_eq!(0, mapper.shift_count);
+    }
+}
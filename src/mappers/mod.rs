@@ -0,0 +1,48 @@
+mod banked16k;
+mod mmc1;
+mod slots8k;
+
+pub use self::banked16k::Banked16k;
+pub use self::mmc1::Mmc1;
+pub use self::slots8k::Slots8k;
+
+const BANK_8K: usize = 0x2000;
+const BANK_16K: usize = 0x4000;
+
+/// Why a mapper couldn't be made from the data it was given
+#[derive(Debug, PartialEq)]
+pub struct MapperError {
+    pub message: String,
+}
+
+impl MapperError {
+    fn partial_bank(bank_size: usize, length: usize) -> MapperError {
+        MapperError::from(format!("Banked data must be a multiple of {} bytes, got {}", bank_size, length))
+    }
+
+    fn no_slots() -> MapperError {
+        MapperError::from("A mapper needs at least one slot")
+    }
+}
+
+impl From<String> for MapperError {
+    fn from(error: String) -> MapperError {
+        MapperError { message: error }
+    }
+}
+
+impl From<&str> for MapperError {
+    fn from(error: &str) -> MapperError {
+        MapperError { message: error.into() }
+    }
+}
+
+/// The number of whole `bank_size` banks in `data`. Data that doesn't
+/// split evenly into at least one bank is an error, as no cartridge would.
+fn bank_count(data: &[u8], bank_size: usize) -> Result<usize, MapperError> {
+    if data.is_empty() || data.len() % bank_size != 0 {
+        return Err(MapperError::partial_bank(bank_size, data.len()));
+    }
+
+    Ok(data.len() / bank_size)
+}
@@ -0,0 +1,131 @@
+use cpu::BusDevice;
+
+use super::{bank_count, MapperError, BANK_8K};
+
+/// A window made up of 8KB slots, each of which can show any 8KB bank of
+/// the backing store. Slot `n` is switched by writing a bank number to
+/// `control + n` (relative to the start of the window). Initially slot `n`
+/// shows bank `n`.
+///
+/// The backing store is ROM unless it is created with `Slots8k::ram`, in
+/// which case writes outside of the control registers land in whichever
+/// bank is mapped at that address.
+pub struct Slots8k {
+    data: Vec<u8>,
+    banks: usize,
+    slots: Vec<usize>,
+    control: u16,
+    writable: bool,
+}
+
+impl Slots8k {
+    /// Creates `slots` slots over a ROM. Map it over `slots * 8KB` bytes.
+    pub fn new(rom: Vec<u8>, slots: usize, control: u16) -> Result<Slots8k, MapperError> {
+        Slots8k::create(rom, slots, control, false)
+    }
+
+    /// Creates `slots` slots over banked RAM
+    pub fn ram(ram: Vec<u8>, slots: usize, control: u16) -> Result<Slots8k, MapperError> {
+        Slots8k::create(ram, slots, control, true)
+    }
+
+    fn create(data: Vec<u8>, slots: usize, control: u16, writable: bool) -> Result<Slots8k, MapperError> {
+        let banks = bank_count(&data, BANK_8K)?;
+        if slots == 0 {
+            return Err(MapperError::no_slots());
+        }
+
+        Ok(Slots8k {
+            data,
+            banks,
+            slots: (0..slots).map(|slot| slot % banks).collect(),
+            control,
+            writable,
+        })
+    }
+
+    fn slot(&self, addr: u16) -> usize {
+        (addr as usize / BANK_8K) % self.slots.len()
+    }
+
+    fn offset(&self, addr: u16) -> usize {
+        self.slots[self.slot(addr)] * BANK_8K + (addr as usize & (BANK_8K - 1))
+    }
+}
+
+impl BusDevice for Slots8k {
+    fn read(&mut self, addr: u16) -> u8 {
+        self.data[self.offset(addr)]
+    }
+
+    fn peek(&self, addr: u16) -> Option<u8> {
+        Some(self.data[self.offset(addr)])
+    }
+
+    fn write(&mut self, addr: u16, byte: u8) {
+        let register = addr.wrapping_sub(self.control) as usize;
+
+        if register < self.slots.len() {
+            self.slots[register] = byte as usize % self.banks;
+        } else if self.writable {
+            let offset = self.offset(addr);
+            self.data[offset] = byte;
+        }
+    }
+
+    fn bank(&self, addr: u16) -> Option<usize> {
+        Some(self.slots[self.slot(addr)])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Banked data where every byte holds the number of its bank
+    fn data(banks: usize) -> Vec<u8> {
+        (0..banks * BANK_8K).map(|i| (i / BANK_8K) as u8).collect()
+    }
+
+    #[test]
+    fn slots_start_on_consecutive_banks() {
+        let mut mapper = Slots8k::new(data(16), 4, 0x7FF0).unwrap();
+
+        assert_eq!(0, mapper.read(0x0000));
+        assert_eq!(1, mapper.read(0x2000));
+        assert_eq!(3, mapper.read(0x7FFF));
+    }
+
+    #[test]
+    fn control_registers_switch_each_slot() {
+        let mut mapper = Slots8k::new(data(16), 4, 0x7FF0).unwrap();
+
+        mapper.write(0x7FF1, 10);
+        mapper.write(0x7FF3, 15);
+
+        assert_eq!(0, mapper.read(0x0000));
+        assert_eq!(10, mapper.read(0x2000));
+        assert_eq!(15, mapper.read(0x6000));
+        assert_eq!(Some(10), mapper.bank(0x3FFF));
+    }
+
+    #[test]
+    fn rom_ignores_writes() {
+        let mut mapper = Slots8k::new(data(4), 2, 0x3FF0).unwrap();
+        mapper.write(0x0000, 0xFF);
+
+        assert_eq!(0, mapper.read(0x0000));
+    }
+
+    #[test]
+    fn ram_writes_land_in_the_mapped_bank() {
+        let mut mapper = Slots8k::ram(vec![0; 4 * BANK_8K], 1, 0x1FFF).unwrap();
+
+        mapper.write(0x1FFF, 2);
+        mapper.write(0x0010, 0x42);
+        assert_eq!(0x42, mapper.read(0x0010));
+
+        mapper.write(0x1FFF, 1);
+        assert_eq!(0x00, mapper.read(0x0010));
+    }
+}
@@ -985,7 +985,7 @@ static OpCodes: [OpCode; 151] = [OpCode {
                                      code: 0x95,
                                      mnemonic: "STA",
                                      length: 2,
-                                     time: 3,
+                                     time: 4,
                                      mode: AddressingMode::ZeroPageX,
                                  },
                                  OpCode {
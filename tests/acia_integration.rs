@@ -0,0 +1,117 @@
+extern crate rs6502;
+
+use std::cell::RefCell;
+use std::io::Cursor;
+use std::rc::Rc;
+
+use rs6502::{Acia, Assembler, Cpu};
+
+type TestAcia = Acia<Cursor<Vec<u8>>, Vec<u8>>;
+
+/// Assembles `asm` at $C000 and maps an ACIA at $5000 that receives `input`
+fn cpu_with_acia(asm: &str, input: &[u8]) -> (Cpu, Rc<RefCell<TestAcia>>) {
+    let mut cpu = Cpu::new();
+    let mut assembler = Assembler::new();
+    let acia = Rc::new(RefCell::new(Acia::new(Cursor::new(input.to_vec()), Vec::new())));
+
+    cpu.memory.map_device(0x5000, 0x5003, acia.clone());
+
+    let segments = assembler.assemble_string(asm, 0xC000).unwrap();
+    for segment in segments {
+        cpu.load(&segment.code[..], segment.address).unwrap();
+    }
+    cpu.set_reset_vector(0xC000);
+
+    // IRQ handler at $D000
+    cpu.memory[0xFFFE] = 0x00;
+    cpu.memory[0xFFFF] = 0xD0;
+
+    cpu.reset();
+
+    (cpu, acia)
+}
+
+#[test]
+#[allow(non_snake_case)]
+fn INTEGRATION_ACIA_can_print_a_string() {
+    let asm = "
+        DATA = $5000
+        MESSAGE = $E000
+
+        LDX #$00
+    LOOP:
+        LDA MESSAGE,X
+        BEQ DONE
+        STA DATA
+        INX
+        JMP LOOP
+    DONE:
+        JMP DONE
+
+    .ORG $E000
+        .BYTE #$48, #$45, #$4C, #$4C, #$4F, #$00
+    ";
+
+    let (mut cpu, acia) = cpu_with_acia(asm, b"");
+    cpu.run_for(500).unwrap();
+
+    assert_eq!(b"HELLO", &acia.borrow().writer()[..]);
+}
+
+#[test]
+#[allow(non_snake_case)]
+fn INTEGRATION_ACIA_can_poll_for_input() {
+    let asm = "
+        DATA = $5000
+        STATUS = $5001
+        COMMAND = $5002
+
+        LDA #$0B    ; DTR on, receiver interrupt off
+        STA COMMAND
+    WAIT:
+        LDA STATUS
+        AND #$08
+        BEQ WAIT
+        LDA DATA
+        CLC
+        ADC #$01
+        STA DATA
+        JMP WAIT
+    ";
+
+    let (mut cpu, acia) = cpu_with_acia(asm, b"HAL");
+    cpu.run_for(500).unwrap();
+
+    assert_eq!(b"IBM", &acia.borrow().writer()[..]);
+}
+
+#[test]
+#[allow(non_snake_case)]
+fn INTEGRATION_ACIA_receive_interrupt_fires_for_each_byte() {
+    let asm = "
+        DATA = $5000
+        STATUS = $5001
+        COMMAND = $5002
+
+        LDA #$09    ; DTR on, receiver interrupt on
+        STA COMMAND
+        CLI
+    LOOP:
+        JMP LOOP
+
+    .ORG $D000
+        PHA
+        LDA STATUS  ; Acknowledge the interrupt
+        LDA DATA
+        STA DATA
+        INC $00
+        PLA
+        RTI
+    ";
+
+    let (mut cpu, acia) = cpu_with_acia(asm, b"ping");
+    cpu.run_for(1000).unwrap();
+
+    assert_eq!(4, cpu.memory[0x00]);
+    assert_eq!(b"ping", &acia.borrow().writer()[..]);
+}
@@ -4,6 +4,9 @@ extern crate rs6502;
 mod tests {
     mod cpu {
 
+        use std::cell::RefCell;
+        use std::rc::Rc;
+
         use rs6502::*;
 
         #[test]
@@ -17,7 +20,7 @@ mod tests {
         fn can_load_code_segment_into_memory() {
             let fake_code = vec![0x0A, 0x0B, 0x0C, 0x0D];
             let mut cpu = Cpu::new();
-            cpu.load(&fake_code[..], None);
+            cpu.load_with_reset_vector(&fake_code[..], None);
             cpu.reset();
 
             let memory_sum: u32 = cpu.memory.iter().map(|n| *n as u32).sum();
@@ -28,7 +31,7 @@ mod tests {
         fn can_load_code_segment_at_default_address() {
             let fake_code = vec![0x0A, 0x0B, 0x0C, 0x0D];
             let mut cpu = Cpu::new();
-            cpu.load(&fake_code[..], None);
+            cpu.load_with_reset_vector(&fake_code[..], None);
             cpu.reset();
 
             assert_eq!(0x0D, cpu.memory.read_byte(0xC003));
@@ -41,7 +44,7 @@ mod tests {
         fn can_load_code_segment_at_specific_address() {
             let fake_code = vec![0x0A, 0x0B, 0x0C, 0x0D];
             let mut cpu = Cpu::new();
-            cpu.load(&fake_code[..], 0xF000);
+            cpu.load_with_reset_vector(&fake_code[..], 0xF000);
             cpu.reset();
 
             assert_eq!(0x0D, cpu.memory.read_byte(0xF003));
@@ -54,18 +57,242 @@ mod tests {
         fn errors_when_code_segment_extends_past_memory_bounds() {
             let fake_code = vec![0x0A, 0x0B, 0x0C, 0x0D];
             let mut cpu = Cpu::new();
-            let load_result = cpu.load(&fake_code[..], 0xFFFD);
+            let load_result = cpu.load_with_reset_vector(&fake_code[..], 0xFFFD);
             cpu.reset();
 
             assert_eq!(Err(CpuError::code_segment_out_of_range(0xFFFD)),
                        load_result);
         }
 
+        #[test]
+        fn load_does_not_touch_reset_vector() {
+            let fake_code = vec![0x0A, 0x0B, 0x0C, 0x0D];
+            let mut cpu = Cpu::new();
+            cpu.load(&fake_code[..], None);
+
+            assert_eq!(&[0x00, 0x00], &cpu.memory[0xFFFC..0xFFFE]);
+        }
+
+        #[test]
+        fn reset_reads_vector_and_sets_interrupt_flag() {
+            let mut cpu = Cpu::new();
+            cpu.set_reset_vector(0x1234);
+            cpu.flags.interrupt_disabled = false;
+            let cycles = cpu.reset();
+
+            assert_eq!(0x1234, cpu.registers.PC);
+            assert!(cpu.flags.interrupt_disabled);
+            assert_eq!(7, cycles);
+            assert_eq!(7, cpu.cycles);
+        }
+
+        #[test]
+        fn reset_decrements_stack_pointer_without_writing() {
+            let mut cpu = Cpu::new();
+            cpu.reset();
+
+            assert_eq!(0xFC, cpu.stack.pointer);
+            assert!(cpu.memory[0x100..0x200].iter().all(|b| *b == 0));
+
+            cpu.stack.pointer = 0x01;
+            cpu.reset();
+
+            assert_eq!(0xFE, cpu.stack.pointer);
+        }
+
+        #[test]
+        fn reset_preserves_registers() {
+            let mut cpu = Cpu::new();
+            cpu.registers.A = 0x11;
+            cpu.registers.X = 0x22;
+            cpu.registers.Y = 0x33;
+            cpu.flags.carry = true;
+            cpu.reset();
+
+            assert_eq!(0x11, cpu.registers.A);
+            assert_eq!(0x22, cpu.registers.X);
+            assert_eq!(0x33, cpu.registers.Y);
+            assert!(cpu.flags.carry);
+        }
+
+        #[test]
+        fn power_on_fills_ram_and_registers() {
+            let mut cpu = Cpu::new();
+            let cycles = cpu.power_on(PowerOnState {
+                ram: PowerOnPattern::Ones,
+                registers: PowerOnPattern::Zero,
+            });
+
+            assert_eq!(7, cycles);
+            assert!(cpu.memory.iter().all(|b| *b == 0xFF));
+            assert_eq!(0xFFFF, cpu.registers.PC);
+            assert_eq!(0x00, cpu.registers.A);
+            assert_eq!(0xFD, cpu.stack.pointer);
+            assert!(cpu.flags.interrupt_disabled);
+            assert!(!cpu.flags.carry);
+        }
+
+        #[test]
+        fn power_on_with_random_pattern_is_repeatable() {
+            let state = PowerOnState {
+                ram: PowerOnPattern::Random(1234),
+                registers: PowerOnPattern::Random(5678),
+            };
+            let mut first = Cpu::new();
+            let mut second = Cpu::new();
+            first.power_on(state);
+            second.power_on(state);
+
+            assert_eq!(&first.memory[..], &second.memory[..]);
+            assert_eq!(first.registers.A, second.registers.A);
+            assert_eq!(first.stack.pointer, second.stack.pointer);
+            assert!(first.memory.iter().any(|b| *b != first.memory[0]));
+        }
+
+        #[test]
+        fn wraps_program_counter_at_end_of_memory() {
+            let mut cpu = Cpu::new();
+            cpu.memory[0xFFFF] = 0xE8; // INX
+            cpu.memory[0x0000] = 0xE8; // INX
+            cpu.registers.PC = 0xFFFF;
+
+            cpu.step_n(2).unwrap();
+
+            assert_eq!(0x0001, cpu.registers.PC);
+            assert_eq!(0x02, cpu.registers.X);
+        }
+
+        #[test]
+        fn wraps_absolute_indexed_addresses() {
+            let code = vec![0xA2, 0x02, 0xBD, 0xFF, 0xFF]; // LDX #$02, LDA $FFFF,X
+            let mut cpu = Cpu::new();
+            cpu.load_with_reset_vector(&code[..], None);
+            cpu.reset();
+            cpu.memory[0x0001] = 0x99;
+
+            cpu.step_n(2).unwrap();
+
+            assert_eq!(0x99, cpu.registers.A);
+        }
+
+        #[test]
+        fn wraps_word_reads_at_end_of_memory() {
+            let mut cpu = Cpu::new();
+            cpu.memory[0xFFFF] = 0x34;
+            cpu.memory[0x0000] = 0x12;
+
+            assert_eq!(0x1234, cpu.memory.read_u16(0xFFFF));
+        }
+
+        #[test]
+        fn wraps_registers_and_memory_on_increment_and_decrement() {
+            // LDX #$00, DEX, LDY #$FF, INY, DEC $10, INC $11
+            let code = vec![0xA2, 0x00, 0xCA, 0xA0, 0xFF, 0xC8, 0xC6, 0x10, 0xE6, 0x11];
+            let mut cpu = Cpu::new();
+            cpu.load_with_reset_vector(&code[..], None);
+            cpu.reset();
+            cpu.memory[0x11] = 0xFF;
+
+            cpu.step_n(6).unwrap();
+
+            assert_eq!(0xFF, cpu.registers.X);
+            assert_eq!(0x00, cpu.registers.Y);
+            assert_eq!(0xFF, cpu.memory[0x10]);
+            assert_eq!(0x00, cpu.memory[0x11]);
+            assert!(cpu.flags.zero);
+        }
+
+        #[test]
+        fn runs_random_memory_images_without_panicking() {
+            for seed in 0..32 {
+                let mut cpu = Cpu::new();
+                cpu.power_on(PowerOnState {
+                    ram: PowerOnPattern::Random(seed),
+                    registers: PowerOnPattern::Random(!seed),
+                });
+
+                for i in 0..20_000 {
+                    if cpu.step().is_err() {
+                        // Skip over anything we can't execute and keep going
+                        cpu.registers.PC = cpu.registers.PC.wrapping_add(1);
+                    }
+                    if i % 1000 == 0 {
+                        cpu.irq();
+                        cpu.nmi();
+                    }
+                }
+            }
+        }
+
+        #[test]
+        fn indirect_x_pointer_wraps_within_zero_page() {
+            let code = vec![0xA2, 0x01, 0xA1, 0xFE]; // LDX #$01, LDA ($FE,X)
+            let mut cpu = Cpu::new();
+            cpu.load_with_reset_vector(&code[..], None);
+            cpu.reset();
+            cpu.memory[0x00FF] = 0x34;
+            cpu.memory[0x0000] = 0x12;
+            cpu.memory[0x0100] = 0x56;
+            cpu.memory[0x1234] = 0xAA;
+
+            cpu.step_n(2).unwrap();
+
+            assert_eq!(0xAA, cpu.registers.A);
+        }
+
+        #[test]
+        fn indirect_y_pointer_wraps_within_zero_page() {
+            let code = vec![0xA0, 0x01, 0xB1, 0xFF]; // LDY #$01, LDA ($FF),Y
+            let mut cpu = Cpu::new();
+            cpu.load_with_reset_vector(&code[..], None);
+            cpu.reset();
+            cpu.memory[0x00FF] = 0x34;
+            cpu.memory[0x0000] = 0x12;
+            cpu.memory[0x0100] = 0x56;
+            cpu.memory[0x1235] = 0xBB;
+
+            cpu.step_n(2).unwrap();
+
+            assert_eq!(0xBB, cpu.registers.A);
+        }
+
+        #[test]
+        fn indirect_jmp_has_page_boundary_bug_on_nmos() {
+            let code = vec![0x6C, 0xFF, 0x10]; // JMP ($10FF)
+            let mut cpu = Cpu::new();
+            cpu.load_with_reset_vector(&code[..], None);
+            cpu.reset();
+            cpu.memory[0x10FF] = 0x34;
+            cpu.memory[0x1000] = 0x12;
+            cpu.memory[0x1100] = 0x56;
+
+            let cycles = cpu.step().unwrap();
+
+            assert_eq!(0x1234, cpu.registers.PC);
+            assert_eq!(5, cycles);
+        }
+
+        #[test]
+        fn indirect_jmp_page_boundary_bug_is_fixed_on_65c02() {
+            let code = vec![0x6C, 0xFF, 0x10]; // JMP ($10FF)
+            let mut cpu = Cpu::with_variant(CpuVariant::Cmos65C02);
+            cpu.load_with_reset_vector(&code[..], None);
+            cpu.reset();
+            cpu.memory[0x10FF] = 0x34;
+            cpu.memory[0x1000] = 0x12;
+            cpu.memory[0x1100] = 0x56;
+
+            let cycles = cpu.step().unwrap();
+
+            assert_eq!(0x5634, cpu.registers.PC);
+            assert_eq!(6, cycles);
+        }
+
         #[test]
         fn errors_on_unknown_opcode() {
             let fake_code = vec![0xC3];
             let mut cpu = Cpu::new();
-            cpu.load(&fake_code[..], None);
+            cpu.load_with_reset_vector(&fake_code[..], None);
             cpu.reset();
             let step_result: CpuStepResult = cpu.step();
 
@@ -76,7 +303,7 @@ mod tests {
         fn can_get_operand_from_opcode() {
             let fake_code = vec![0xC3];
             let mut cpu = Cpu::new();
-            cpu.load(&fake_code[..], None);
+            cpu.load_with_reset_vector(&fake_code[..], None);
             cpu.reset();
             let step_result: CpuStepResult = cpu.step();
         }
@@ -85,7 +312,7 @@ mod tests {
         fn adc_can_set_decimal_flag() {
             let code = vec![0xF8];
             let mut cpu = Cpu::new();
-            cpu.load(&code[..], None);
+            cpu.load_with_reset_vector(&code[..], None);
             cpu.reset();
 
             cpu.step();
@@ -97,7 +324,7 @@ mod tests {
         fn adc_can_disable_decimal_flag() {
             let code = vec![0xD8];
             let mut cpu = Cpu::new();
-            cpu.load(&code[..], None);
+            cpu.load_with_reset_vector(&code[..], None);
             cpu.reset();
 
             cpu.step();
@@ -109,7 +336,7 @@ mod tests {
         fn adc_can_add_basic_numbers() {
             let code = vec![0xA9, 0x05, 0x69, 0x03];
             let mut cpu = Cpu::new();
-            cpu.load(&code[..], None);
+            cpu.load_with_reset_vector(&code[..], None);
             cpu.reset();
 
             cpu.step_n(2);
@@ -121,7 +348,7 @@ mod tests {
         fn adc_can_add_basic_numbers_set_carry_and_wrap_around() {
             let code = vec![0xA9, 0xFD, 0x69, 0x05];
             let mut cpu = Cpu::new();
-            cpu.load(&code[..], None);
+            cpu.load_with_reset_vector(&code[..], None);
             cpu.reset();
 
             cpu.step_n(2);
@@ -134,7 +361,7 @@ mod tests {
         fn adc_can_add_numbers_in_binary_coded_decimal() {
             let code = vec![0xF8, 0xA9, 0x05, 0x69, 0x05];
             let mut cpu = Cpu::new();
-            cpu.load(&code[..], None);
+            cpu.load_with_reset_vector(&code[..], None);
             cpu.reset();
 
             cpu.step_n(3);
@@ -147,7 +374,7 @@ mod tests {
         fn adc_can_add_numbers_in_binary_coded_decimal_and_set_carry() {
             let code = vec![0xF8, 0xA9, 0x95, 0x69, 0x10];
             let mut cpu = Cpu::new();
-            cpu.load(&code[..], None);
+            cpu.load_with_reset_vector(&code[..], None);
             cpu.reset();
 
             cpu.step_n(3);
@@ -161,7 +388,7 @@ mod tests {
         fn sta_can_store_bytes_in_memory() {
             let code = vec![0xA9, 0x20, 0x8D, 0x00, 0x20];
             let mut cpu = Cpu::new();
-            cpu.load(&code[..], None);
+            cpu.load_with_reset_vector(&code[..], None);
             cpu.reset();
 
             cpu.step_n(2);
@@ -175,7 +402,7 @@ mod tests {
             // Load 255 into A and mask it against 0x0F
             let code = vec![0xA9, 0xFF, 0x29, 0x0F];
             let mut cpu = Cpu::new();
-            cpu.load(&code[..], None);
+            cpu.load_with_reset_vector(&code[..], None);
             cpu.reset();
 
             cpu.step_n(2);
@@ -189,7 +416,7 @@ mod tests {
             // Load 2 into the A register and shift it left
             let code = vec![0xA9, 0x02, 0x0A];
             let mut cpu = Cpu::new();
-            cpu.load(&code[..], None);
+            cpu.load_with_reset_vector(&code[..], None);
             cpu.reset();
 
             cpu.step_n(2);
@@ -202,7 +429,7 @@ mod tests {
         fn asl_can_shift_bits_left() {
             let code = vec![0xA9, 0x02, 0x0A];
             let mut cpu = Cpu::new();
-            cpu.load(&code[..], None);
+            cpu.load_with_reset_vector(&code[..], None);
             cpu.reset();
 
             cpu.step_n(2);
@@ -215,7 +442,7 @@ mod tests {
         fn asl_shifts_last_bit_into_carry() {
             let code = vec![0xA9, 0x80, 0x0A];
             let mut cpu = Cpu::new();
-            cpu.load(&code[..], None);
+            cpu.load_with_reset_vector(&code[..], None);
             cpu.reset();
 
             cpu.step_n(2);
@@ -228,7 +455,7 @@ mod tests {
         fn bcc_can_jump_forward() {
             let code = vec![0xA9, 0xFE, 0x69, 0x01, 0x90, 0x03, 0xA9, 0x00];
             let mut cpu = Cpu::new();
-            cpu.load(&code[..], None);
+            cpu.load_with_reset_vector(&code[..], None);
             cpu.reset();
 
             cpu.step_n(3);
@@ -242,7 +469,7 @@ mod tests {
         fn bcc_can_jump_backward() {
             let code = vec![0xA9, 0xF0, 0x69, 0x01, 0x90, 0xFC];
             let mut cpu = Cpu::new();
-            cpu.load(&code[..], None);
+            cpu.load_with_reset_vector(&code[..], None);
             cpu.reset();
 
             cpu.step_n(50);
@@ -254,7 +481,7 @@ mod tests {
         fn bcs_can_jump_forward() {
             let code = vec![0xA9, 0xFF, 0x69, 0x01, 0xB0, 0x03, 0xA9, 0xAA];
             let mut cpu = Cpu::new();
-            cpu.load(&code[..], None);
+            cpu.load_with_reset_vector(&code[..], None);
             cpu.reset();
 
             cpu.step_n(10);
@@ -267,7 +494,7 @@ mod tests {
         fn beq_can_jump_forward() {
             let code = vec![0xA9, 0xF0, 0x69, 0x10, 0xF0, 0x03, 0xA9, 0xAA];
             let mut cpu = Cpu::new();
-            cpu.load(&code[..], None);
+            cpu.load_with_reset_vector(&code[..], None);
             cpu.reset();
 
             cpu.step_n(10);
@@ -279,7 +506,7 @@ mod tests {
         fn bit_can_set_flags_and_preserve_registers() {
             let code = vec![0xA9, 0xF0, 0x24, 0x00];
             let mut cpu = Cpu::new();
-            cpu.load(&code[..], None);
+            cpu.load_with_reset_vector(&code[..], None);
             cpu.reset();
 
             cpu.step_n(10);
@@ -292,7 +519,7 @@ mod tests {
         fn bit_can_set_overflow_flag() {
             let code = vec![0xA9, 0xF0, 0x85, 0x44, 0x24, 0x44];
             let mut cpu = Cpu::new();
-            cpu.load(&code[..], None);
+            cpu.load_with_reset_vector(&code[..], None);
             cpu.reset();
 
             cpu.step_n(10);
@@ -307,7 +534,7 @@ mod tests {
         fn bmi_can_jump_forward() {
             let code = vec![0xA9, 0x7F, 0x69, 0x01, 0x30, 0x03, 0xA9, 0x00];
             let mut cpu = Cpu::new();
-            cpu.load(&code[..], None);
+            cpu.load_with_reset_vector(&code[..], None);
             cpu.reset();
 
             cpu.step_n(10);
@@ -320,7 +547,7 @@ mod tests {
         fn bne_jumps_on_non_zero() {
             let code = vec![0xA9, 0xFE, 0x69, 0x01, 0xD0, 0x03, 0xA9, 0xAA];
             let mut cpu = Cpu::new();
-            cpu.load(&code[..], None);
+            cpu.load_with_reset_vector(&code[..], None);
             cpu.reset();
 
             cpu.step_n(10);
@@ -333,7 +560,7 @@ mod tests {
         fn bne_does_not_jump_on_zero() {
             let code = vec![0xA9, 0xFF, 0x69, 0x01, 0xD0, 0x03, 0xA9, 0xAA];
             let mut cpu = Cpu::new();
-            cpu.load(&code[..], None);
+            cpu.load_with_reset_vector(&code[..], None);
             cpu.reset();
 
             cpu.step_n(10);
@@ -345,7 +572,7 @@ mod tests {
         fn bpl_does_not_jump_on_sign_set() {
             let code = vec![0xA9, 0xFE, 0x10, 0x03, 0xA9, 0xF3];
             let mut cpu = Cpu::new();
-            cpu.load(&code[..], None);
+            cpu.load_with_reset_vector(&code[..], None);
             cpu.reset();
 
             cpu.step_n(10);
@@ -358,7 +585,7 @@ mod tests {
         fn bpl_does_jump_on_sign_not_set() {
             let code = vec![0xA9, 0x0E, 0x10, 0x03, 0xA9, 0xF3];
             let mut cpu = Cpu::new();
-            cpu.load(&code[..], None);
+            cpu.load_with_reset_vector(&code[..], None);
             cpu.reset();
 
             cpu.step_n(10);
@@ -371,7 +598,7 @@ mod tests {
         fn bvc_does_not_jump_on_overflow_set() {
             let code = vec![0xA9, 0x7F, 0x69, 0x01, 0x50, 0x03, 0xA9, 0xFF];
             let mut cpu = Cpu::new();
-            cpu.load(&code[..], None);
+            cpu.load_with_reset_vector(&code[..], None);
             cpu.reset();
 
             cpu.step_n(10);
@@ -384,7 +611,7 @@ mod tests {
         fn bvc_does_jump_on_overflow_clear() {
             let code = vec![0xA9, 0x7E, 0x69, 0x01, 0x50, 0x03, 0xA9, 0xFF];
             let mut cpu = Cpu::new();
-            cpu.load(&code[..], None);
+            cpu.load_with_reset_vector(&code[..], None);
             cpu.reset();
 
             cpu.step_n(10);
@@ -397,7 +624,7 @@ mod tests {
         fn bvs_does_not_jump_on_overflow_clear() {
             let code = vec![0xA9, 0x7E, 0x69, 0x01, 0x70, 0x03, 0xA9, 0xFF];
             let mut cpu = Cpu::new();
-            cpu.load(&code[..], None);
+            cpu.load_with_reset_vector(&code[..], None);
             cpu.reset();
 
             cpu.step_n(10);
@@ -410,7 +637,7 @@ mod tests {
         fn bvs_does_jump_on_overflow_set() {
             let code = vec![0xA9, 0x7F, 0x69, 0x01, 0x70, 0x03, 0xA9, 0xFF];
             let mut cpu = Cpu::new();
-            cpu.load(&code[..], None);
+            cpu.load_with_reset_vector(&code[..], None);
             cpu.reset();
 
             cpu.step_n(10);
@@ -423,7 +650,7 @@ mod tests {
         fn clc_clears_carry_flag() {
             let code = vec![0x18];
             let mut cpu = Cpu::new();
-            cpu.load(&code[..], None);
+            cpu.load_with_reset_vector(&code[..], None);
             cpu.reset();
             cpu.flags.carry = true;
 
@@ -436,7 +663,7 @@ mod tests {
         fn cld_clears_decimal_flag() {
             let code = vec![0xD8];
             let mut cpu = Cpu::new();
-            cpu.load(&code[..], None);
+            cpu.load_with_reset_vector(&code[..], None);
             cpu.reset();
             cpu.flags.decimal = true;
 
@@ -449,7 +676,7 @@ mod tests {
         fn cli_clears_interrupt_flag() {
             let code = vec![0x58];
             let mut cpu = Cpu::new();
-            cpu.load(&code[..], None);
+            cpu.load_with_reset_vector(&code[..], None);
             cpu.reset();
             cpu.flags.interrupt_disabled = true;
 
@@ -462,7 +689,7 @@ mod tests {
         fn clv_clears_overflow_flag() {
             let code = vec![0xB8];
             let mut cpu = Cpu::new();
-            cpu.load(&code[..], None);
+            cpu.load_with_reset_vector(&code[..], None);
             cpu.reset();
             cpu.flags.overflow = true;
 
@@ -475,7 +702,7 @@ mod tests {
         fn cmp_sets_zero_flag() {
             let code = vec![0xA9, 0x55, 0xC9, 0x55];
             let mut cpu = Cpu::new();
-            cpu.load(&code[..], None);
+            cpu.load_with_reset_vector(&code[..], None);
             cpu.reset();
             cpu.flags.zero = false;
 
@@ -488,7 +715,7 @@ mod tests {
         fn cmp_clears_carry_flag() {
             let code = vec![0xA9, 0x55, 0xC9, 0x65];
             let mut cpu = Cpu::new();
-            cpu.load(&code[..], None);
+            cpu.load_with_reset_vector(&code[..], None);
             cpu.reset();
             cpu.flags.carry = true;
 
@@ -501,7 +728,7 @@ mod tests {
         fn cmp_sets_carry_flag() {
             let code = vec![0xA9, 0x65, 0xC9, 0x55];
             let mut cpu = Cpu::new();
-            cpu.load(&code[..], None);
+            cpu.load_with_reset_vector(&code[..], None);
             cpu.reset();
             cpu.flags.carry = false;
 
@@ -514,7 +741,7 @@ mod tests {
         fn cpx_clears_carry_flag() {
             let code = vec![0xA2, 0x55, 0xE0, 0x65];
             let mut cpu = Cpu::new();
-            cpu.load(&code[..], None);
+            cpu.load_with_reset_vector(&code[..], None);
             cpu.reset();
             cpu.flags.carry = true;
 
@@ -527,7 +754,7 @@ mod tests {
         fn cpx_sets_carry_flag() {
             let code = vec![0xA2, 0x65, 0xE0, 0x55];
             let mut cpu = Cpu::new();
-            cpu.load(&code[..], None);
+            cpu.load_with_reset_vector(&code[..], None);
             cpu.reset();
             cpu.flags.carry = false;
 
@@ -540,7 +767,7 @@ mod tests {
         fn cpy_clears_carry_flag() {
             let code = vec![0xA0, 0x55, 0xC0, 0x65];
             let mut cpu = Cpu::new();
-            cpu.load(&code[..], None);
+            cpu.load_with_reset_vector(&code[..], None);
             cpu.reset();
             cpu.flags.carry = true;
 
@@ -553,7 +780,7 @@ mod tests {
         fn cpy_sets_carry_flag() {
             let code = vec![0xA0, 0x65, 0xC0, 0x55];
             let mut cpu = Cpu::new();
-            cpu.load(&code[..], None);
+            cpu.load_with_reset_vector(&code[..], None);
             cpu.reset();
             cpu.flags.carry = false;
 
@@ -566,7 +793,7 @@ mod tests {
         fn dec_decrements() {
             let code = vec![0xA9, 0x55, 0x85, 0x85, 0xC6, 0x85];
             let mut cpu = Cpu::new();
-            cpu.load(&code[..], None);
+            cpu.load_with_reset_vector(&code[..], None);
             cpu.reset();
 
             cpu.step_n(10);
@@ -578,7 +805,7 @@ mod tests {
         fn dex_decrements() {
             let code = vec![0xA2, 0x55, 0xCA];
             let mut cpu = Cpu::new();
-            cpu.load(&code[..], None);
+            cpu.load_with_reset_vector(&code[..], None);
             cpu.reset();
 
             cpu.step_n(10);
@@ -590,7 +817,7 @@ mod tests {
         fn dey_decrements() {
             let code = vec![0xA0, 0x55, 0x88];
             let mut cpu = Cpu::new();
-            cpu.load(&code[..], None);
+            cpu.load_with_reset_vector(&code[..], None);
             cpu.reset();
 
             cpu.step_n(10);
@@ -602,7 +829,7 @@ mod tests {
         fn eor_xors() {
             let code = vec![0xA9, 0x00, 0x49, 0x80];
             let mut cpu = Cpu::new();
-            cpu.load(&code[..], None);
+            cpu.load_with_reset_vector(&code[..], None);
             cpu.reset();
 
             cpu.step_n(2);
@@ -614,7 +841,7 @@ mod tests {
         fn inc_increments() {
             let code = vec![0xA9, 0x55, 0x85, 0x85, 0xE6, 0x85];
             let mut cpu = Cpu::new();
-            cpu.load(&code[..], None);
+            cpu.load_with_reset_vector(&code[..], None);
             cpu.reset();
 
             cpu.step_n(10);
@@ -626,7 +853,7 @@ mod tests {
         fn inx_increments_x() {
             let code = vec![0xA2, 0x55, 0xE8];
             let mut cpu = Cpu::new();
-            cpu.load(&code[..], None);
+            cpu.load_with_reset_vector(&code[..], None);
             cpu.reset();
 
             cpu.step_n(10);
@@ -638,7 +865,7 @@ mod tests {
         fn iny_increments_y() {
             let code = vec![0xA0, 0x55, 0xC8];
             let mut cpu = Cpu::new();
-            cpu.load(&code[..], None);
+            cpu.load_with_reset_vector(&code[..], None);
             cpu.reset();
 
             cpu.step_n(20);
@@ -650,7 +877,7 @@ mod tests {
         fn jmp_jumps() {
             let code = vec![0xA9, 0x55, 0x4C, 0x07, 0x00, 0xA9, 0xFF];
             let mut cpu = Cpu::new();
-            cpu.load(&code[..], None);
+            cpu.load_with_reset_vector(&code[..], None);
             cpu.reset();
 
             cpu.step_n(2);
@@ -658,5 +885,671 @@ mod tests {
             assert_eq!(0x55, cpu.registers.A);
             assert_eq!(0x0007, cpu.registers.PC);
         }
+
+        struct RecordingDevice {
+            accesses: Vec<BusAccess>,
+            value: u8,
+        }
+
+        impl BusDevice for RecordingDevice {
+            fn read(&mut self, addr: u16) -> u8 {
+                self.accesses.push(BusAccess::read(addr, self.value));
+                self.value
+            }
+
+            fn write(&mut self, addr: u16, byte: u8) {
+                self.accesses.push(BusAccess::write(addr, byte));
+                self.value = byte;
+            }
+        }
+
+        fn recording_device(value: u8) -> Rc<RefCell<RecordingDevice>> {
+            Rc::new(RefCell::new(RecordingDevice {
+                accesses: Vec::new(),
+                value,
+            }))
+        }
+
+        #[test]
+        fn mapped_device_receives_reads_and_writes() {
+            // LDA $D000, STA $D001
+            let code = vec![0xAD, 0x00, 0xD0, 0x8D, 0x01, 0xD0];
+            let device = recording_device(0x42);
+            let mut cpu = Cpu::new();
+            cpu.memory.map_device(0xD000, 0xD00F, device.clone());
+            cpu.load_with_reset_vector(&code[..], None);
+            cpu.reset();
+
+            cpu.step_n(2).unwrap();
+
+            assert_eq!(0x42, cpu.registers.A);
+            assert_eq!(vec![BusAccess::read(0x00, 0x42), BusAccess::write(0x01, 0x42)],
+                       device.borrow().accesses);
+            assert_eq!(0x00, cpu.memory[0xD001]);
+        }
+
+        #[test]
+        fn read_modify_write_writes_old_value_then_new_value_on_nmos() {
+            // INC $D000
+            let code = vec![0xEE, 0x00, 0xD0];
+            let device = recording_device(0x10);
+            let mut cpu = Cpu::new();
+            cpu.memory.map_device(0xD000, 0xD00F, device.clone());
+            cpu.load_with_reset_vector(&code[..], None);
+            cpu.reset();
+
+            let cycles = cpu.step().unwrap();
+
+            assert_eq!(6, cycles);
+            assert_eq!(vec![BusAccess::read(0x00, 0x10),
+                            BusAccess::write(0x00, 0x10),
+                            BusAccess::write(0x00, 0x11)],
+                       device.borrow().accesses);
+        }
+
+        #[test]
+        fn read_modify_write_reads_twice_on_65c02() {
+            // INC $D000
+            let code = vec![0xEE, 0x00, 0xD0];
+            let device = recording_device(0x10);
+            let mut cpu = Cpu::with_variant(CpuVariant::Cmos65C02);
+            cpu.memory.map_device(0xD000, 0xD00F, device.clone());
+            cpu.load_with_reset_vector(&code[..], None);
+            cpu.reset();
+
+            cpu.step().unwrap();
+
+            assert_eq!(vec![BusAccess::read(0x00, 0x10),
+                            BusAccess::read(0x00, 0x10),
+                            BusAccess::write(0x00, 0x11)],
+                       device.borrow().accesses);
+        }
+
+        #[test]
+        fn indexed_read_across_page_makes_dummy_read_from_unfixed_address() {
+            // LDA $D0F0,X
+            let code = vec![0xBD, 0xF0, 0xD0];
+            let mut cpu = Cpu::new();
+            cpu.load_with_reset_vector(&code[..], None);
+            cpu.reset();
+            cpu.registers.X = 0x20;
+            cpu.memory[0xD110] = 0x99;
+            cpu.set_bus_trace(true);
+
+            let cycles = cpu.step().unwrap();
+
+            assert_eq!(5, cycles);
+            assert_eq!(&[BusAccess::read(0xC000, 0xBD),
+                         BusAccess::read(0xC001, 0xF0),
+                         BusAccess::read(0xC002, 0xD0),
+                         BusAccess::read(0xD010, 0x00),
+                         BusAccess::read(0xD110, 0x99)],
+                       cpu.bus_trace());
+        }
+
+        #[test]
+        fn indexed_write_always_makes_dummy_read() {
+            // STA $D000,X
+            let code = vec![0x9D, 0x00, 0xD0];
+            let mut cpu = Cpu::new();
+            cpu.load_with_reset_vector(&code[..], None);
+            cpu.reset();
+            cpu.registers.A = 0x77;
+            cpu.registers.X = 0x01;
+            cpu.set_bus_trace(true);
+
+            let cycles = cpu.step().unwrap();
+
+            assert_eq!(5, cycles);
+            assert_eq!(BusAccess::read(0xD001, 0x00), cpu.bus_trace()[3]);
+            assert_eq!(BusAccess::write(0xD001, 0x77), cpu.bus_trace()[4]);
+        }
+
+        #[test]
+        fn implied_instruction_reads_next_byte() {
+            // INX, NOP
+            let code = vec![0xE8, 0xEA];
+            let mut cpu = Cpu::new();
+            cpu.load_with_reset_vector(&code[..], None);
+            cpu.reset();
+            cpu.set_bus_trace(true);
+
+            let cycles = cpu.step().unwrap();
+
+            assert_eq!(2, cycles);
+            assert_eq!(&[BusAccess::read(0xC000, 0xE8), BusAccess::read(0xC001, 0xEA)],
+                       cpu.bus_trace());
+        }
+
+        #[test]
+        fn taken_branch_across_page_costs_two_extra_cycles() {
+            // BNE +$10 from the end of a page
+            let code = vec![0xD0, 0x10];
+            let mut cpu = Cpu::new();
+            cpu.load_with_reset_vector(&code[..], 0xC0F0);
+            cpu.reset();
+            cpu.flags.zero = false;
+            cpu.set_bus_trace(true);
+
+            let cycles = cpu.step().unwrap();
+
+            assert_eq!(4, cycles);
+            assert_eq!(0xC102, cpu.registers.PC);
+            assert_eq!(BusAccess::read(0xC0F2, 0x00), cpu.bus_trace()[2]);
+            assert_eq!(BusAccess::read(0xC002, 0x00), cpu.bus_trace()[3]);
+        }
+
+        #[test]
+        fn jsr_and_rts_take_six_cycles_each() {
+            // JSR $C005, BRK, BRK, RTS
+            let code = vec![0x20, 0x04, 0xC0, 0x00, 0x60];
+            let mut cpu = Cpu::new();
+            cpu.load_with_reset_vector(&code[..], None);
+            cpu.reset();
+            cpu.set_bus_trace(true);
+
+            assert_eq!(6, cpu.step().unwrap());
+            assert_eq!(6, cpu.bus_trace().len());
+            assert_eq!(0xC004, cpu.registers.PC);
+
+            assert_eq!(6, cpu.step().unwrap());
+            assert_eq!(6, cpu.bus_trace().len());
+            assert_eq!(0xC003, cpu.registers.PC);
+        }
+
+        fn cpu_ready_to_run(code: &[u8]) -> Cpu {
+            let mut cpu = Cpu::new();
+            cpu.load_with_reset_vector(code, 0x0200);
+            cpu.reset();
+            cpu.flags.interrupt_disabled = false;
+
+            // Operands point at $0210, or through the pointer at $10 to
+            // $0300, so indexing with X and Y of zero never crosses a page
+            cpu.memory[0x10] = 0x00;
+            cpu.memory[0x11] = 0x03;
+            cpu.memory[0xFFFE] = 0x00;
+            cpu.memory[0xFFFF] = 0x80;
+
+            cpu
+        }
+
+        #[test]
+        fn every_opcode_ticks_for_its_documented_number_of_cycles() {
+            let branches = ["BCC", "BCS", "BEQ", "BMI", "BNE", "BPL", "BVC", "BVS"];
+
+            for byte in 0..0x100 {
+                let opcode = match OpCode::from_raw_byte(byte as u8) {
+                    Some(opcode) => *opcode,
+                    None => continue,
+                };
+
+                let mut cpu = cpu_ready_to_run(&[opcode.code, 0x10, 0x02]);
+
+                let mut ticks = 1;
+                while !cpu.tick().unwrap() {
+                    ticks += 1;
+                }
+
+                // A branch that is taken costs one more cycle, as it doesn't
+                // cross a page here
+                let expected = if branches.contains(&opcode.mnemonic) &&
+                                  cpu.registers.PC != 0x0202 {
+                    opcode.time + 1
+                } else {
+                    opcode.time
+                };
+
+                assert_eq!(expected, ticks, "{} ({:02X})", opcode.mnemonic, opcode.code);
+            }
+        }
+
+        #[test]
+        fn tick_only_reports_the_last_cycle_of_an_instruction() {
+            // LDA $0210, INX
+            let mut cpu = cpu_ready_to_run(&[0xAD, 0x10, 0x02, 0xE8]);
+
+            assert!(!cpu.tick().unwrap());
+            assert!(!cpu.tick().unwrap());
+            assert!(!cpu.tick().unwrap());
+            assert!(cpu.tick().unwrap());
+            assert!(!cpu.tick().unwrap());
+            assert!(cpu.tick().unwrap());
+            assert_eq!(0x01, cpu.registers.X);
+        }
+
+        #[test]
+        fn step_finishes_an_instruction_that_was_started_by_tick() {
+            // INC $0210, INX
+            let mut cpu = cpu_ready_to_run(&[0xEE, 0x10, 0x02, 0xE8]);
+
+            cpu.tick().unwrap();
+            cpu.tick().unwrap();
+            let cycles = cpu.step().unwrap();
+
+            assert_eq!(4, cycles);
+            assert_eq!(0x0203, cpu.registers.PC);
+            assert_eq!(0x01, cpu.memory[0x0210]);
+            assert_eq!(0x00, cpu.registers.X);
+        }
+
+        #[test]
+        fn unknown_opcode_is_reported_by_tick() {
+            let mut cpu = cpu_ready_to_run(&[0xFF]);
+
+            assert!(cpu.tick().is_err());
+            assert_eq!(0x0200, cpu.registers.PC);
+        }
+
+        #[test]
+        fn rdy_low_halts_on_the_next_read_cycle() {
+            // LDA $0210
+            let mut cpu = cpu_ready_to_run(&[0xAD, 0x10, 0x02]);
+            cpu.memory[0x0210] = 0x42;
+
+            cpu.tick().unwrap();
+            cpu.pins.rdy = false;
+            for _ in 0..5 {
+                assert!(!cpu.tick().unwrap());
+                assert!(cpu.halted());
+            }
+
+            assert_eq!(0x0201, cpu.registers.PC);
+            assert_eq!(0x00, cpu.registers.A);
+
+            cpu.pins.rdy = true;
+            cpu.step().unwrap();
+
+            assert!(!cpu.halted());
+            assert_eq!(0x42, cpu.registers.A);
+            assert_eq!(7 + 4 + 5, cpu.cycles);
+        }
+
+        #[test]
+        fn nmos_finishes_write_cycles_before_halting_on_rdy() {
+            // JSR $0300
+            let mut cpu = cpu_ready_to_run(&[0x20, 0x00, 0x03]);
+            let stack = 0x100 + cpu.stack.pointer;
+
+            // Opcode, low byte of the address and the internal stack read
+            cpu.tick().unwrap();
+            cpu.tick().unwrap();
+            cpu.tick().unwrap();
+
+            cpu.pins.rdy = false;
+            cpu.tick().unwrap();
+            cpu.tick().unwrap();
+            assert!(!cpu.halted());
+            cpu.tick().unwrap();
+            assert!(cpu.halted());
+
+            assert_eq!(0x02, cpu.memory[stack]);
+            assert_eq!(0x02, cpu.memory[stack - 1]);
+            assert_eq!(0x0202, cpu.registers.PC);
+        }
+
+        #[test]
+        fn cmos_halts_on_write_cycles() {
+            // JSR $0300
+            let mut cpu = Cpu::with_variant(CpuVariant::Cmos65C02);
+            cpu.load_with_reset_vector(&[0x20, 0x00, 0x03], 0x0200);
+            cpu.reset();
+            let stack = 0x100 + cpu.stack.pointer;
+
+            cpu.tick().unwrap();
+            cpu.tick().unwrap();
+            cpu.tick().unwrap();
+
+            cpu.pins.rdy = false;
+            cpu.tick().unwrap();
+            assert!(cpu.halted());
+            assert_eq!(0x00, cpu.memory[stack]);
+        }
+
+        #[test]
+        fn dma_can_use_the_bus_while_the_cpu_is_halted() {
+            // INX
+            let mut cpu = cpu_ready_to_run(&[0xE8]);
+            cpu.memory[0x0400] = 0x11;
+            cpu.memory[0x0401] = 0x22;
+
+            cpu.pins.rdy = false;
+            for offset in 0..2 {
+                cpu.tick().unwrap();
+                assert!(cpu.halted());
+
+                let byte = cpu.dma_read(0x0400 + offset);
+                cpu.tick().unwrap();
+                assert!(cpu.halted());
+
+                cpu.dma_write(0x0500 + offset, byte);
+            }
+            cpu.pins.rdy = true;
+            cpu.step().unwrap();
+
+            assert_eq!(0x11, cpu.memory[0x0500]);
+            assert_eq!(0x22, cpu.memory[0x0501]);
+            assert_eq!(0x01, cpu.registers.X);
+            assert_eq!(7 + 4 + 2, cpu.cycles);
+        }
+
+        #[test]
+        fn falling_edge_on_so_sets_overflow() {
+            // CLV, NOP, NOP
+            let mut cpu = cpu_ready_to_run(&[0xB8, 0xEA, 0xEA]);
+            cpu.flags.overflow = true;
+
+            cpu.pins.so = false;
+            cpu.step().unwrap();
+            assert!(!cpu.flags.overflow);
+
+            // Holding it low does nothing more..
+            cpu.step().unwrap();
+            assert!(!cpu.flags.overflow);
+
+            // ..only a fresh edge does
+            cpu.pins.so = true;
+            cpu.tick().unwrap();
+            cpu.pins.so = false;
+            cpu.tick().unwrap();
+            assert!(cpu.flags.overflow);
+        }
+
+        #[test]
+        fn releasing_reset_pin_runs_the_reset_sequence() {
+            // LDA #$01, JMP $0200
+            let mut cpu = cpu_ready_to_run(&[0xA9, 0x01, 0x4C, 0x00, 0x02]);
+            cpu.step_n(3).unwrap();
+
+            cpu.pins.reset = false;
+            cpu.tick().unwrap();
+            cpu.tick().unwrap();
+            assert!(cpu.halted());
+
+            cpu.pins.reset = true;
+            let mut ticks = 1;
+            while !cpu.tick().unwrap() {
+                ticks += 1;
+            }
+
+            assert_eq!(7, ticks);
+            assert_eq!(0x0200, cpu.registers.PC);
+            assert!(cpu.flags.interrupt_disabled);
+        }
+
+        #[test]
+        fn scheduled_events_run_at_their_cycle() {
+            // JMP $0200
+            let mut cpu = cpu_ready_to_run(&[0x4C, 0x00, 0x02]);
+            let fired = Rc::new(RefCell::new(Vec::new()));
+            let start = cpu.cycles;
+
+            for offset in &[25, 20] {
+                let fired = fired.clone();
+                cpu.scheduler.schedule(start + offset, move |cpu| fired.borrow_mut().push(cpu.cycles));
+            }
+            cpu.run_for(30).unwrap();
+
+            assert_eq!(vec![start + 20, start + 25], *fired.borrow());
+            assert!(cpu.scheduler.is_empty());
+        }
+
+        fn periodic_event(cpu: &mut Cpu) {
+            cpu.memory[0x20] += 1;
+
+            let next = cpu.cycles + 100;
+            cpu.scheduler.schedule(next, periodic_event);
+        }
+
+        #[test]
+        fn events_can_reschedule_themselves() {
+            // JMP $0200
+            let mut cpu = cpu_ready_to_run(&[0x4C, 0x00, 0x02]);
+            let first = cpu.cycles + 100;
+            cpu.scheduler.schedule(first, periodic_event);
+
+            cpu.run_for(1000).unwrap();
+
+            assert_eq!(9, cpu.memory[0x20]);
+            assert_eq!(1, cpu.scheduler.len());
+        }
+
+        fn immediate_event(cpu: &mut Cpu) {
+            cpu.memory[0x21] += 1;
+
+            let now = cpu.cycles;
+            cpu.scheduler.schedule(now, immediate_event);
+        }
+
+        #[test]
+        fn events_rescheduled_for_the_current_cycle_wait_for_the_next_tick() {
+            // JMP $0200
+            let mut cpu = cpu_ready_to_run(&[0x4C, 0x00, 0x02]);
+            let now = cpu.cycles;
+            cpu.scheduler.schedule(now, immediate_event);
+
+            cpu.tick().unwrap();
+            assert_eq!(1, cpu.memory[0x21]);
+            cpu.tick().unwrap();
+            assert_eq!(2, cpu.memory[0x21]);
+        }
+
+        #[test]
+        fn events_can_raise_an_nmi() {
+            // JMP $0200, and an NMI handler of INC $30, RTI
+            let mut cpu = cpu_ready_to_run(&[0x4C, 0x00, 0x02]);
+            cpu.load(&[0xE6, 0x30, 0x40], 0x0300).unwrap();
+            cpu.memory[0xFFFA] = 0x00;
+            cpu.memory[0xFFFB] = 0x03;
+
+            let pulse = cpu.cycles + 50;
+            cpu.scheduler.schedule(pulse, |cpu| cpu.pins.nmi = false);
+            cpu.scheduler.schedule(pulse + 1, |cpu| cpu.pins.nmi = true);
+            cpu.run_for(200).unwrap();
+
+            assert_eq!(0x01, cpu.memory[0x30]);
+            assert!(cpu.registers.PC >= 0x0200 && cpu.registers.PC < 0x0203);
+        }
+
+        #[test]
+        fn irq_pin_is_taken_between_instructions_when_enabled() {
+            // JMP $0200, and an IRQ handler of INC $31, JMP $8002
+            let mut cpu = cpu_ready_to_run(&[0x4C, 0x00, 0x02]);
+            cpu.load(&[0xE6, 0x31, 0x4C, 0x02, 0x80], 0x8000).unwrap();
+
+            let raise = cpu.cycles + 10;
+            cpu.scheduler.schedule(raise, |cpu| cpu.pins.irq = false);
+            cpu.run_for(100).unwrap();
+
+            // The line is still low, but the handler runs with interrupts
+            // disabled
+            assert_eq!(0x01, cpu.memory[0x31]);
+            assert!(cpu.flags.interrupt_disabled);
+        }
+
+        #[test]
+        fn irq_pin_is_ignored_while_interrupts_are_disabled() {
+            // SEI, JMP $0201
+            let mut cpu = cpu_ready_to_run(&[0x78, 0x4C, 0x01, 0x02]);
+            cpu.load(&[0xE6, 0x31, 0x4C, 0x02, 0x80], 0x8000).unwrap();
+            cpu.step().unwrap();
+
+            cpu.pins.irq = false;
+            cpu.run_for(100).unwrap();
+
+            assert_eq!(0x00, cpu.memory[0x31]);
+        }
+
+        #[test]
+        fn writes_to_rom_are_dropped() {
+            // LDA #$42, STA $C000
+            let mut cpu = cpu_ready_to_run(&[0xA9, 0x42, 0x8D, 0x00, 0xC0]);
+            cpu.memory[0xC000] = 0x11;
+            cpu.memory.map_region(0xC000, 0xFFFF, RegionKind::Rom, FaultPolicy::Ignore);
+
+            cpu.step_n(2).unwrap();
+
+            assert_eq!(0x11, cpu.memory[0xC000]);
+            assert!(cpu.memory_faults().is_empty());
+        }
+
+        #[test]
+        fn code_can_be_loaded_into_rom() {
+            let mut cpu = Cpu::new();
+            cpu.memory.map_region(0xC000, 0xFFFF, RegionKind::Rom, FaultPolicy::Raise);
+
+            cpu.load(&[0xEA, 0xEA], 0xC000).unwrap();
+
+            assert_eq!(0xEA, cpu.memory[0xC001]);
+        }
+
+        #[test]
+        fn can_raise_writes_to_rom_from_step() {
+            // LDA #$42, STA $FFFC
+            let mut cpu = cpu_ready_to_run(&[0xA9, 0x42, 0x8D, 0xFC, 0xFF]);
+            cpu.memory.map_region(0xC000, 0xFFFF, RegionKind::Rom, FaultPolicy::Raise);
+
+            cpu.step().unwrap();
+            let error = cpu.step().unwrap_err();
+
+            assert_eq!(&CpuErrorKind::MemoryFault(FaultKind::WriteToRom), error.kind());
+            assert_eq!(0xFFFC, error.addr());
+            assert_eq!("Write of 42 to ROM at FFFC (PC 0202)", error.message());
+            assert_eq!(0x02, cpu.memory[0xFFFD]);
+        }
+
+        #[test]
+        fn can_log_reads_of_unmapped_memory() {
+            // LDA $4000, LDA $4001
+            let mut cpu = cpu_ready_to_run(&[0xAD, 0x00, 0x40, 0xAD, 0x01, 0x40]);
+            cpu.memory.map_region(0x4000, 0x7FFF, RegionKind::Unmapped, FaultPolicy::Log);
+
+            cpu.step_n(2).unwrap();
+
+            let faults = cpu.memory_faults();
+            assert_eq!(2, faults.len());
+            assert_eq!(FaultKind::UnmappedRead, faults[0].kind);
+            assert_eq!(0x4000, faults[0].addr);
+            assert_eq!(0x0200, faults[0].pc);
+            assert_eq!(0x0203, faults[1].pc);
+
+            cpu.clear_memory_faults();
+            assert!(cpu.memory_faults().is_empty());
+        }
+
+        #[test]
+        fn can_raise_execution_from_io() {
+            // JMP $6000
+            let mut cpu = cpu_ready_to_run(&[0x4C, 0x00, 0x60]);
+            cpu.memory[0x6000] = 0xEA;
+            cpu.memory.map_region(0x6000, 0x600F, RegionKind::Io, FaultPolicy::Raise);
+
+            cpu.step().unwrap();
+            let error = cpu.step().unwrap_err();
+
+            assert_eq!(&CpuErrorKind::MemoryFault(FaultKind::ExecuteFromIo), error.kind());
+            assert_eq!(0x6000, error.addr());
+        }
+
+        #[test]
+        fn later_regions_take_priority() {
+            // STA $C000, STA $D000
+            let mut cpu = cpu_ready_to_run(&[0x8D, 0x00, 0xC0, 0x8D, 0x00, 0xD0]);
+            cpu.registers.A = 0x42;
+            cpu.memory.map_region(0xC000, 0xFFFF, RegionKind::Rom, FaultPolicy::Log);
+            cpu.memory.map_region(0xD000, 0xDFFF, RegionKind::Ram, FaultPolicy::Log);
+
+            cpu.step_n(2).unwrap();
+
+            assert_eq!(RegionKind::Ram, cpu.memory.region_kind(0xD000));
+            assert_eq!(1, cpu.memory_faults().len());
+            assert_eq!(0x42, cpu.memory[0xD000]);
+        }
+
+        #[test]
+        fn faults_raised_by_interrupts_are_returned_by_the_next_tick() {
+            // NOP
+            let mut cpu = cpu_ready_to_run(&[0xEA]);
+            cpu.memory.map_region(0x0100, 0x01FF, RegionKind::Rom, FaultPolicy::Raise);
+
+            cpu.irq();
+            let error = cpu.step().unwrap_err();
+
+            assert_eq!(&CpuErrorKind::MemoryFault(FaultKind::WriteToRom), error.kind());
+            assert!(error.message().ends_with("(PC 0200)"));
+            assert_eq!(0x8000, cpu.registers.PC);
+            assert!(cpu.step().is_ok());
+        }
+
+        #[test]
+        fn dummy_reads_of_unmapped_memory_are_not_faults() {
+            // LDX #$01, LDA $40FF,X
+            let mut cpu = cpu_ready_to_run(&[0xA2, 0x01, 0xBD, 0xFF, 0x40]);
+            cpu.memory.map_region(0x4000, 0x40FF, RegionKind::Unmapped, FaultPolicy::Log);
+            cpu.memory.map_region(0x0100, 0x01FF, RegionKind::Unmapped, FaultPolicy::Log);
+
+            cpu.step_n(2).unwrap();
+            cpu.reset();
+
+            assert!(cpu.memory_faults().is_empty());
+        }
+
+        #[test]
+        fn read_modify_writes_to_rom_fault_once() {
+            // INC $C000
+            let mut cpu = cpu_ready_to_run(&[0xEE, 0x00, 0xC0]);
+            cpu.memory.map_region(0xC000, 0xFFFF, RegionKind::Rom, FaultPolicy::Log);
+
+            cpu.step().unwrap();
+
+            assert_eq!(1, cpu.memory_faults().len());
+            assert_eq!(0x01, cpu.memory_faults()[0].value);
+        }
+
+        #[test]
+        fn reads_of_uninitialized_memory_are_reported() {
+            // LDA #$01, STA $10, LDA $10, LDA $11
+            let code = [0xA9, 0x01, 0x85, 0x10, 0xA5, 0x10, 0xA5, 0x11];
+            let mut cpu = Cpu::new();
+            cpu.memory.set_shadow_memory(true);
+            cpu.load_with_reset_vector(&code, 0x0200).unwrap();
+            cpu.reset();
+
+            cpu.step_n(4).unwrap();
+
+            assert_eq!(&[UninitializedRead {
+                             pc: 0x0206,
+                             addr: 0x0011,
+                             instruction: "LDA $11".into(),
+                         }],
+                       cpu.uninitialized_reads());
+        }
+
+        #[test]
+        fn uninitialized_pointers_and_stack_are_reported_once() {
+            // LDA ($20),Y, PLA, JMP $0200
+            let code = [0xB1, 0x20, 0x68, 0x4C, 0x00, 0x02];
+            let mut cpu = Cpu::new();
+            cpu.memory.set_shadow_memory(true);
+            cpu.load_with_reset_vector(&code, 0x0200).unwrap();
+            cpu.reset();
+
+            cpu.step_n(6).unwrap();
+
+            let reads = cpu.take_uninitialized_reads();
+            let addrs: Vec<u16> = reads.iter().map(|read| read.addr).collect();
+            // The second time around, only the stack has moved
+            assert_eq!(vec![0x0020, 0x0021, 0x0000, 0x01FD, 0x01FE], addrs);
+            assert_eq!("PLA", reads[3].instruction);
+            assert!(cpu.uninitialized_reads().is_empty());
+        }
+
+        #[test]
+        fn reads_are_not_checked_without_shadow_memory() {
+            // LDA $10
+            let mut cpu = cpu_ready_to_run(&[0xA5, 0x10]);
+            cpu.step().unwrap();
+
+            assert!(cpu.uninitialized_reads().is_empty());
+        }
     }
-}
\ No newline at end of file
+}
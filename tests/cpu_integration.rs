@@ -12,7 +12,7 @@ fn INTEGRATION_CPU_can_add_basic_numbers_in_accumulator() {
     let mut assembler = rs6502::Assembler::new();
 
     let segments = assembler.assemble_string(asm, None).unwrap();
-    cpu.load(&segments[0].code[..], None);
+    cpu.load_with_reset_vector(&segments[0].code[..], None);
     cpu.reset();
 
     cpu.step_n(2);
@@ -32,7 +32,7 @@ fn INTEGRATION_CPU_can_add_binary_coded_decimal_numbers_in_accumulator() {
     let mut assembler = rs6502::Assembler::new();
 
     let segments = assembler.assemble_string(asm, None).unwrap();
-    cpu.load(&segments[0].code[..], None);
+    cpu.load_with_reset_vector(&segments[0].code[..], None);
     cpu.reset();
 
     cpu.step_n(3);
@@ -51,7 +51,7 @@ fn INTEGRATION_CPU_can_add_mixed_mode_numbers_in_accumulator() {
     let mut assembler = rs6502::Assembler::new();
 
     let segments = assembler.assemble_string(asm, None).unwrap();
-    cpu.load(&segments[0].code[..], None);
+    cpu.load_with_reset_vector(&segments[0].code[..], None);
     cpu.reset();
 
     cpu.step_n(2);
@@ -72,7 +72,7 @@ fn INTEGRATION_CPU_can_store_bytes_in_memory() {
     let mut assembler = rs6502::Assembler::new();
 
     let segments = assembler.assemble_string(asm, None).unwrap();
-    cpu.load(&segments[0].code[..], None);
+    cpu.load_with_reset_vector(&segments[0].code[..], None);
     cpu.reset();
 
     cpu.step_n(4);
@@ -95,7 +95,7 @@ fn INTEGRATION_CPU_can_overwrite_own_memory() {
     let mut assembler = rs6502::Assembler::new();
 
     let segments = assembler.assemble_string(asm, None).unwrap();
-    cpu.load(&segments[0].code[..], None);
+    cpu.load_with_reset_vector(&segments[0].code[..], None);
     cpu.reset();
 
     cpu.step_n(4);
@@ -116,7 +116,7 @@ fn INTEGRATION_CPU_can_load_byte_into_memory_and_logical_AND_it_with_A_register(
     let mut assembler = rs6502::Assembler::new();
 
     let segments = assembler.assemble_string(asm, None).unwrap();
-    cpu.load(&segments[0].code[..], None);
+    cpu.load_with_reset_vector(&segments[0].code[..], None);
     cpu.reset();
 
     cpu.step_n(4);
@@ -140,7 +140,7 @@ fn INTEGRATION_CPU_can_load_byte_into_memory_and_logical_AND_it_with_A_register_
     let mut assembler = rs6502::Assembler::new();
 
     let segments = assembler.assemble_string(asm, None).unwrap();
-    cpu.load(&segments[0].code[..], None);
+    cpu.load_with_reset_vector(&segments[0].code[..], None);
     cpu.reset();
 
     cpu.step_n(4);
@@ -162,7 +162,7 @@ fn INTEGRATION_CPU_does_not_branch_on_clear_carry_flag() {
     let mut assembler = rs6502::Assembler::new();
 
     let segments = assembler.assemble_string(asm, None).unwrap();
-    cpu.load(&segments[0].code[..], None);
+    cpu.load_with_reset_vector(&segments[0].code[..], None);
     cpu.reset();
 
     cpu.step_n(3);
@@ -184,7 +184,7 @@ fn INTEGRATION_CPU_can_branch_on_carry_flag() {
     let mut assembler = rs6502::Assembler::new();
 
     let segments = assembler.assemble_string(asm, None).unwrap();
-    cpu.load(&segments[0].code[..], None);
+    cpu.load_with_reset_vector(&segments[0].code[..], None);
     cpu.reset();
 
     cpu.step_n(4);
@@ -211,7 +211,7 @@ fn INTEGRATION_CPU_can_branch_on_carry_flag_to_correct_offset() {
     let mut assembler = rs6502::Assembler::new();
 
     let segments = assembler.assemble_string(asm, None).unwrap();
-    cpu.load(&segments[0].code[..], None);
+    cpu.load_with_reset_vector(&segments[0].code[..], None);
     cpu.reset();
 
     cpu.step_n(5);
@@ -232,7 +232,7 @@ fn INTEGRATION_CPU_can_loop_on_bcc() {
     let mut assembler = rs6502::Assembler::new();
 
     let segments = assembler.assemble_string(asm, None).unwrap();
-    cpu.load(&segments[0].code[..], None);
+    cpu.load_with_reset_vector(&segments[0].code[..], None);
     cpu.reset();
 
     cpu.step_n(30);
@@ -254,7 +254,7 @@ fn INTEGRATION_CPU_can_branch_on_bcs() {
     let mut assembler = rs6502::Assembler::new();
 
     let segments = assembler.assemble_string(asm, None).unwrap();
-    cpu.load(&segments[0].code[..], None);
+    cpu.load_with_reset_vector(&segments[0].code[..], None);
     cpu.reset();
 
     cpu.step_n(30);
@@ -276,7 +276,7 @@ fn INTEGRATION_CPU_can_branch_on_beq() {
     let mut assembler = rs6502::Assembler::new();
 
     let segments = assembler.assemble_string(asm, None).unwrap();
-    cpu.load(&segments[0].code[..], None);
+    cpu.load_with_reset_vector(&segments[0].code[..], None);
     cpu.reset();
 
     cpu.step_n(30);
@@ -298,7 +298,7 @@ fn INTEGRATION_CPU_does_not_branch_on_beq() {
     let mut assembler = rs6502::Assembler::new();
 
     let segments = assembler.assemble_string(asm, None).unwrap();
-    cpu.load(&segments[0].code[..], None);
+    cpu.load_with_reset_vector(&segments[0].code[..], None);
     cpu.reset();
 
     cpu.step_n(30);
@@ -322,7 +322,7 @@ fn INTEGRATION_CPU_preserves_flags_on_bit() {
     let mut assembler = rs6502::Assembler::new();
 
     let segments = assembler.assemble_string(asm, None).unwrap();
-    cpu.load(&segments[0].code[..], None);
+    cpu.load_with_reset_vector(&segments[0].code[..], None);
     cpu.reset();
 
     cpu.step_n(30);
@@ -344,7 +344,7 @@ fn INTEGRATION_CPU_bmi_branches_on_sign_bit_set() {
     let mut assembler = rs6502::Assembler::new();
 
     let segments = assembler.assemble_string(asm, None).unwrap();
-    cpu.load(&segments[0].code[..], None);
+    cpu.load_with_reset_vector(&segments[0].code[..], None);
     cpu.reset();
 
     cpu.step_n(30);
@@ -366,7 +366,7 @@ fn INTEGRATION_CPU_bne_branches_on_zero_clear() {
     let mut assembler = rs6502::Assembler::new();
 
     let segments = assembler.assemble_string(asm, None).unwrap();
-    cpu.load(&segments[0].code[..], None);
+    cpu.load_with_reset_vector(&segments[0].code[..], None);
     cpu.reset();
 
     cpu.step_n(50);
@@ -388,7 +388,7 @@ fn INTEGRATION_CPU_bpl_branches_on_sign_clear() {
     let mut assembler = rs6502::Assembler::new();
 
     let segments = assembler.assemble_string(asm, None).unwrap();
-    cpu.load(&segments[0].code[..], None);
+    cpu.load_with_reset_vector(&segments[0].code[..], None);
     cpu.reset();
 
     cpu.step_n(50);
@@ -410,7 +410,7 @@ fn INTEGRATION_CPU_bpl_does_not_branch_on_sign_set() {
     let mut assembler = rs6502::Assembler::new();
 
     let segments = assembler.assemble_string(asm, None).unwrap();
-    cpu.load(&segments[0].code[..], None);
+    cpu.load_with_reset_vector(&segments[0].code[..], None);
     cpu.reset();
 
     cpu.step_n(50);
@@ -436,7 +436,7 @@ fn INTEGRATION_CPU_cmp_does_branch_on_accumulator_less_than_memory_bcc() {
     let mut assembler = rs6502::Assembler::new();
 
     let segments = assembler.assemble_string(asm, None).unwrap();
-    cpu.load(&segments[0].code[..], None);
+    cpu.load_with_reset_vector(&segments[0].code[..], None);
     cpu.reset();
 
     cpu.step_n(50);
@@ -461,7 +461,7 @@ fn INTEGRATION_CPU_cmp_does_branch_on_accumulator_greater_than_memory_bcs() {
     let mut assembler = rs6502::Assembler::new();
 
     let segments = assembler.assemble_string(asm, None).unwrap();
-    cpu.load(&segments[0].code[..], None);
+    cpu.load_with_reset_vector(&segments[0].code[..], None);
     cpu.reset();
 
     cpu.step_n(50);
@@ -486,7 +486,7 @@ fn INTEGRATION_CPU_cmp_does_branch_on_accumulator_less_than_equal_to_bcc() {
     let mut assembler = rs6502::Assembler::new();
 
     let segments = assembler.assemble_string(asm, None).unwrap();
-    cpu.load(&segments[0].code[..], None);
+    cpu.load_with_reset_vector(&segments[0].code[..], None);
     cpu.reset();
 
     cpu.step_n(50);
@@ -506,7 +506,7 @@ fn INTEGRATION_CPU_dec_decrements() {
     let mut assembler = rs6502::Assembler::new();
 
     let segments = assembler.assemble_string(asm, None).unwrap();
-    cpu.load(&segments[0].code[..], None);
+    cpu.load_with_reset_vector(&segments[0].code[..], None);
     cpu.reset();
 
     cpu.step_n(3);
@@ -530,7 +530,7 @@ fn INTEGRATION_CPU_dex_decrements() {
     let mut assembler = rs6502::Assembler::new();
 
     let segments = assembler.assemble_string(asm, None).unwrap();
-    cpu.load(&segments[0].code[..], None);
+    cpu.load_with_reset_vector(&segments[0].code[..], None);
     cpu.reset();
 
     cpu.step_n(20);
@@ -557,7 +557,7 @@ fn INTEGRATION_CPU_jsr_rts_combination_works() {
     let mut assembler = rs6502::Assembler::new();
 
     let segments = assembler.assemble_string(asm, 0xC000).unwrap();
-    cpu.load(&segments[0].code[..], None);
+    cpu.load_with_reset_vector(&segments[0].code[..], None);
     cpu.reset();
 
     cpu.step_n(20);
@@ -584,7 +584,7 @@ fn INTEGRATION_CPU_jsr_rts_combination_works_when_code_segment_loaded_at_weird_a
     let mut assembler = rs6502::Assembler::new();
 
     let segments = assembler.assemble_string(asm, 0xABCD).unwrap();
-    cpu.load(&segments[0].code[..], 0xABCD);  // Load it at a weird address
+    cpu.load_with_reset_vector(&segments[0].code[..], 0xABCD);  // Load it at a weird address
     cpu.reset();
 
     cpu.step_n(20);
@@ -609,7 +609,7 @@ fn INTEGRATION_CPU_lsr_can_halve_a_number() {
     let mut assembler = rs6502::Assembler::new();
 
     let segments = assembler.assemble_string(asm, None).unwrap();
-    cpu.load(&segments[0].code[..], None);
+    cpu.load_with_reset_vector(&segments[0].code[..], None);
     cpu.reset();
 
     cpu.step_n(20);
@@ -629,7 +629,7 @@ fn INTEGRATION_CPU_ora_ors_against_accumulator() {
     let mut assembler = rs6502::Assembler::new();
 
     let segments = assembler.assemble_string(asm, None).unwrap();
-    cpu.load(&segments[0].code[..], None);
+    cpu.load_with_reset_vector(&segments[0].code[..], None);
     cpu.reset();
 
     cpu.step_n(10);
@@ -650,7 +650,7 @@ fn INTEGRATION_CPU_pha_pla() {
     let mut assembler = rs6502::Assembler::new();
 
     let segments = assembler.assemble_string(asm, None).unwrap();
-    cpu.load(&segments[0].code[..], None);
+    cpu.load_with_reset_vector(&segments[0].code[..], None);
     cpu.reset();
 
     cpu.step_n(3);
@@ -677,7 +677,7 @@ fn INTEGRATION_CPU_rol() {
     let mut assembler = rs6502::Assembler::new();
 
     let segments = assembler.assemble_string(asm, None).unwrap();
-    cpu.load(&segments[0].code[..], None);
+    cpu.load_with_reset_vector(&segments[0].code[..], None);
     cpu.reset();
 
     cpu.step_n(3);
@@ -701,7 +701,7 @@ fn INTEGRATION_CPU_ror() {
     let mut assembler = rs6502::Assembler::new();
 
     let segments = assembler.assemble_string(asm, None).unwrap();
-    cpu.load(&segments[0].code[..], None);
+    cpu.load_with_reset_vector(&segments[0].code[..], None);
     cpu.reset();
 
     cpu.step_n(3);
@@ -724,7 +724,7 @@ fn INTEGRATION_CPU_brk_rti() {
     let mut assembler = rs6502::Assembler::new();
 
     let segments = assembler.assemble_string(asm, None).unwrap();
-    cpu.load(&segments[0].code[..], None);
+    cpu.load_with_reset_vector(&segments[0].code[..], None);
     cpu.load(&segments[1].code[..], segments[1].address);
     cpu.reset();
     cpu.flags.interrupt_disabled = false;
@@ -755,7 +755,7 @@ fn INTEGRATION_CPU_sbc() {
     let mut assembler = rs6502::Assembler::new();
 
     let segments = assembler.assemble_string(asm, None).unwrap();
-    cpu.load(&segments[0].code[..], None);
+    cpu.load_with_reset_vector(&segments[0].code[..], None);
     cpu.reset();
 
     cpu.step_n(2);
@@ -775,7 +775,7 @@ fn INTEGRATION_CPU_sbc_with_decimal_mode() {
     let mut assembler = rs6502::Assembler::new();
 
     let segments = assembler.assemble_string(asm, None).unwrap();
-    cpu.load(&segments[0].code[..], None);
+    cpu.load_with_reset_vector(&segments[0].code[..], None);
     cpu.reset();
 
     cpu.step_n(3);
@@ -837,6 +837,7 @@ fn INTEGRATION_CPU_can_force_interrupt_code() {
     for segment in segments {
         cpu.load(&segment.code[..], segment.address);
     }
+    cpu.set_reset_vector(0xC000);
     cpu.reset();
     cpu.flags.interrupt_disabled = false;
 
@@ -883,6 +884,7 @@ fn INTEGRATION_CPU_cant_interrupt_when_disabled() {
     for segment in segments {
         cpu.load(&segment.code[..], segment.address);
     }
+    cpu.set_reset_vector(0xC000);
     cpu.reset();
     cpu.flags.interrupt_disabled = false;
 
@@ -928,6 +930,7 @@ fn INTEGRATION_CPU_can_interrupt_when_not_disabled() {
     for segment in segments {
         cpu.load(&segment.code[..], segment.address);
     }
+    cpu.set_reset_vector(0xC000);
     cpu.reset();
     cpu.flags.interrupt_disabled = false;
 
@@ -941,4 +944,244 @@ fn INTEGRATION_CPU_can_interrupt_when_not_disabled() {
     cpu.step_n(50);
 
     assert_eq!(0xA0, cpu.registers.A);
-}
\ No newline at end of file
+}
+#[test]
+fn INTEGRATION_CPU_ticking_matches_stepping() {
+    let asm = "
+        LDX #$00
+    LOOP:
+        LDA $C0F0,X     ; Crosses a page once X reaches $10
+        STA $0300,X
+        INC $10
+        ASL $10
+        PHA
+        PLA
+        JSR SUB
+        INX
+        CPX #$20
+        BNE LOOP
+        JMP LOOP
+    SUB:
+        LDY $10
+        RTS
+    ";
+
+    let mut assembler = rs6502::Assembler::new();
+    let segments = assembler.assemble_string(asm, None).unwrap();
+
+    let mut stepped = rs6502::Cpu::new();
+    let mut ticked = rs6502::Cpu::new();
+    for cpu in vec![&mut stepped, &mut ticked] {
+        cpu.load_with_reset_vector(&segments[0].code[..], None);
+        cpu.reset();
+        cpu.set_bus_trace(true);
+    }
+
+    for _ in 0..500 {
+        let cycles = stepped.step().unwrap();
+
+        let mut ticks = 1;
+        while !ticked.tick().unwrap() {
+            ticks += 1;
+        }
+
+        assert_eq!(cycles, ticks);
+        assert_eq!(stepped.bus_trace(), ticked.bus_trace());
+        assert_eq!(stepped.registers.PC, ticked.registers.PC);
+        assert_eq!(stepped.registers.A, ticked.registers.A);
+        assert_eq!(stepped.registers.X, ticked.registers.X);
+        assert_eq!(stepped.registers.Y, ticked.registers.Y);
+        assert_eq!(stepped.flags.to_u8(), ticked.flags.to_u8());
+        assert_eq!(stepped.cycles, ticked.cycles);
+    }
+
+    assert_eq!(&stepped.memory[..], &ticked.memory[..]);
+}
+
+#[test]
+fn INTEGRATION_CPU_code_analysis_finds_self_modifying_code_and_executed_data() {
+    let asm = "
+        LDX #$00
+    LOOP:
+        INX
+        LDA #$E8        ; Writes INX back over itself
+        STA $C002
+        CPX #$02
+        BNE LOOP
+        LDA #$60        ; Builds an RTS in data memory, and calls it
+        STA $0300
+        JSR $0300
+    DONE:
+        JMP DONE
+    ";
+
+    let mut cpu = rs6502::Cpu::new();
+    let mut assembler = rs6502::Assembler::new();
+
+    let segments = assembler.assemble_string(asm, 0xC000).unwrap();
+    cpu.load_with_reset_vector(&segments[0].code[..], 0xC000).unwrap();
+    cpu.reset();
+    cpu.start_code_analysis(&segments);
+
+    cpu.step_n(16).unwrap();
+    let report = cpu.stop_code_analysis().unwrap();
+
+    assert_eq!(vec![0x0300..=0x0300, 0xC000..=0xC016], report.executed);
+    assert_eq!(vec![0x01FB..=0x01FC, 0x0300..=0x0300, 0xC002..=0xC002],
+               report.written);
+    assert_eq!(vec![rs6502::CodeFinding {
+                        kind: rs6502::CodeFindingKind::SelfModifyingWrite,
+                        pc: 0xC005,
+                        addr: 0xC002,
+                    },
+                    // The patched INX then runs the second time around
+                    rs6502::CodeFinding {
+                        kind: rs6502::CodeFindingKind::ExecutedData,
+                        pc: 0xC002,
+                        addr: 0xC002,
+                    },
+                    rs6502::CodeFinding {
+                        kind: rs6502::CodeFindingKind::ExecutedData,
+                        pc: 0x0300,
+                        addr: 0x0300,
+                    },
+                    rs6502::CodeFinding {
+                        kind: rs6502::CodeFindingKind::ExecutedOutsideSegments,
+                        pc: 0x0300,
+                        addr: 0x0300,
+                    }],
+               report.findings);
+    assert!(cpu.code_report().is_none());
+}
+
+#[test]
+fn INTEGRATION_CPU_code_analysis_catches_runaway_jumps() {
+    let asm = "
+        JMP $8000
+    ";
+
+    let mut cpu = rs6502::Cpu::new();
+    let mut assembler = rs6502::Assembler::new();
+
+    let segments = assembler.assemble_string(asm, 0xC000).unwrap();
+    cpu.load_with_reset_vector(&segments[0].code[..], 0xC000).unwrap();
+    cpu.memory[0x8000] = 0xEA;
+    cpu.reset();
+    cpu.start_code_analysis(&segments);
+
+    cpu.step_n(2).unwrap();
+
+    let findings = cpu.code_report().unwrap().findings;
+    assert_eq!(1, findings.len());
+    assert_eq!(rs6502::CodeFindingKind::ExecutedOutsideSegments, findings[0].kind);
+    assert_eq!(0x8000, findings[0].addr);
+}
+
+#[test]
+fn INTEGRATION_CPU_backtrace_names_each_frame_after_its_label() {
+    let asm = "
+    MAIN:
+        JSR OUTER
+    DONE:
+        JMP DONE
+    OUTER:
+        NOP
+        JSR INNER
+        RTS
+    INNER:
+        NOP
+        NOP
+        RTS
+    ";
+
+    let mut cpu = rs6502::Cpu::new();
+    let mut assembler = rs6502::Assembler::new();
+
+    let segments = assembler.assemble_string(asm, 0xC000).unwrap();
+    cpu.load_with_reset_vector(&segments[0].code[..], 0xC000).unwrap();
+    cpu.reset();
+
+    cpu.step_n(4).unwrap();
+
+    let symbols = assembler.symbols();
+    let backtrace: Vec<String> = cpu.backtrace(Some(&symbols))
+        .iter()
+        .map(|frame| frame.to_string())
+        .collect();
+    assert_eq!(vec!["C00C INNER+1", "C007 OUTER+1", "C000 MAIN"], backtrace);
+    assert_eq!(2, cpu.call_stack().len());
+    assert_eq!(0xC00A, cpu.call_stack()[1].return_addr);
+
+    cpu.step_n(3).unwrap();
+
+    assert_eq!(0xC003, cpu.registers.PC);
+    assert!(cpu.call_stack().is_empty());
+    assert!(cpu.stack_anomalies().is_empty());
+}
+
+#[test]
+fn INTEGRATION_CPU_returning_to_a_pushed_address_is_an_anomaly() {
+    let asm = "
+        LDA #$C0
+        PHA
+        LDA #$06        ; TARGET - 1
+        PHA
+        RTS
+    TARGET:
+        JMP TARGET
+    ";
+
+    let mut cpu = rs6502::Cpu::new();
+    let mut assembler = rs6502::Assembler::new();
+
+    let segments = assembler.assemble_string(asm, 0xC000).unwrap();
+    cpu.load_with_reset_vector(&segments[0].code[..], 0xC000).unwrap();
+    cpu.reset();
+
+    cpu.step_n(5).unwrap();
+
+    assert_eq!(0xC007, cpu.registers.PC);
+    assert_eq!(&[rs6502::StackAnomaly {
+                     kind: rs6502::StackAnomalyKind::ReturnWithoutCall,
+                     pc: 0xC006,
+                     frame: None,
+                 }],
+               cpu.stack_anomalies());
+}
+
+#[test]
+fn INTEGRATION_CPU_pulling_a_return_address_discards_the_frame() {
+    let asm = "
+        JSR SUB
+    DONE:
+        JMP DONE
+    SUB:
+        PLA
+        PLA
+        JMP DONE
+    ";
+
+    let mut cpu = rs6502::Cpu::new();
+    let mut assembler = rs6502::Assembler::new();
+
+    let segments = assembler.assemble_string(asm, 0xC000).unwrap();
+    cpu.load_with_reset_vector(&segments[0].code[..], 0xC000).unwrap();
+    cpu.reset();
+    let stack_pointer = cpu.stack.pointer as u8;
+
+    cpu.step_n(4).unwrap();
+
+    assert!(cpu.call_stack().is_empty());
+    assert_eq!(&[rs6502::StackAnomaly {
+                     kind: rs6502::StackAnomalyKind::DiscardedFrame,
+                     pc: 0xC006,
+                     frame: Some(rs6502::CallFrame {
+                         kind: rs6502::FrameKind::Subroutine,
+                         call_site: 0xC000,
+                         target: 0xC006,
+                         return_addr: 0xC003,
+                         stack_pointer: stack_pointer.wrapping_sub(2),
+                     }),
+                 }],
+               cpu.stack_anomalies());
+}
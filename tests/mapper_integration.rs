@@ -0,0 +1,166 @@
+extern crate rs6502;
+
+use rs6502::{Assembler, BusDevice, Banked16k, Cpu, Disassembler, Mmc1, Slots8k};
+
+const BANK_16K: usize = 0x4000;
+
+/// Builds a ROM of `banks` 16KB banks where the first byte of each bank
+/// holds its number, with `asm` assembled into the last bank at $C000
+fn rom_with_code(asm: &str, banks: usize) -> Vec<u8> {
+    let mut rom = vec![0; banks * BANK_16K];
+    for bank in 0..banks {
+        rom[bank * BANK_16K] = bank as u8;
+    }
+
+    let mut assembler = Assembler::new();
+    let segments = assembler.assemble_string(asm, 0xC000).unwrap();
+    let last_bank = (banks - 1) * BANK_16K;
+    for segment in segments {
+        let start = last_bank + segment.address as usize - 0xC000;
+        rom[start..start + segment.code.len()].copy_from_slice(&segment.code);
+    }
+
+    // Reset vector at $FFFC
+    rom[banks * BANK_16K - 4] = 0x00;
+    rom[banks * BANK_16K - 3] = 0xC0;
+
+    rom
+}
+
+#[test]
+#[allow(non_snake_case)]
+fn INTEGRATION_MAPPER_uxrom_program_can_switch_banks() {
+    let asm = "
+        LDA #$05
+        STA $8000
+        LDA $8000
+        STA $00
+        LDA #$02
+        STA $8000
+        LDA $8000
+        STA $01
+    ";
+
+    let mut cpu = Cpu::new();
+    cpu.memory.map_device(0x8000, 0xFFFF, Banked16k::uxrom(rom_with_code(asm, 8)).unwrap());
+    cpu.reset();
+    cpu.step_n(8).unwrap();
+
+    assert_eq!(0x05, cpu.memory[0x00]);
+    assert_eq!(0x02, cpu.memory[0x01]);
+    assert_eq!(Some(2), cpu.memory.bank_at(0x8000));
+    assert_eq!(Some(7), cpu.memory.bank_at(0xC000));
+    assert_eq!(None, cpu.memory.bank_at(0x0000));
+}
+
+#[test]
+#[allow(non_snake_case)]
+fn INTEGRATION_MAPPER_interrupt_vectors_can_be_in_banked_rom() {
+    let asm = "
+            CLI
+        LOOP:
+            JMP LOOP
+        .ORG $C010
+            INC $10
+            RTI
+        .ORG $C020
+            INC $11
+            RTI
+    ";
+    let mut rom = rom_with_code(asm, 8);
+    let end = rom.len();
+    // NMI vector at $FFFA, IRQ vector at $FFFE
+    rom[end - 6..end - 4].copy_from_slice(&[0x20, 0xC0]);
+    rom[end - 2..].copy_from_slice(&[0x10, 0xC0]);
+
+    let mut cpu = Cpu::new();
+    cpu.memory.map_device(0x8000, 0xFFFF, Banked16k::uxrom(rom).unwrap());
+    cpu.reset();
+    cpu.step().unwrap();
+
+    cpu.pins.irq = false;
+    cpu.step_n(2).unwrap();
+    cpu.pins.irq = true;
+    assert_eq!(0x01, cpu.memory[0x10]);
+
+    cpu.nmi();
+    cpu.step().unwrap();
+    assert_eq!(0x01, cpu.memory[0x11]);
+}
+
+#[test]
+#[allow(non_snake_case)]
+fn INTEGRATION_MAPPER_mmc1_program_can_switch_banks() {
+    let asm = "
+        LDA #$80    ; Reset the shift register
+        STA $8000
+        LDA #$03    ; Bank 3, one bit at a time
+        STA $E000
+        LSR
+        STA $E000
+        LSR
+        STA $E000
+        LSR
+        STA $E000
+        LSR
+        STA $E000
+        LDA $8000
+        STA $00
+    ";
+
+    let mut cpu = Cpu::new();
+    cpu.memory.map_device(0x8000, 0xFFFF, Mmc1::new(rom_with_code(asm, 8)).unwrap());
+    cpu.reset();
+    cpu.step_n(15).unwrap();
+
+    assert_eq!(0x03, cpu.memory[0x00]);
+    assert_eq!(Some(3), cpu.memory.bank_at(0x8000));
+}
+
+#[test]
+#[allow(non_snake_case)]
+fn INTEGRATION_MAPPER_mmc1_ignores_read_modify_write_double_writes() {
+    let asm = "
+        LDA #$80    ; Reset the shift register
+        STA $8000
+        INC $E000   ; Writes $01 then $02, but only the first is seen
+        LDA #$01
+        STA $E000
+        STA $E000
+        STA $E000
+        STA $E000
+    ";
+
+    let mut rom = rom_with_code(asm, 8);
+    rom[7 * BANK_16K + 0x2000] = 0x01;
+
+    let mut cpu = Cpu::new();
+    cpu.memory.map_device(0x8000, 0xFFFF, Mmc1::new(rom).unwrap());
+    cpu.reset();
+    cpu.step_n(8).unwrap();
+
+    // Five 1 bits select bank 15, which wraps around to 7. Had the second
+    // write of INC been seen, bank 13 (5) would have been selected.
+    assert_eq!(Some(7), cpu.memory.bank_at(0x8000));
+}
+
+#[test]
+#[allow(non_snake_case)]
+fn INTEGRATION_MAPPER_can_disassemble_banked_code() {
+    let mut rom = vec![0xEA; 4 * 0x2000];
+    rom[2 * 0x2000] = 0xA9;
+    rom[2 * 0x2000 + 1] = 0x42;
+
+    let mut mapper = Slots8k::new(rom, 2, 0x3FFE).unwrap();
+    mapper.write(0x3FFF, 2);
+
+    let mut cpu = Cpu::new();
+    cpu.memory.map_device(0x8000, 0xBFFF, mapper);
+
+    let code: Vec<u8> = (0xA000..0xA003).map(|addr| cpu.memory.read_byte(addr)).collect();
+    let bank = cpu.memory.bank_at(0xA000).unwrap();
+    let dasm = Disassembler::with_bank(0xA000, bank);
+
+    assert_eq!(&["02:A000 A9 42    LDA #$42", "02:A002 EA       NOP"],
+               &Disassembler::clean_asm(dasm.disassemble(&code))[..]);
+}
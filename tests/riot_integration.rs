@@ -0,0 +1,103 @@
+extern crate rs6502;
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use rs6502::{Assembler, BusDevice, Cpu, Riot};
+
+/// Assembles `asm` at $F000 and maps a RIOT the way the Atari 2600 does:
+/// RAM at $80-$FF and the registers at $280-$29F
+fn cpu_with_riot(asm: &str) -> (Cpu, Rc<RefCell<Riot>>) {
+    let mut cpu = Cpu::new();
+    let mut assembler = Assembler::new();
+    let riot = Rc::new(RefCell::new(Riot::new()));
+
+    cpu.memory.map_device(0x0080, 0x00FF, Riot::ram(&riot));
+    cpu.memory.map_device(0x0280, 0x029F, riot.clone());
+
+    let segments = assembler.assemble_string(asm, 0xF000).unwrap();
+    for segment in segments {
+        cpu.load(&segment.code[..], segment.address).unwrap();
+    }
+    cpu.set_reset_vector(0xF000);
+
+    // IRQ handler at $F800
+    cpu.memory[0xFFFE] = 0x00;
+    cpu.memory[0xFFFF] = 0xF8;
+
+    cpu.reset();
+
+    (cpu, riot)
+}
+
+#[test]
+#[allow(non_snake_case)]
+fn INTEGRATION_RIOT_zero_page_ram_lives_in_the_chip() {
+    let asm = "
+        LDA #$42
+        STA $80
+        LDX #$FF
+        TXS
+        PHA
+    ";
+
+    let (mut cpu, riot) = cpu_with_riot(asm);
+    cpu.step_n(5).unwrap();
+
+    let mut ram = Riot::ram(&riot);
+    assert_eq!(0x42, ram.read(0x00));
+    assert_eq!(0x00, cpu.memory[0x80]);
+    // The stack is not in the RIOT on this board
+    assert_eq!(0x42, cpu.memory[0x1FF]);
+}
+
+#[test]
+#[allow(non_snake_case)]
+fn INTEGRATION_RIOT_can_wait_on_the_timer() {
+    let asm = "
+        TIM64T = $296
+        INTIM = $284
+
+        LDA #$02
+        STA TIM64T
+    WAIT:
+        INX
+        LDA INTIM
+        BNE WAIT
+        STX $00
+    ";
+
+    let (mut cpu, _riot) = cpu_with_riot(asm);
+    cpu.run_for(300).unwrap();
+
+    // 128 cycles of waiting, at 9 cycles a loop
+    assert_eq!(15, cpu.memory[0x00]);
+}
+
+#[test]
+#[allow(non_snake_case)]
+fn INTEGRATION_RIOT_timer_interrupt() {
+    let asm = "
+        TIM8T_IRQ = $29D
+        INTIM = $284
+
+        LDA #$10
+        STA TIM8T_IRQ
+        CLI
+    LOOP:
+        JMP LOOP
+
+    .ORG $F800
+        INC $00
+        LDA #$10    ; Restart the timer, acknowledging the interrupt
+        STA TIM8T_IRQ
+        RTI
+    ";
+
+    let (mut cpu, _riot) = cpu_with_riot(asm);
+    cpu.run_for(1000).unwrap();
+
+    // 136 timer cycles, plus the time taken to get into the handler and
+    // restart it
+    assert_eq!(6, cpu.memory[0x00]);
+}
@@ -0,0 +1,224 @@
+extern crate rs6502;
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use rs6502::{Assembler, BusDevice, Cpu, Via};
+
+/// Assembles `asm`, loads every segment and maps a VIA at $6000
+fn cpu_with_via(asm: &str) -> (Cpu, Rc<RefCell<Via>>) {
+    let mut cpu = Cpu::new();
+    let mut assembler = Assembler::new();
+    let via = Rc::new(RefCell::new(Via::new()));
+
+    cpu.memory.map_device(0x6000, 0x600F, via.clone());
+
+    let segments = assembler.assemble_string(asm, 0xC000).unwrap();
+    for segment in segments {
+        cpu.load(&segment.code[..], segment.address).unwrap();
+    }
+    cpu.set_reset_vector(0xC000);
+
+    // IRQ handler at $D000
+    cpu.memory[0xFFFE] = 0x00;
+    cpu.memory[0xFFFF] = 0xD0;
+
+    cpu.reset();
+
+    (cpu, via)
+}
+
+#[test]
+#[allow(non_snake_case)]
+fn INTEGRATION_VIA_can_write_to_output_port() {
+    let asm = "
+        DDRB = $6002
+        ORB = $6000
+
+        LDA #$FF
+        STA DDRB
+        LDA #$A5
+        STA ORB
+    ";
+
+    let (mut cpu, via) = cpu_with_via(asm);
+    cpu.step_n(4).unwrap();
+
+    assert_eq!(0xA5, via.borrow().port_b());
+}
+
+#[test]
+#[allow(non_snake_case)]
+fn INTEGRATION_VIA_can_read_input_port() {
+    let asm = "
+        DDRA = $6003
+        PORTA = $6001
+
+        LDA #$0F
+        STA DDRA
+        LDA #$05
+        STA PORTA
+        LDA PORTA
+    ";
+
+    let (mut cpu, via) = cpu_with_via(asm);
+    via.borrow_mut().set_port_a_input(0x90);
+    cpu.step_n(5).unwrap();
+
+    assert_eq!(0x95, cpu.registers.A);
+}
+
+#[test]
+#[allow(non_snake_case)]
+fn INTEGRATION_VIA_timer1_free_run_interrupts_periodically() {
+    let asm = "
+        ACR = $600B
+        IER = $600E
+        T1CL = $6004
+        T1CH = $6005
+
+        LDA #$40    ; Timer 1 free run
+        STA ACR
+        LDA #$C0    ; Enable the timer 1 interrupt
+        STA IER
+        LDA #$E6    ; Interrupt every 1000 cycles ($03E6 + 2)
+        STA T1CL
+        LDA #$03
+        STA T1CH
+        CLI
+    LOOP:
+        JMP LOOP
+
+    .ORG $D000
+        LDA T1CL    ; Acknowledge the interrupt
+        INC $00
+        RTI
+    ";
+
+    let (mut cpu, _via) = cpu_with_via(asm);
+    cpu.run_for(10_050).unwrap();
+
+    assert_eq!(10, cpu.memory[0x00]);
+}
+
+#[test]
+#[allow(non_snake_case)]
+fn INTEGRATION_VIA_timer1_one_shot_interrupts_once() {
+    let asm = "
+        IER = $600E
+        T1CL = $6004
+        T1CH = $6005
+
+        LDA #$C0
+        STA IER
+        LDA #$64
+        STA T1CL
+        LDA #$00
+        STA T1CH
+        CLI
+    LOOP:
+        JMP LOOP
+
+    .ORG $D000
+        LDA T1CL
+        INC $00
+        RTI
+    ";
+
+    let (mut cpu, _via) = cpu_with_via(asm);
+    cpu.run_for(100_000).unwrap();
+
+    assert_eq!(1, cpu.memory[0x00]);
+}
+
+#[test]
+#[allow(non_snake_case)]
+fn INTEGRATION_VIA_can_poll_timer2_flag() {
+    let asm = "
+        IFR = $600D
+        T2CL = $6008
+        T2CH = $6009
+
+        LDA #$00
+        STA T2CL
+        LDA #$02    ; 512 cycles
+        STA T2CH
+    WAIT:
+        INX
+        LDA IFR
+        AND #$20
+        BEQ WAIT
+        STX $00
+    ";
+
+    let (mut cpu, _via) = cpu_with_via(asm);
+    cpu.run_for(1000).unwrap();
+
+    // Each time around the loop takes 11 cycles
+    assert_eq!(48, cpu.memory[0x00]);
+}
+
+#[test]
+#[allow(non_snake_case)]
+fn INTEGRATION_VIA_shift_register_shifts_out_and_interrupts() {
+    let asm = "
+        ACR = $600C
+        SR = $600A
+        IFR = $600D
+
+        LDA #$18    ; Shift out under phi2
+        STA $600B
+        LDA #$F0
+        STA SR
+    WAIT:
+        LDA IFR
+        AND #$04
+        BEQ WAIT
+        LDA #$01
+        STA $00
+    ";
+
+    let (mut cpu, via) = cpu_with_via(asm);
+    cpu.run_for(200).unwrap();
+
+    assert_eq!(1, cpu.memory[0x00]);
+    // Eight shifts rotate the register back around
+    assert_eq!(0xF0, via.borrow_mut().read(0x0A));
+}
+
+#[test]
+#[allow(non_snake_case)]
+fn INTEGRATION_VIA_ca1_interrupt_with_handshake() {
+    let asm = "
+        PCR = $600C
+        IER = $600E
+        PORTA = $6001
+
+        LDA #$08    ; CA2 handshake output, CA1 negative edge
+        STA PCR
+        LDA #$82    ; Enable the CA1 interrupt
+        STA IER
+        LDA #$42
+        STA PORTA     ; CA2 goes low: data ready
+        CLI
+    LOOP:
+        JMP LOOP
+
+    .ORG $D000
+        LDA PORTA     ; Acknowledge the interrupt
+        INC $00
+        RTI
+    ";
+
+    let (mut cpu, via) = cpu_with_via(asm);
+    cpu.run_for(100).unwrap();
+    assert!(!via.borrow().ca2_output());
+
+    // The peripheral takes the data
+    via.borrow_mut().set_ca1(false);
+    assert!(via.borrow().ca2_output());
+    cpu.run_for(100).unwrap();
+    via.borrow_mut().set_ca1(true);
+
+    assert_eq!(1, cpu.memory[0x00]);
+}